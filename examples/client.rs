@@ -223,7 +223,7 @@ fn main() {
         }
         if sbuf.is_empty() {
             if assoc.is_closed() {
-                info!("Association closed");
+                info!("Association closed: {:?}", assoc.get_close_reason());
                 break 'main;
             }    
             'send: loop {
@@ -12,7 +12,6 @@ use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use rusctp::*;
 
 type RemoteAddressMap = HashMap<IpAddr, u16>;
-type PeerMap = HashMap<(u16, u16, u32), (SctpAssociation, RemoteAddressMap)>;
 
 const USAGE: &str = "Usage:
   server [options] <ServerAddress>
@@ -30,7 +29,7 @@ fn main() {
     let mut sbuf: Vec<u8> = Vec::new();
     let mut readbuf: Vec<u8> = Vec::new();
     let send_data: &[u8] = &[0u8; 1500];
-    let mut peers = PeerMap::new();
+    let mut raddr_map = RemoteAddressMap::new();
 
     env_logger::builder().format_timestamp_nanos().init();
 
@@ -39,7 +38,7 @@ fn main() {
         .unwrap_or_else(|e| e.exit());
 
     let server_port = args.get_str("--server_port");
-    let _server_port = u16::from_str_radix(server_port, 10).unwrap();
+    let server_port = u16::from_str_radix(server_port, 10).unwrap();
 
     let server_udp_port = args.get_str("--server_udp_port");
     let server_udp_port = u16::from_str_radix(server_udp_port, 10).unwrap();
@@ -47,9 +46,9 @@ fn main() {
     let send_bytes = args.get_str("--send_bytes");
     let _send_bytes = usize::from_str_radix(send_bytes, 10).unwrap();
 
-    let _server_ip = args.get_str("<ServerAddress>").parse::<IpAddr>().unwrap();
+    let server_ip = args.get_str("<ServerAddress>").parse::<IpAddr>().unwrap();
 
-    let secret_key = (0..32).map(|_| rand::random::<u8>()).collect::<Vec<u8>>();
+    let mut endpoint = SctpEndpoint::new(server_port, &[server_ip]).unwrap();
 
     let poll = Poll::new().unwrap();
     let mut events = Events::with_capacity(1024);
@@ -78,14 +77,19 @@ fn main() {
 
     let mut raddr: Option<SocketAddr> = None;
     'main: loop {
-        peers.retain(|_, (ref mut assoc, _)| {
-            if assoc.is_closed() {
-                info!("association {} closed", assoc.my_vtag);
-            }
-            !assoc.is_closed()
-        });
+        for assoc in endpoint.take_closed() {
+            info!(
+                "association {} closed: {:?}",
+                assoc.my_vtag,
+                assoc.get_close_reason()
+            );
+        }
+
+        while let Some(id) = endpoint.accept() {
+            info!("association {} established", id);
+        }
 
-        for (ref mut assoc, _) in peers.values_mut() {
+        for assoc in endpoint.iter_mut() {
             'read: loop {
                 if assoc.is_established() {
                     let readable: Vec<u16> = assoc.get_readable().collect();
@@ -114,10 +118,7 @@ fn main() {
             }
         }
 
-        let timeout = peers
-            .values()
-            .filter_map(|(assoc, _)| assoc.get_timeout())
-            .min();
+        let timeout = endpoint.get_timeout();
 
         'poll: loop {
             poll.poll(&mut events, timeout).unwrap();
@@ -125,7 +126,7 @@ fn main() {
             if events.is_empty() {
                 // timed out
                 debug!("timed out");
-                peers.values_mut().for_each(|(assoc, _)| assoc.on_timeout());
+                endpoint.on_timeout();
             }
             for event in &events {
                 let udpsock = if event.token() == Token(0) {
@@ -190,87 +191,24 @@ fn main() {
                             continue 'recv;
                         }
 
-                        let mut off = 0;
-                        let (header, consumed) = match SctpCommonHeader::from_bytes(&rbuf[0..len]) {
-                            Ok(v) => v,
-                            Err(e) => {
-                                error!("SctpCommonHeader::from_bytes() failed: {:?}", e);
-                                continue 'recv;
-                            }
-                        };
-                        off += consumed;
-                        if !peers.contains_key(&(header.src_port, header.dst_port, header.vtag)) {
-                            match SctpAssociation::accept(
-                                &from.ip(),
-                                &header,
-                                &rbuf[off..len],
-                                &mut sbuf,
-                                &secret_key[..],
-                            ) {
-                                Ok((Some(assoc), consumed)) => {
-                                    let mut raddr_map = RemoteAddressMap::new();
-                                    raddr_map.insert(from.ip(), from.port());
-                                    peers.insert(
-                                        (header.src_port, header.dst_port, header.vtag),
-                                        (assoc, raddr_map),
-                                    );
-                                    off += consumed;
-                                }
-                                Ok((None, _)) => {
-                                    match udpsock.send_to(&sbuf, &from) {
-                                        Ok(olen) => {
-                                            debug!("sent {} bytes to {}", olen, from);
-                                            sbuf.clear();
-                                        }
-                                        Err(e) => {
-                                            if e.kind() == std::io::ErrorKind::WouldBlock {
-                                                raddr = Some(from);
-                                                break 'poll;
-                                            }
-                                            error!("send_to() failed: to {}, {:?}", from, e);
-                                        }
-                                    };
-                                }
-                                Err(e) => {
-                                    error!("SctpAssociation::accept() failed: {:?}", e);
-                                    continue 'recv;
-                                }
-                            }
-                        }
-                        let (assoc, raddr_map) =
-                            match peers.get_mut(&(header.src_port, header.dst_port, header.vtag)) {
-                                Some(v) => v,
-                                None => {
-                                    continue 'recv;
-                                }
-                            };
-                        if !raddr_map.contains_key(&from.ip()) {
-                            raddr_map.insert(from.ip(), from.port());
-                        }
+                        raddr_map.insert(from.ip(), from.port());
 
-                        while off < len {
-                            match assoc.recv(&from.ip(), &rbuf[off..len], &mut sbuf) {
-                                Ok(v) => {
-                                    off += v;
+                        if let Err(e) = endpoint.recv(&from.ip(), &rbuf[0..len], &mut sbuf) {
+                            error!("SctpEndpoint::recv() failed: {:?}", e);
+                        }
+                        if !sbuf.is_empty() {
+                            match udpsock.send_to(&sbuf, &from) {
+                                Ok(olen) => {
+                                    debug!("sent {} bytes to {}", olen, from);
+                                    sbuf.clear();
                                 }
                                 Err(e) => {
-                                    error!("SctpAssociation::recv() failed: {:?}", e);
-                                    if !sbuf.is_empty() {
-                                        match udpsock.send_to(&sbuf, &from) {
-                                            Ok(olen) => {
-                                                debug!("sent {} bytes to {}", olen, from);
-                                            }
-                                            Err(e) => {
-                                                if e.kind() == std::io::ErrorKind::WouldBlock {
-                                                    raddr = Some(from);
-                                                    break 'poll;
-                                                }
-                                                error!("send_to() failed: to {}, {:?}", from, e);
-                                            }
-                                        };
+                                    if e.kind() == std::io::ErrorKind::WouldBlock {
+                                        raddr = Some(from);
+                                        break 'poll;
                                     }
+                                    error!("send_to() failed: to {}, {:?}", from, e);
                                     sbuf.clear();
-                                    continue 'recv;
                                 }
                             };
                         }
@@ -281,55 +219,41 @@ fn main() {
         }
 
         if sbuf.is_empty() {
-            'eval_assocs: for (assoc, raddr_map) in
-                peers.iter_mut().filter_map(|(_, (assoc, raddr_map))| {
-                    if !assoc.is_closed() {
-                        Some((assoc, raddr_map))
-                    } else {
-                        None
+            'send: loop {
+                let (_, rip) = match endpoint.send(&mut sbuf) {
+                    Ok(v) => v,
+                    Err(SctpError::Done) => {
+                        break 'send;
                     }
-                })
-            {
-                'send: loop {
-                    let (_, rip) = match assoc.send(&mut sbuf) {
-                        Ok(v) => v,
-                        Err(SctpError::Done) => {
-                            break 'send;
-                        }
-                        Err(e) => {
-                            error!("SctpAssociation::send() failed: {:?}", e);
-                            break 'send;
-                        }
-                    };
+                    Err(e) => {
+                        error!("SctpEndpoint::send() failed: {:?}", e);
+                        break 'send;
+                    }
+                };
 
-                    if !sbuf.is_empty() {
-                        let port = match raddr_map.get(&rip) {
-                            Some(port) => port,
-                            None => raddr_map.values().next().unwrap_or(&0),
-                        };
-                        if *port == 0 {
-                            println!("Cannot assign UDP dport: {:?}", rip);
-                            continue;
+                let port = match raddr_map.get(&rip) {
+                    Some(port) => *port,
+                    None => {
+                        println!("Cannot assign UDP dport: {:?}", rip);
+                        sbuf.clear();
+                        continue;
+                    }
+                };
+                raddr = Some(SocketAddr::new(rip, port));
+                let udpsock = if rip.is_ipv4() { &udpsock4 } else { &udpsock6 };
+                match udpsock.send_to(&sbuf, &raddr.unwrap()) {
+                    Ok(olen) => {
+                        debug!("sent {} bytes to {}", olen, raddr.unwrap());
+                        sbuf.clear();
+                    }
+                    Err(e) => {
+                        if e.kind() == std::io::ErrorKind::WouldBlock {
+                            break 'send;
                         }
-                        raddr = Some(SocketAddr::new(rip, *port));
-                        let udpsock = if raddr.unwrap().is_ipv4() {
-                            &udpsock4
-                        } else {
-                            &udpsock6
-                        };
-                        match udpsock.send_to(&sbuf, &raddr.unwrap()) {
-                            Ok(olen) => {
-                                debug!("sent {} bytes to {}", olen, raddr.unwrap());
-                            }
-                            Err(e) => {
-                                if e.kind() == std::io::ErrorKind::WouldBlock {
-                                    break 'eval_assocs;
-                                }
-                                error!("send_to() failed: to {}, {:?}", raddr.unwrap(), e);
-                            }
-                        };
+                        error!("send_to() failed: to {}, {:?}", raddr.unwrap(), e);
+                        sbuf.clear();
                     }
-                }
+                };
             }
             if !sbuf.is_empty() {
                 if raddr.unwrap().is_ipv4() {
@@ -11,20 +11,33 @@ extern crate sna;
 
 use std::cmp;
 use std::collections::{BTreeMap, VecDeque};
-use std::net::IpAddr;
+use std::net::{IpAddr, Ipv4Addr};
 use std::time::{Duration, Instant, SystemTime};
 
 use crc::crc32;
+use crypto::hmac::Hmac;
+use crypto::mac::{Mac, MacResult};
+use crypto::sha1::Sha1;
 use sna::SerialNumber;
 
 use crate::sctp_mapping_array::SctpMappingArray;
-use crate::sctp_recovery::{SctpPathState, SctpRecovery};
+use crate::sctp_recovery::SctpRecovery;
 use crate::sctp_stream::{SctpStreamIn, SctpStreamIter, SctpStreamOut};
+pub use sctp_bbr::{SctpBbr, SctpBbrMode};
+pub use sctp_congestion::{CongestionController, SctpCongestionControl, SctpReno};
+pub use sctp_cubic::SctpCubic;
+pub use sctp_endpoint::SctpEndpoint;
 pub use sctp_pkt::*;
+pub use sctp_recovery::SctpPathState;
 
+mod sctp_bbr;
 mod sctp_collections;
+mod sctp_congestion;
+mod sctp_cubic;
+mod sctp_endpoint;
 mod sctp_mapping_array;
 pub mod sctp_pkt;
+mod sctp_pmtud;
 mod sctp_recovery;
 mod sctp_stream;
 
@@ -38,28 +51,111 @@ const DEFAULT_ACK_FREQ: u32 = 2;
 
 const DEFAULT_MTU: usize = 1500;
 
+// HMAC-SHA-1, the only HMAC used for AUTH
+const AUTH_HMAC_LEN: usize = 20;
+
 // A SCTP Error.
 #[derive(Clone, Copy, Debug, PartialEq)]
-#[repr(C)]
 pub enum SctpError {
-    // There is no more task to do.
-    Done = -1,
-    // The passed buffer is too short.
-    BufferTooShort = -2,
-    // The passed packet cannot be parsed because it includes any invalid chunk.
-    InvalidChunk = -3,
-    // The operation fails because the status of the associaion is not intended one.
-    InvalidState = -4,
-    // The passed stream id is not valid one.
-    InvalidStreamId = -5,
-    // The passed path id is not valid one.
-    InvalidPathId = -6,
-    // The passed packet contains any information which is not according to the specification.
-    ProtocolViolation = -7,
-    // The passed packet is an "out of the blue" (OOTB).
-    OOTB = -8,
+    // There is no more task to do. (-1)
+    Done,
+    // The passed buffer is too short. (-2)
+    BufferTooShort,
+    // The passed packet cannot be parsed because it includes any invalid chunk. (-3)
+    InvalidChunk(SctpParseError),
+    // The operation fails because the status of the associaion is not intended one. (-4)
+    InvalidState(SctpAssociationState),
+    // The passed stream id is not valid one. (-5)
+    InvalidStreamId,
+    // The passed path id is not valid one. (-6)
+    InvalidPathId,
+    // The passed packet contains any information which is not according to the specification. (-7)
+    ProtocolViolation,
+    // The passed packet is an "out of the blue" (OOTB). (-8)
+    OOTB,
+    // The passed packet has an incorrect checksum. (-9)
+    InvalidChecksum,
+    // The peer does not support the requested extension. (-10)
+    Unsupported,
+    // The passed address is unknown, or cannot be removed. (-11)
+    InvalidAddress,
+    // The passed parameter is out of range. (-12)
+    InvalidValue,
+}
+
+// Where a received packet failed to be parsed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SctpParseError {
+    // The type of the chunk, if its header could be read.
+    pub chunk_type: Option<SctpChunkType>,
+    // The type of the parameter within the chunk, if a parameter is malformed.
+    pub param_type: Option<SctpParameterType>,
+    // The byte offset of the malformed chunk, or parameter, in the packet.
+    pub offset: usize,
+}
+
+impl SctpError {
+    // Returns the numeric error code for FFI users.
+    pub fn to_c(&self) -> i32 {
+        match self {
+            SctpError::Done => -1,
+            SctpError::BufferTooShort => -2,
+            SctpError::InvalidChunk(_) => -3,
+            SctpError::InvalidState(_) => -4,
+            SctpError::InvalidStreamId => -5,
+            SctpError::InvalidPathId => -6,
+            SctpError::ProtocolViolation => -7,
+            SctpError::OOTB => -8,
+            SctpError::InvalidChecksum => -9,
+            SctpError::Unsupported => -10,
+            SctpError::InvalidAddress => -11,
+            SctpError::InvalidValue => -12,
+        }
+    }
+
+    // Parsers report offsets relative to the passed buffer. Callers shift them by the position
+    // of that buffer in the packet.
+    fn add_offset(self, off: usize) -> SctpError {
+        match self {
+            SctpError::InvalidChunk(mut v) => {
+                v.offset += off;
+                SctpError::InvalidChunk(v)
+            }
+            _ => self,
+        }
+    }
+}
+
+impl std::fmt::Display for SctpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SctpError::Done => write!(f, "no more work to do"),
+            SctpError::BufferTooShort => write!(f, "buffer too short"),
+            SctpError::InvalidChunk(v) => {
+                write!(f, "invalid chunk")?;
+                if let Some(chunk_type) = v.chunk_type {
+                    write!(f, " type={:?}", chunk_type)?;
+                }
+                if let Some(param_type) = v.param_type {
+                    write!(f, " parameter={:?}", param_type)?;
+                }
+                write!(f, " at offset {}", v.offset)
+            }
+            SctpError::InvalidState(state) => write!(f, "invalid association state {:?}", state),
+            SctpError::InvalidStreamId => write!(f, "invalid stream id"),
+            SctpError::InvalidPathId => write!(f, "invalid path id"),
+            SctpError::ProtocolViolation => write!(f, "protocol violation"),
+            SctpError::OOTB => write!(f, "out of the blue packet"),
+            SctpError::InvalidChecksum => write!(f, "invalid checksum"),
+            SctpError::Unsupported => write!(f, "not supported by the peer"),
+            SctpError::InvalidAddress => write!(f, "invalid address"),
+            SctpError::InvalidValue => write!(f, "invalid value"),
+        }
+    }
 }
 
+impl std::error::Error for SctpError {}
+
 #[derive(Debug)]
 pub struct SctpAssociation {
     pub src_port: u16,
@@ -81,7 +177,9 @@ pub struct SctpAssociation {
     ack_delay: Duration,
     ack_freq: u32,
     delayed_ack_timeout: Option<Instant>,
+    dac_pkts_seen: u8,
     send_sack: bool,
+    send_abort: bool,
 
     last_data_from: Option<usize>,
     mapping_array: SctpMappingArray,
@@ -92,15 +190,39 @@ pub struct SctpAssociation {
     control_waiting_trans: BTreeMap<u64, (SctpChunk, usize)>,
     next_control_sequence: SerialNumber<u64>,
 
+    nodelay: bool,
+    max_burst: usize,
+    // Packets of new data sent since the last SACK
     send_burst_count: usize,
     sent_data_count: usize,
     recv_data_count: usize,
 
     trace_id: String,
-    error_cause: Option<SctpErrorCause>,
+    abort_cause: Option<SctpErrorCause>,
+    close_reason: Option<SctpCloseReason>,
+    events: VecDeque<SctpEvent>,
+
+    asconf_supported: bool,
+    asconf_serial: SerialNumber<u32>,
+    asconf_outstanding: bool,
+    asconf_waiting: VecDeque<SctpParameter>,
+    next_asconf_correlation_id: u32,
+    peer_asconf_serial: SerialNumber<u32>,
+    last_asconf_ack: Option<SctpChunk>,
+
+    // RANDOM, CHUNKS and HMAC-ALGO sent in INIT
+    auth_params: Vec<SctpParameter>,
+    // The association shared key, if both endpoints support AUTH
+    auth_key: Option<Vec<u8>>,
+    // The offset of the AUTH chunk in the packet being built
+    auth_offset: Option<usize>,
+
+    ecn_supported: bool,
+    // The Lowest TSN of the ECNE sent with every SACK until a CWR covers it
+    ecne_tsn: Option<SerialNumber<u32>>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 #[repr(C)]
 pub enum SctpAssociationState {
     Closed = 0,
@@ -113,6 +235,164 @@ pub enum SctpAssociationState {
     ShutdownAckSent = 7,
 }
 
+// Why an association went to the CLOSED state.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SctpCloseReason {
+    // The association was shut down gracefully.
+    Shutdown,
+    // The peer sent an ABORT chunk.
+    PeerAbort(Option<SctpErrorCause>),
+    // The association was aborted by the user or by the shutdown guard timer.
+    LocalAbort(Option<SctpErrorCause>),
+    // The association was aborted because the peer violated the protocol.
+    ProtocolViolation(SctpErrorCause),
+    // INIT or COOKIE-ECHO was retransmitted Max.Init.Retransmits times.
+    HandshakeTimeout,
+    // The peer was unreachable for Association.Max.Retrans retransmissions.
+    RetransmissionLimit,
+}
+
+// When new data goes back to the primary path after a failover.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SctpSwitchBackPolicy {
+    // As soon as the primary path is confirmed reachable again.
+    Immediate,
+    // Only when the alternate path fails as well.
+    Never,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SctpEvent {
+    // New data and SACKs moved off a failed path.
+    PathFailover {
+        from: usize,
+        to: usize,
+    },
+    // New data and SACKs moved back to the primary path.
+    PathSwitchBack {
+        from: usize,
+        to: usize,
+    },
+    // The primary path failed and the new path became the primary path.
+    PrimaryPathSwitchover {
+        from: usize,
+        to: usize,
+    },
+    // A HEARTBEAT-ACK verified the address of the path (RFC 9260 Section 5.4).
+    PathConfirmed {
+        pathid: usize,
+    },
+    // The peer added or deleted one of its addresses with ASCONF.
+    PeerAddressAdded {
+        addr: IpAddr,
+        pathid: usize,
+    },
+    PeerAddressRemoved {
+        addr: IpAddr,
+        pathid: usize,
+    },
+    // The peer asked for the path to be our primary path.
+    PeerSetPrimary {
+        addr: IpAddr,
+        pathid: usize,
+    },
+    // The peer accepted or refused our ASCONF request.
+    AsconfSucceeded {
+        request: SctpParameter,
+    },
+    AsconfFailed {
+        request: SctpParameter,
+        cause: Option<SctpErrorCause>,
+    },
+}
+
+// A destination address of the peer, as returned by list_paths().
+#[derive(Clone, Debug, PartialEq)]
+pub struct SctpPathInfo {
+    pub pathid: usize,
+    pub addr: IpAddr,
+    pub confirmed: bool,
+    pub state: SctpPathState,
+    pub primary: bool,
+}
+
+// Metadata of a packet written by send_with_info()
+#[derive(Clone, Debug, PartialEq)]
+pub struct SctpSendInfo {
+    pub to: IpAddr,
+    // The packet is to be sent with ECT(0) in the IP header.
+    pub ect: bool,
+}
+
+// Metadata of a packet passed to recv_with_info()
+#[derive(Clone, Debug, PartialEq)]
+pub struct SctpRecvInfo {
+    pub from: IpAddr,
+    // The IP header was marked CE.
+    pub ce: bool,
+}
+
+// Per-path parameters, like SCTP_PEER_ADDR_PARAMS and SCTP_RTOINFO in RFC 6458.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SctpPathParams {
+    // HB.interval, added to the RTO between HEARTBEATs on an idle path
+    pub hb_interval: Duration,
+    // HEARTBEATs are still sent to confirm the path or probe it while it is
+    // potentially failed.
+    pub hb_enabled: bool,
+    // Path.Max.Retrans
+    pub max_retrans: u32,
+    // With PMTUD disabled, pmtu is a fixed path MTU including the IP, UDP
+    // and SCTP common headers.
+    pub pmtud_enabled: bool,
+    pub pmtu: usize,
+    pub rto_initial: Duration,
+    pub rto_min: Duration,
+    pub rto_max: Duration,
+}
+
+// Congestion control and RTT state of a path, like SCTP_GET_PEER_ADDR_INFO
+// in RFC 6458.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SctpPathStatus {
+    pub confirmed: bool,
+    pub state: SctpPathState,
+    // None until the first RTT measurement
+    pub srtt: Option<Duration>,
+    pub rttvar: Duration,
+    pub rto: Duration,
+    pub cwnd: usize,
+    pub ssthresh: usize,
+    pub flight: usize,
+    pub partial_bytes_acked: usize,
+    // In bytes per second, from the congestion control algorithm
+    pub pacing_rate: Option<u64>,
+    // Including the IP, UDP and SCTP common headers
+    pub pmtu: usize,
+    pub error_count: u32,
+}
+
+// Association-wide state, like SCTP_STATUS in RFC 6458.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SctpStatus {
+    pub state: SctpAssociationState,
+    pub peer_rwnd: usize,
+    // The a_rwnd we would advertise now
+    pub a_rwnd: u32,
+    // DATA chunks sent but not yet cumulatively acknowledged
+    pub unacked_data: usize,
+    // Messages received but not yet read, including incomplete ones
+    pub pending_data: usize,
+    pub next_tsn: u32,
+    // The peer's cumulative TSN ack of our DATA chunks
+    pub cumulative_tsn_ack: u32,
+    // Our cumulative TSN of the peer's DATA chunks
+    pub cumulative_tsn: u32,
+    pub inbound_streams: usize,
+    pub outbound_streams: usize,
+    pub primary_path: Option<usize>,
+}
+
 #[derive(Debug)]
 struct SctpRemoteAddress {
     addr: IpAddr,
@@ -172,8 +452,8 @@ enum SctpLocalAddressState {
     Empty,
     Adding,
     Added,
-    _Deleting,
-    _Deleted,
+    Deleting,
+    Deleted,
 }
 
 macro_rules! write_retrans_chunks_for_single_pkt {
@@ -214,7 +494,8 @@ macro_rules! write_retrans_chunks_for_single_pkt {
                     mtu = Some($recovery.get_path_mtu(pathid.unwrap()).unwrap());
                 }
                 let available_space = mtu.unwrap() - $sbuf.len().checked_sub(12).unwrap_or(0);
-                if available_space < bytes_len {
+                // A chunk larger than a reduced path MTU is sent alone.
+                if available_space < bytes_len && $sbuf.len() > 12 {
                     break;
                 }
                 $waiting.remove(&tsn);
@@ -223,6 +504,9 @@ macro_rules! write_retrans_chunks_for_single_pkt {
                     chunk.to_bytes($sbuf).unwrap();
                     $recovery.on_data_sent(chunk, pathid.unwrap(), $send_time, true);
                 }
+                if available_space < bytes_len {
+                    break;
+                }
             }
             if $sbuf.len() > old_len {
                 Ok((pathid.unwrap(), $sbuf.len() - old_len))
@@ -236,7 +520,7 @@ macro_rules! write_retrans_chunks_for_single_pkt {
 }
 
 macro_rules! write_control_chunks {
-    ($recovery:expr, $waiting:expr, $sbuf:expr, $pathid:expr, $send_time:expr, $trace_id:expr) => {{
+    ($recovery:expr, $waiting:expr, $sbuf:expr, $pathid:expr, $send_time:expr, $auth_key:expr, $auth_offset:expr, $trace_id:expr) => {{
         if $waiting.len() > 0 {
             let mut pathid = $pathid;
             let old_len = $sbuf.len();
@@ -255,11 +539,27 @@ macro_rules! write_control_chunks {
                     mtu = Some($recovery.get_path_mtu(pathid.unwrap()).unwrap());
                 }
 
+                // ASCONF and ASCONF-ACK are preceded by an AUTH chunk.
+                let auth_chunk = match $auth_key {
+                    Some(_)
+                        if $auth_offset.is_none()
+                            && SctpAssociation::is_auth_required(chunk.get_type()) =>
+                    {
+                        Some(SctpAssociation::auth_chunk())
+                    }
+                    _ => None,
+                };
+                let auth_len = auth_chunk.as_ref().map_or(0, |auth| auth.bytes_len());
+
                 let available_space = mtu.unwrap() - $sbuf.len().checked_sub(12).unwrap_or(0);
-                if available_space < chunk.bytes_len() {
+                if available_space < chunk.bytes_len() + auth_len {
                     break;
                 }
                 let (chunk, _) = $waiting.remove(&sequence).unwrap();
+                if let Some(auth_chunk) = auth_chunk {
+                    $auth_offset = Some($sbuf.len());
+                    auth_chunk.to_bytes($sbuf).unwrap();
+                }
                 trace!(
                     "{} transmission CONTROL chunk type={}",
                     $trace_id,
@@ -311,7 +611,9 @@ macro_rules! write_retrans_chunks {
                     mtu.unwrap() - $sbuf.len().checked_sub(12).unwrap_or(0),
                     cwnd,
                 );
-                if available_space < bytes_len {
+                // A chunk larger than a reduced path MTU is sent alone.
+                let oversized = bytes_len > mtu.unwrap() && $sbuf.len() <= 12 && cwnd >= bytes_len;
+                if available_space < bytes_len && !oversized {
                     break;
                 }
                 $waiting.remove(&tsn);
@@ -320,6 +622,9 @@ macro_rules! write_retrans_chunks {
                     chunk.to_bytes($sbuf).unwrap();
                     $recovery.on_data_sent(chunk, pathid.unwrap(), $send_time, true);
                 }
+                if oversized {
+                    break;
+                }
             }
             if pathid.is_some() && $sbuf.len() > old_len {
                 Ok((pathid.unwrap(), $sbuf.len() - old_len))
@@ -354,7 +659,7 @@ impl SctpAssociation {
         let pathid = assoc.add_raddr(&dst_ip).unwrap();
         assoc.state = SctpAssociationState::CookieWait;
 
-        let params: Vec<SctpParameter> = assoc
+        let mut params: Vec<SctpParameter> = assoc
             .laddr_list
             .iter()
             .filter_map(|x| match x.addr {
@@ -362,6 +667,10 @@ impl SctpAssociation {
                 IpAddr::V6(ip6) => Some(SctpParameter::Ipv6(ip6.clone())),
             })
             .collect();
+        params.push(SctpAssociation::supported_extensions());
+        params.push(SctpParameter::Ecn);
+        assoc.auth_params = SctpAssociation::auth_parameters();
+        params.extend(assoc.auth_params.iter().cloned());
         assoc.control_waiting_trans.insert(
             assoc.next_control_sequence.0,
             (
@@ -386,6 +695,17 @@ impl SctpAssociation {
         rbuf: &[u8],
         sbuf: &mut Vec<u8>,
         secret_key: &[u8],
+    ) -> Result<(Option<SctpAssociation>, usize)> {
+        SctpAssociation::accept_with_laddrs(rip, header, rbuf, sbuf, secret_key, &[])
+    }
+
+    fn accept_with_laddrs(
+        rip: &IpAddr,
+        header: &SctpCommonHeader,
+        rbuf: &[u8],
+        sbuf: &mut Vec<u8>,
+        secret_key: &[u8],
+        laddrs: &[IpAddr],
     ) -> Result<(Option<SctpAssociation>, usize)> {
         trace!("accept from={}, len={}", rip, rbuf.len());
         let (chunk, consumed) = match SctpChunk::from_bytes(rbuf) {
@@ -412,8 +732,19 @@ impl SctpAssociation {
                     num_out_strm: 10,
                     num_in_strm: 2048,
                     init_tsn: rand::random::<u32>(),
-                    params: Vec::new(),
+                    params: laddrs
+                        .iter()
+                        .map(|x| match x {
+                            IpAddr::V4(ip4) => SctpParameter::Ipv4(*ip4),
+                            IpAddr::V6(ip6) => SctpParameter::Ipv6(*ip6),
+                        })
+                        .chain(std::iter::once(SctpAssociation::supported_extensions()))
+                        .chain(SctpAssociation::auth_parameters())
+                        .collect(),
                 };
+                if SctpAssociation::is_ecn_supported(&v.params) {
+                    init_ack_contents.params.push(SctpParameter::Ecn);
+                }
                 let now = SystemTime::now()
                     .duration_since(SystemTime::UNIX_EPOCH)
                     .unwrap();
@@ -446,6 +777,13 @@ impl SctpAssociation {
                         return Err(e);
                     }
                 };
+                if cookie.my_vtag != header.vtag
+                    || cookie.src_port != header.dst_port
+                    || cookie.dst_port != header.src_port
+                {
+                    trace!("discard COOKIE-ECHO not matching the common header");
+                    return Ok((None, consumed));
+                }
                 let (peer_init_tsn, peer_a_rwnd, peer_num_in_strm, peer_num_out_strm, peer_params) =
                     match cookie.init {
                         SctpChunk::Init(v) => (
@@ -457,12 +795,17 @@ impl SctpAssociation {
                         ),
                         _ => (0, 0, 0, 0, Vec::new()),
                     };
-                let (my_init_tsn, my_a_rwnd, my_num_in_strm, my_num_out_strm) = match cookie
-                    .init_ack
-                {
-                    SctpChunk::InitAck(v) => (v.init_tsn, v.a_rwnd, v.num_in_strm, v.num_out_strm),
-                    _ => (0, 0, 0, 0),
-                };
+                let (my_init_tsn, my_a_rwnd, my_num_in_strm, my_num_out_strm, my_params) =
+                    match cookie.init_ack {
+                        SctpChunk::InitAck(v) => (
+                            v.init_tsn,
+                            v.a_rwnd,
+                            v.num_in_strm,
+                            v.num_out_strm,
+                            v.params,
+                        ),
+                        _ => (0, 0, 0, 0, Vec::new()),
+                    };
                 let mut assoc = SctpAssociation::new(
                     cookie.src_port,
                     cookie.dst_port,
@@ -476,6 +819,11 @@ impl SctpAssociation {
 
                 assoc.mapping_array.initialize(peer_init_tsn).unwrap();
                 assoc.peer_vtag = cookie.peer_vtag;
+                assoc.peer_asconf_serial = SerialNumber(peer_init_tsn.wrapping_sub(1));
+                assoc.auth_key = SctpAssociation::generate_auth_key(&my_params, &peer_params);
+                assoc.asconf_supported =
+                    assoc.auth_key.is_some() && SctpAssociation::is_asconf_supported(&peer_params);
+                assoc.ecn_supported = SctpAssociation::is_ecn_supported(&peer_params);
                 assoc
                     .generate_stream_in(cmp::min(my_num_in_strm, peer_num_out_strm))
                     .unwrap();
@@ -514,6 +862,14 @@ impl SctpAssociation {
                 assoc.recovery.confirm_path(pathid).unwrap();
                 assoc.set_primary_path(pathid).unwrap();
 
+                for laddr in laddrs {
+                    if let Err(e) = assoc.add_laddr(laddr) {
+                        if e != SctpError::Done {
+                            return Err(e);
+                        }
+                    }
+                }
+
                 assoc.state = SctpAssociationState::Established;
                 assoc.control_waiting_trans.insert(
                     assoc.next_control_sequence.0,
@@ -530,8 +886,12 @@ impl SctpAssociation {
     }
 
     fn handle_ootb(header: &SctpCommonHeader, chunk: SctpChunk, sbuf: &mut Vec<u8>) {
+        // RFC 9260 Section 8.4
         let sending_chunk = match chunk.get_type() {
-            SctpChunkType::Abort | SctpChunkType::ShutdownComplete => {
+            SctpChunkType::Abort
+            | SctpChunkType::ShutdownComplete
+            | SctpChunkType::CookieAck
+            | SctpChunkType::Error => {
                 return;
             }
             SctpChunkType::ShutdownAck => SctpChunk::ShutdownComplete(true),
@@ -571,12 +931,14 @@ impl SctpAssociation {
 
             delayed_ack: true,
             num_data_pkts_seen: 0,
+            dac_pkts_seen: 0,
             ack_delay: DEFAULT_ACK_DELAY,
             ack_freq: DEFAULT_ACK_FREQ,
             delayed_ack_timeout: None,
 
             last_data_from: None,
             send_sack: false,
+            send_abort: false,
 
             stream_in: Vec::new(),
             stream_out: Vec::new(),
@@ -585,12 +947,31 @@ impl SctpAssociation {
             recovery: SctpRecovery::new(init_tsn, trace_id.clone()).unwrap(),
             raddr_list: VecDeque::new(),
             laddr_list: VecDeque::new(),
+            nodelay: true,
+            max_burst: MAX_BURST,
             send_burst_count: 0,
             sent_data_count: 0,
             recv_data_count: 0,
 
             trace_id: trace_id.clone(),
-            error_cause: None,
+            abort_cause: None,
+            close_reason: None,
+            events: VecDeque::new(),
+
+            asconf_supported: false,
+            asconf_serial: SerialNumber(init_tsn),
+            asconf_outstanding: false,
+            asconf_waiting: VecDeque::new(),
+            next_asconf_correlation_id: 0,
+            peer_asconf_serial: SerialNumber(0),
+            last_asconf_ack: None,
+
+            auth_params: Vec::new(),
+            auth_key: None,
+            auth_offset: None,
+
+            ecn_supported: false,
+            ecne_tsn: None,
         };
         Ok(assoc)
     }
@@ -607,208 +988,1060 @@ impl SctpAssociation {
         Ok(num_out_strm)
     }
 
+    // After the handshake, address changes are sent to the peer with ASCONF
+    // (RFC 5061). The address is used once the peer acknowledges it.
     pub fn add_laddr(&mut self, addr: &IpAddr) -> Result<()> {
-        let ret = self.laddr_list.iter_mut().find(|x| x.addr == *addr);
-        let mut laddr = if ret.is_some() {
-            ret.unwrap()
-        } else {
-            self.laddr_list.push_back(SctpLocalAddress::new(addr));
-            self.laddr_list.back_mut().unwrap()
-        };
-
-        if laddr.state == SctpLocalAddressState::Adding
-            || laddr.state == SctpLocalAddressState::Added
+        let state = self
+            .laddr_list
+            .iter()
+            .find(|x| x.addr == *addr)
+            .map(|x| x.state);
+        if state == Some(SctpLocalAddressState::Adding)
+            || state == Some(SctpLocalAddressState::Added)
         {
             trace!(
                 "{} already added local address addr={}",
                 self.trace_id,
-                laddr.addr,
+                addr,
             );
 
             return Err(SctpError::Done);
         }
-        if self.state == SctpAssociationState::Closed {
-            laddr.state = SctpLocalAddressState::Added;
-        } else {
-            laddr.state = SctpLocalAddressState::Adding;
+        let new_state = match self.state {
+            SctpAssociationState::Closed => SctpLocalAddressState::Added,
+            SctpAssociationState::Established if self.asconf_supported => {
+                SctpLocalAddressState::Adding
+            }
+            SctpAssociationState::Established => return Err(SctpError::Unsupported),
+            _ => return Err(SctpError::InvalidState(self.state)),
+        };
+        if state.is_none() {
+            self.laddr_list.push_back(SctpLocalAddress::new(addr));
         }
+        self.set_laddr_state(addr, new_state);
         trace!(
-            "{} local address assigned added={}",
+            "{} local address assigned added={}, state={:?}",
             self.trace_id,
-            laddr.addr,
+            addr,
+            new_state
         );
 
+        if new_state == SctpLocalAddressState::Adding {
+            self.request_asconf(SctpParameter::AddIp, addr);
+        }
         Ok(())
     }
 
-    fn add_raddr(&mut self, addr: &IpAddr) -> Result<usize> {
-        let ret = self.raddr_list.iter_mut().find(|x| x.addr == *addr);
-        let mut raddr = if ret.is_some() {
-            ret.unwrap()
-        } else {
-            let len = self.raddr_list.len();
-            let mtu = if addr.is_ipv4() {
-                DEFAULT_MTU - 20 - 8 - 12 // IPv4 hdr, UDP hdr, and SCTP hdr
-            } else {
-                DEFAULT_MTU - 40 - 8 - 12 // IPv6 hdr, UDP hdr, and SCTP hdr
-            };
-            self.raddr_list
-                .push_back(SctpRemoteAddress::new(addr, mtu, len));
-            self.raddr_list.back_mut().unwrap()
-        };
-
-        if raddr.state != SctpRemoteAddressState::Deleted {
+    // The last local address known to the peer cannot be removed.
+    pub fn remove_laddr(&mut self, addr: &IpAddr) -> Result<()> {
+        match self.laddr_list.iter().find(|x| x.addr == *addr) {
+            Some(laddr) if laddr.state == SctpLocalAddressState::Added => {}
+            Some(_) => return Err(SctpError::Done),
+            None => return Err(SctpError::InvalidAddress),
+        }
+        match self.state {
+            SctpAssociationState::Closed => {
+                self.set_laddr_state(addr, SctpLocalAddressState::Deleted);
+                return Ok(());
+            }
+            SctpAssociationState::Established if self.asconf_supported => {}
+            SctpAssociationState::Established => return Err(SctpError::Unsupported),
+            _ => return Err(SctpError::InvalidState(self.state)),
+        }
+        if !self
+            .laddr_list
+            .iter()
+            .any(|x| x.addr != *addr && x.state == SctpLocalAddressState::Added)
+        {
             trace!(
-                "{} already assigned remote address addr={}, pathid={}",
+                "{} cannot remove the last local address addr={}",
                 self.trace_id,
-                raddr.addr,
-                raddr.pathid
+                addr
             );
-            return Err(SctpError::Done);
+            return Err(SctpError::InvalidAddress);
         }
-        raddr.state = SctpRemoteAddressState::Added;
-        let pathid = self.recovery.add_path(raddr.mtu);
-        assert!(pathid == raddr.pathid);
-        trace!(
-            "{} remote address assigned addr={}, pathid={}",
-            self.trace_id,
-            raddr.addr,
-            raddr.pathid
-        );
-        Ok(raddr.pathid)
+        self.set_laddr_state(addr, SctpLocalAddressState::Deleting);
+        trace!("{} local address removing addr={}", self.trace_id, addr);
+
+        self.request_asconf(SctpParameter::DeleteIp, addr);
+        Ok(())
     }
 
-    pub fn get_pathid(&self, addr: &IpAddr) -> Option<usize> {
-        if let Some(raddr) = self.raddr_list.iter().find(|x| x.addr == *addr) {
-            Some(raddr.pathid)
-        } else {
-            None
+    // Asks the peer to send to one of our addresses by default.
+    pub fn request_peer_primary(&mut self, addr: &IpAddr) -> Result<()> {
+        match self.laddr_list.iter().find(|x| x.addr == *addr) {
+            Some(laddr)
+                if laddr.state == SctpLocalAddressState::Added
+                    || laddr.state == SctpLocalAddressState::Adding => {}
+            _ => return Err(SctpError::InvalidAddress),
+        }
+        match self.state {
+            SctpAssociationState::Established if self.asconf_supported => {}
+            SctpAssociationState::Established => return Err(SctpError::Unsupported),
+            _ => return Err(SctpError::InvalidState(self.state)),
         }
+
+        self.request_asconf(SctpParameter::SetPrimary, addr);
+        Ok(())
     }
 
-    pub fn get_remote_ip(&self, pathid: usize) -> Option<IpAddr> {
-        if let Some(raddr) = self.raddr_list.get(pathid) {
-            Some(raddr.addr)
-        } else {
-            None
+    fn set_laddr_state(&mut self, addr: &IpAddr, state: SctpLocalAddressState) {
+        if let Some(laddr) = self.laddr_list.iter_mut().find(|x| x.addr == *addr) {
+            laddr.state = state;
         }
     }
 
-    pub fn get_primary_path(&self) -> Option<usize> {
-        self.recovery.get_primary_path()
+    fn supported_extensions() -> SctpParameter {
+        SctpParameter::SupportedExts(vec![
+            SctpChunkType::Asconf,
+            SctpChunkType::AsconfAck,
+            SctpChunkType::Auth,
+        ])
     }
 
-    pub fn set_primary_path(&mut self, pathid: usize) -> Result<()> {
-        self.recovery.set_primary_path(pathid)
+    // ASCONF is used only if the peer also requires ASCONF and ASCONF-ACK
+    // to be authenticated (RFC 5061 Section 4.1.1).
+    fn is_asconf_supported(params: &[SctpParameter]) -> bool {
+        let is_listed = |v: &Vec<SctpChunkType>| {
+            v.contains(&SctpChunkType::Asconf) && v.contains(&SctpChunkType::AsconfAck)
+        };
+        let supported = params.iter().any(|param| match param {
+            SctpParameter::SupportedExts(v) => is_listed(v),
+            _ => false,
+        });
+        let authenticated = params.iter().any(|param| match param {
+            SctpParameter::Chunks(v) => is_listed(v),
+            _ => false,
+        });
+        supported && authenticated
     }
 
-    pub fn get_timeout(&self) -> Option<Duration> {
-        let mut timeouts = Vec::new();
-        if let Some(timeout) = self.delayed_ack_timeout {
-            timeouts.push(timeout);
-        }
-        if let Some(timeout) = self.recovery.get_timeout() {
-            timeouts.push(timeout);
+    // AUTH (RFC 4895) is required for ASCONF and ASCONF-ACK only, with
+    // HMAC-SHA-1 and the empty endpoint pair shared key.
+    fn auth_parameters() -> Vec<SctpParameter> {
+        vec![
+            SctpParameter::Random(rand::random::<[u8; 32]>().to_vec()),
+            SctpParameter::Chunks(vec![SctpChunkType::Asconf, SctpChunkType::AsconfAck]),
+            SctpParameter::HmacAlgo(vec![SctpHmacAlgoId::Sha1]),
+        ]
+    }
+
+    fn is_auth_required(chunk_type: SctpChunkType) -> bool {
+        chunk_type == SctpChunkType::Asconf || chunk_type == SctpChunkType::AsconfAck
+    }
+
+    // The HMAC is filled in once the packet is built.
+    fn auth_chunk() -> SctpChunk {
+        SctpChunk::Auth(SctpAuthChunk {
+            shared_key_id: 0,
+            hmac_id: SctpHmacAlgoId::Sha1,
+            hmac: vec![0; AUTH_HMAC_LEN],
+        })
+    }
+
+    // RANDOM, CHUNKS and HMAC-ALGO as sent, without padding (RFC 4895
+    // Section 6.1). None if AUTH is not supported.
+    fn auth_key_vector(params: &[SctpParameter]) -> Option<Vec<u8>> {
+        let random = params
+            .iter()
+            .find(|param| matches!(param, SctpParameter::Random(_)))?;
+        let chunks = params
+            .iter()
+            .find(|param| matches!(param, SctpParameter::Chunks(_)));
+        let hmac_algo = params.iter().find(|param| match param {
+            SctpParameter::HmacAlgo(v) => v.contains(&SctpHmacAlgoId::Sha1),
+            _ => false,
+        })?;
+        let mut key_vector = Vec::new();
+        for param in vec![Some(random), chunks, Some(hmac_algo)]
+            .into_iter()
+            .flatten()
+        {
+            let start = key_vector.len();
+            param.to_bytes(&mut key_vector).unwrap();
+            let len = (key_vector[start + 2] as usize) << 8 | key_vector[start + 3] as usize;
+            key_vector.truncate(start + len);
         }
+        Some(key_vector)
+    }
 
-        let min_timeout = timeouts.into_iter().min();
-        if let Some(timeout) = min_timeout {
-            let now = Instant::now();
-            if timeout <= now {
-                return Some(Duration::new(0, 0));
-            } else {
-                return Some(timeout.duration_since(now));
-            }
+    // The association shared key is the empty endpoint pair shared key
+    // followed by the smaller and then the larger key vector, compared as
+    // big-endian numbers (RFC 4895 Section 6.2).
+    fn generate_auth_key(
+        local_params: &[SctpParameter],
+        peer_params: &[SctpParameter],
+    ) -> Option<Vec<u8>> {
+        let local = SctpAssociation::auth_key_vector(local_params)?;
+        let peer = SctpAssociation::auth_key_vector(peer_params)?;
+        let as_number = |v: &[u8]| {
+            let zeros = v.iter().take_while(|byte| **byte == 0).count();
+            (v.len() - zeros, v[zeros..].to_vec())
+        };
+        if as_number(&local) <= as_number(&peer) {
+            Some([local, peer].concat())
         } else {
-            return None;
+            Some([peer, local].concat())
         }
     }
 
-    pub fn on_timeout(&mut self) {
-        let now = Instant::now();
-        self.on_delayed_ack_timeout(now);
-        self.recovery.on_timeout(now);
+    fn compute_auth_hmac(key: &[u8], bytes: &[u8]) -> MacResult {
+        let mut mac = Hmac::new(Sha1::new(), key);
+        mac.input(bytes);
+        mac.result()
     }
 
-    fn on_delayed_ack_timeout(&mut self, now: Instant) {
-        if let Some(timeout) = self.delayed_ack_timeout {
-            if timeout <= now {
-                trace!("{} delayed ack timeout expired", self.trace_id);
-                self.send_sack = true;
-                self.delayed_ack_timeout = None;
-            }
+    // The HMAC covers the AUTH chunk, with the HMAC zeroed, and all the
+    // chunks after it.
+    fn sign_auth(&mut self, sbuf: &mut [u8]) {
+        let offset = match self.auth_offset.take() {
+            Some(v) => v,
+            None => return,
+        };
+        if let Some(key) = &self.auth_key {
+            let hmac = SctpAssociation::compute_auth_hmac(key, &sbuf[offset..]);
+            sbuf[offset + 8..offset + 8 + AUTH_HMAC_LEN].copy_from_slice(hmac.code());
         }
     }
-    fn set_delayed_ack_timer(&mut self) -> bool {
-        if self.delayed_ack_timeout.is_none() {
-            trace!(
-                "{} set delayed ack timeout ack_delay={:?}",
-                self.trace_id,
-                self.ack_delay
-            );
-            self.delayed_ack_timeout = Some(Instant::now() + self.ack_delay);
-            true
-        } else {
-            false
+
+    fn verify_auth(&self, auth: &SctpAuthChunk, rbuf: &[u8]) -> bool {
+        let key = match &self.auth_key {
+            Some(v) => v,
+            None => return false,
+        };
+        if auth.shared_key_id != 0
+            || auth.hmac_id != SctpHmacAlgoId::Sha1
+            || auth.hmac.len() != AUTH_HMAC_LEN
+        {
+            return false;
         }
+        let mut bytes = rbuf.to_vec();
+        for byte in bytes[8..8 + AUTH_HMAC_LEN].iter_mut() {
+            *byte = 0;
+        }
+        SctpAssociation::compute_auth_hmac(key, &bytes) == MacResult::new(&auth.hmac)
     }
 
-    fn set_checksum(sbuf: &mut Vec<u8>) {
-        let checksum = crc32::checksum_castagnoli(sbuf);
-        let bytes = sbuf.as_mut_slice();
-        bytes[0x08] = ((checksum >> 0) & 0x000000FF) as u8;
-        bytes[0x09] = ((checksum >> 8) & 0x000000FF) as u8;
-        bytes[0x0a] = ((checksum >> 16) & 0x000000FF) as u8;
-        bytes[0x0b] = ((checksum >> 24) & 0x000000FF) as u8;
+    fn is_ecn_supported(params: &[SctpParameter]) -> bool {
+        params.contains(&SctpParameter::Ecn)
     }
 
-    pub fn recv(&mut self, from: &IpAddr, rbuf: &[u8], sbuf: &mut Vec<u8>) -> Result<usize> {
-        let mut off = 0;
-        let recv_time = Instant::now();
-        let pathid = self.get_pathid(&from);
+    fn request_asconf(&mut self, request: fn(u32, IpAddr) -> SctpParameter, addr: &IpAddr) {
+        let correlation_id = self.next_asconf_correlation_id;
+        self.next_asconf_correlation_id = self.next_asconf_correlation_id.wrapping_add(1);
+        self.asconf_waiting
+            .push_back(request(correlation_id, *addr));
+        self.send_asconf();
+    }
 
-        if pathid.is_none() {
-            self.abort(sbuf, None);
-            return Err(SctpError::OOTB);
+    // Only one ASCONF is outstanding at a time, requests made meanwhile go
+    // into the next one (RFC 5061 Section 5.1).
+    fn send_asconf(&mut self) {
+        if self.asconf_outstanding
+            || self.asconf_waiting.is_empty()
+            || self.state != SctpAssociationState::Established
+        {
+            return;
         }
+        // The peer looks up the association with an address it already knows.
+        let addr = self
+            .laddr_list
+            .iter()
+            .find(|x| x.state == SctpLocalAddressState::Added)
+            .map(|x| x.addr)
+            .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+        let chunk = SctpChunk::Asconf(SctpAsconfChunk {
+            serial: self.asconf_serial.0,
+            addr,
+            params: self.asconf_waiting.drain(..).collect(),
+        });
+        let pathid = self.get_active_path().unwrap();
+        trace!(
+            "{} send ASCONF serial={}, pathid={}",
+            self.trace_id,
+            self.asconf_serial,
+            pathid
+        );
+        self.asconf_serial += 1;
+        self.asconf_outstanding = true;
+        self.control_waiting_trans
+            .insert(self.next_control_sequence.0, (chunk, pathid));
+        self.next_control_sequence += 1;
+    }
 
-        let mut data_appears = false;
-        while off < rbuf.len() {
-            let (chunk, consumed) = match SctpChunk::from_bytes(&rbuf[off..]) {
-                Ok(v) => v,
-                Err(e) => {
-                    return Err(e);
+    fn on_asconf_ack_received(&mut self, ack: SctpAsconfAckChunk) {
+        let asconf = match self.recovery.on_asconf_ack_received(ack.serial) {
+            Some(v) => v,
+            None => {
+                trace!("{} discard ASCONF-ACK serial={}", self.trace_id, ack.serial);
+                return;
+            }
+        };
+        self.asconf_outstanding = false;
+
+        // A request without a response succeeded, unless an earlier one
+        // failed and the peer stopped there (RFC 5061 Section 5.3).
+        let mut failed = false;
+        for request in asconf.params {
+            let correlation_id = match request {
+                SctpParameter::AddIp(id, _)
+                | SctpParameter::DeleteIp(id, _)
+                | SctpParameter::SetPrimary(id, _) => id,
+                _ => continue,
+            };
+            let response = ack.params.iter().find(|param| match param {
+                SctpParameter::SuccessIndication(id)
+                | SctpParameter::ErrorCauseIndication(id, _) => *id == correlation_id,
+                _ => false,
+            });
+            let result = match response {
+                Some(SctpParameter::ErrorCauseIndication(_, causes)) => {
+                    failed = true;
+                    Err(causes.first().cloned())
                 }
+                None if failed => Err(None),
+                _ => Ok(()),
             };
-            off += consumed;
+            self.on_asconf_request_completed(request, result);
+        }
+        self.send_asconf();
+    }
 
-            trace!(
-                "{} recv CHUNK type={}, from={}",
-                self.trace_id,
-                chunk.get_type(),
-                from
-            );
-            if chunk.is_control() && data_appears {
-                self.abort(
-                    sbuf,
-                    Some(SctpErrorCause::ProtocolViolation(Vec::from(
-                        format!(
-                            "DATA chunk followed by CONTROL chunk type={}",
-                            chunk.get_type()
-                        )
-                        .as_str(),
-                    ))),
-                );
-                return Err(SctpError::ProtocolViolation);
+    fn on_asconf_request_completed(
+        &mut self,
+        request: SctpParameter,
+        result: std::result::Result<(), Option<SctpErrorCause>>,
+    ) {
+        trace!(
+            "{} ASCONF request completed request={:?}, result={:?}",
+            self.trace_id,
+            request,
+            result
+        );
+        let transition = match (&request, &result) {
+            (SctpParameter::AddIp(_, addr), Ok(())) => Some((
+                *addr,
+                SctpLocalAddressState::Adding,
+                SctpLocalAddressState::Added,
+            )),
+            (SctpParameter::AddIp(_, addr), Err(_)) => Some((
+                *addr,
+                SctpLocalAddressState::Adding,
+                SctpLocalAddressState::Deleted,
+            )),
+            (SctpParameter::DeleteIp(_, addr), Ok(())) => Some((
+                *addr,
+                SctpLocalAddressState::Deleting,
+                SctpLocalAddressState::Deleted,
+            )),
+            (SctpParameter::DeleteIp(_, addr), Err(_)) => Some((
+                *addr,
+                SctpLocalAddressState::Deleting,
+                SctpLocalAddressState::Added,
+            )),
+            _ => None,
+        };
+        if let Some((addr, from, to)) = transition {
+            if let Some(laddr) = self
+                .laddr_list
+                .iter_mut()
+                .find(|x| x.addr == addr && x.state == from)
+            {
+                laddr.state = to;
             }
-
-            match chunk {
+        }
+        self.events.push_back(match result {
+            Ok(()) => SctpEvent::AsconfSucceeded { request },
+            Err(cause) => SctpEvent::AsconfFailed { request, cause },
+        });
+    }
+
+    // An ASCONF from an address being added is found by the address parameter
+    // (RFC 5061 Section 5.2), once the AUTH chunk bundled before it is verified.
+    pub(crate) fn find_asconf_path(&self, rbuf: &[u8]) -> Option<usize> {
+        if !self.asconf_supported {
+            return None;
+        }
+        let (auth, consumed) = match SctpChunk::from_bytes(rbuf) {
+            Ok((SctpChunk::Auth(auth), consumed)) => (auth, consumed),
+            _ => return None,
+        };
+        let asconf = match SctpChunk::from_bytes(&rbuf[consumed..]) {
+            Ok((SctpChunk::Asconf(asconf), _)) => asconf,
+            _ => return None,
+        };
+        if !self.verify_auth(&auth, rbuf) {
+            return None;
+        }
+        self.get_pathid(&asconf.addr)
+    }
+
+    // Each ASCONF is processed once. A retransmitted one gets the same
+    // ASCONF-ACK again, sent to the source address (RFC 5061 Section 5.2).
+    fn on_asconf_received(&mut self, asconf: SctpAsconfChunk, from: &IpAddr, pathid: usize) {
+        let serial = SerialNumber(asconf.serial);
+        if serial == self.peer_asconf_serial + 1 {
+            let mut params = Vec::new();
+            for request in asconf.params {
+                let (correlation_id, result) = match &request {
+                    SctpParameter::AddIp(id, addr) => {
+                        (*id, self.on_peer_add_ip(&request, addr, from))
+                    }
+                    SctpParameter::DeleteIp(id, addr) => {
+                        (*id, self.on_peer_delete_ip(&request, addr, from))
+                    }
+                    SctpParameter::SetPrimary(id, addr) => {
+                        (*id, self.on_peer_set_primary(&request, addr, from))
+                    }
+                    _ => {
+                        trace!(
+                            "{} skip ASCONF parameter param={:?}",
+                            self.trace_id,
+                            request
+                        );
+                        continue;
+                    }
+                };
+                params.push(match result {
+                    Ok(()) => SctpParameter::SuccessIndication(correlation_id),
+                    Err(cause) => SctpParameter::ErrorCauseIndication(correlation_id, vec![cause]),
+                });
+            }
+            self.peer_asconf_serial = serial;
+            self.last_asconf_ack = Some(SctpChunk::AsconfAck(SctpAsconfAckChunk {
+                serial: asconf.serial,
+                params: params,
+            }));
+        } else if serial != self.peer_asconf_serial || self.last_asconf_ack.is_none() {
+            trace!(
+                "{} discard ASCONF serial={}, expected={}",
+                self.trace_id,
+                serial,
+                self.peer_asconf_serial + 1
+            );
+            return;
+        }
+
+        let pathid = self.get_pathid(from).unwrap_or(pathid);
+        trace!(
+            "{} send ASCONF-ACK serial={}, pathid={}",
+            self.trace_id,
+            serial,
+            pathid
+        );
+        self.control_waiting_trans.insert(
+            self.next_control_sequence.0,
+            (self.last_asconf_ack.clone().unwrap(), pathid),
+        );
+        self.next_control_sequence += 1;
+    }
+
+    // The error causes carry the refused request.
+    fn get_request_tlv(request: &SctpParameter) -> Vec<u8> {
+        let mut tlv = Vec::new();
+        request.to_bytes(&mut tlv).unwrap();
+        tlv
+    }
+
+    // The wildcard address stands for the source address of the ASCONF.
+    fn on_peer_add_ip(
+        &mut self,
+        request: &SctpParameter,
+        addr: &IpAddr,
+        from: &IpAddr,
+    ) -> std::result::Result<(), SctpErrorCause> {
+        let addr = if addr.is_unspecified() { from } else { addr };
+        match self.add_raddr(addr) {
+            Ok(pathid) => {
+                self.events.push_back(SctpEvent::PeerAddressAdded {
+                    addr: *addr,
+                    pathid: pathid,
+                });
+                Ok(())
+            }
+            Err(SctpError::Done) => Ok(()),
+            Err(_) => Err(SctpErrorCause::ResourceShortage(
+                SctpAssociation::get_request_tlv(request),
+            )),
+        }
+    }
+
+    // The wildcard address deletes all addresses but the source address.
+    fn on_peer_delete_ip(
+        &mut self,
+        request: &SctpParameter,
+        addr: &IpAddr,
+        from: &IpAddr,
+    ) -> std::result::Result<(), SctpErrorCause> {
+        if addr == from {
+            return Err(SctpErrorCause::DeleteSourceAddr(
+                SctpAssociation::get_request_tlv(request),
+            ));
+        }
+        let addrs: Vec<IpAddr> = self
+            .raddr_list
+            .iter()
+            .filter(|x| x.state == SctpRemoteAddressState::Added)
+            .map(|x| x.addr)
+            .filter(|x| {
+                if addr.is_unspecified() {
+                    x != from
+                } else {
+                    x == addr
+                }
+            })
+            .collect();
+        for addr in addrs {
+            let pathid = match self.delete_raddr(&addr) {
+                Ok(v) => v,
+                Err(_) => {
+                    return Err(SctpErrorCause::DeleteLastAddr(
+                        SctpAssociation::get_request_tlv(request),
+                    ));
+                }
+            };
+            self.events
+                .push_back(SctpEvent::PeerAddressRemoved { addr, pathid });
+        }
+        Ok(())
+    }
+
+    fn on_peer_set_primary(
+        &mut self,
+        request: &SctpParameter,
+        addr: &IpAddr,
+        from: &IpAddr,
+    ) -> std::result::Result<(), SctpErrorCause> {
+        let addr = if addr.is_unspecified() { from } else { addr };
+        let pathid = match self.get_pathid(addr) {
+            Some(v) => v,
+            None => {
+                let (param_type, octets) = match addr {
+                    IpAddr::V4(addr4) => (SctpParameterType::Ipv4, addr4.octets().to_vec()),
+                    IpAddr::V6(addr6) => (SctpParameterType::Ipv6, addr6.octets().to_vec()),
+                };
+                return Err(SctpErrorCause::UnresolvableAddr(
+                    param_type,
+                    4 + octets.len() as u16,
+                    octets,
+                ));
+            }
+        };
+        if self.recovery.request_primary_path(pathid).is_err() {
+            return Err(SctpErrorCause::ResourceShortage(Self::get_request_tlv(
+                request,
+            )));
+        }
+        self.events.push_back(SctpEvent::PeerSetPrimary {
+            addr: *addr,
+            pathid,
+        });
+        Ok(())
+    }
+
+    fn get_header_overhead(addr: &IpAddr) -> usize {
+        if addr.is_ipv4() {
+            20 + 8 + 12 // IPv4 hdr, UDP hdr, and SCTP hdr
+        } else {
+            40 + 8 + 12 // IPv6 hdr, UDP hdr, and SCTP hdr
+        }
+    }
+
+    fn add_raddr(&mut self, addr: &IpAddr) -> Result<usize> {
+        let ret = self.raddr_list.iter_mut().find(|x| x.addr == *addr);
+        let mut raddr = if ret.is_some() {
+            ret.unwrap()
+        } else {
+            let len = self.raddr_list.len();
+            let mtu = DEFAULT_MTU - SctpAssociation::get_header_overhead(addr);
+            self.raddr_list
+                .push_back(SctpRemoteAddress::new(addr, mtu, len));
+            self.raddr_list.back_mut().unwrap()
+        };
+
+        if raddr.state != SctpRemoteAddressState::Deleted {
+            trace!(
+                "{} already assigned remote address addr={}, pathid={}",
+                self.trace_id,
+                raddr.addr,
+                raddr.pathid
+            );
+            return Err(SctpError::Done);
+        }
+        let overhead = SctpAssociation::get_header_overhead(&raddr.addr);
+        if raddr.pathid < self.recovery.get_path_count() {
+            // A deleted address is added again.
+            self.recovery
+                .restore_path(raddr.pathid, raddr.mtu + overhead, overhead)?;
+        } else {
+            let pathid = self.recovery.add_path(raddr.mtu + overhead, overhead);
+            assert!(pathid == raddr.pathid);
+        }
+        raddr.state = SctpRemoteAddressState::Added;
+        trace!(
+            "{} remote address assigned addr={}, pathid={}",
+            self.trace_id,
+            raddr.addr,
+            raddr.pathid
+        );
+        Ok(raddr.pathid)
+    }
+
+    // Outstanding DATA and control chunks for the deleted address move to another path.
+    fn delete_raddr(&mut self, addr: &IpAddr) -> Result<usize> {
+        let pathid = match self.get_pathid(addr) {
+            Some(v) => v,
+            None => return Err(SctpError::InvalidAddress),
+        };
+        self.recovery.remove_path(pathid)?;
+        self.raddr_list[pathid].state = SctpRemoteAddressState::Deleted;
+
+        let active_pathid = self.get_active_path().unwrap();
+        for (_, pathid1) in self.control_waiting_trans.values_mut() {
+            if *pathid1 == pathid {
+                *pathid1 = active_pathid;
+            }
+        }
+        if self.last_data_from == Some(pathid) {
+            self.last_data_from = None;
+        }
+        trace!(
+            "{} remote address deleted addr={}, pathid={}",
+            self.trace_id,
+            addr,
+            pathid
+        );
+        Ok(pathid)
+    }
+
+    // Stops sending to one of the peer's addresses. DATA in flight to it is
+    // retransmitted on another path. The last confirmed address is kept.
+    pub fn remove_raddr(&mut self, addr: &IpAddr) -> Result<()> {
+        let pathid = match self.get_pathid(addr) {
+            Some(v) => v,
+            None => return Err(SctpError::InvalidAddress),
+        };
+        if self.state == SctpAssociationState::Closed {
+            return Err(SctpError::InvalidState(self.state));
+        }
+        if !self.recovery.has_other_confirmed_path(pathid) {
+            trace!(
+                "{} cannot remove the last usable remote address addr={}",
+                self.trace_id,
+                addr
+            );
+            return Err(SctpError::InvalidAddress);
+        }
+        self.delete_raddr(addr)?;
+        self.update_active_path();
+        Ok(())
+    }
+
+    // The peer's addresses in use, ordered by pathid
+    pub fn list_paths(&self) -> Vec<SctpPathInfo> {
+        let primary = self.get_primary_path();
+        self.raddr_list
+            .iter()
+            .filter(|x| x.state == SctpRemoteAddressState::Added)
+            .filter_map(|raddr| {
+                let (confirmed, state) = self.recovery.get_path_state(raddr.pathid).ok()?;
+                Some(SctpPathInfo {
+                    pathid: raddr.pathid,
+                    addr: raddr.addr,
+                    confirmed,
+                    state,
+                    primary: primary == Some(raddr.pathid),
+                })
+            })
+            .collect()
+    }
+
+    pub fn get_pathid(&self, addr: &IpAddr) -> Option<usize> {
+        self.raddr_list
+            .iter()
+            .find(|x| x.addr == *addr && x.state == SctpRemoteAddressState::Added)
+            .map(|raddr| raddr.pathid)
+    }
+
+    pub fn get_remote_ip(&self, pathid: usize) -> Option<IpAddr> {
+        self.raddr_list
+            .get(pathid)
+            .filter(|x| x.state == SctpRemoteAddressState::Added)
+            .map(|raddr| raddr.addr)
+    }
+
+    pub fn get_primary_path(&self) -> Option<usize> {
+        self.recovery.get_primary_path()
+    }
+
+    pub fn set_primary_path(&mut self, pathid: usize) -> Result<()> {
+        self.recovery.set_primary_path(pathid)
+    }
+
+    // Only ICMP messages quoting a packet sent by this association are
+    // accepted (RFC 9260 Appendix C). The quoted header starts at the SCTP
+    // common header.
+    fn validate_icmp(&self, dst: &IpAddr, quoted_header: &[u8]) -> Result<usize> {
+        if quoted_header.len() < 8 {
+            return Err(SctpError::BufferTooShort);
+        }
+        let q = quoted_header;
+        let src_port = u16::from_be_bytes([q[0], q[1]]);
+        let dst_port = u16::from_be_bytes([q[2], q[3]]);
+        let vtag = u32::from_be_bytes([q[4], q[5], q[6], q[7]]);
+        if src_port != self.src_port || dst_port != self.dst_port {
+            return Err(SctpError::OOTB);
+        }
+        if vtag == 0 {
+            // Only an INIT has no tag, its Initiate Tag must be ours.
+            if q.len() < 20
+                || SctpChunkType(q[12]) != SctpChunkType::Init
+                || u32::from_be_bytes([q[16], q[17], q[18], q[19]]) != self.my_vtag
+            {
+                return Err(SctpError::OOTB);
+            }
+        } else if vtag != self.peer_vtag {
+            return Err(SctpError::OOTB);
+        }
+        self.get_pathid(dst).ok_or(SctpError::InvalidPathId)
+    }
+
+    // ICMPv4 Fragmentation Needed or ICMPv6 Packet Too Big. The path MTU is
+    // lowered, and DATA not yet sent is fragmented for it.
+    pub fn on_icmp_packet_too_big(
+        &mut self,
+        dst: &IpAddr,
+        mtu: usize,
+        quoted_header: &[u8],
+    ) -> Result<()> {
+        let pathid = self.validate_icmp(dst, quoted_header)?;
+        trace!(
+            "{} ICMP packet too big pathid={}, mtu={}",
+            self.trace_id,
+            pathid,
+            mtu
+        );
+        self.recovery.on_packet_too_big(pathid, mtu, Instant::now())
+    }
+
+    // ICMP Destination Unreachable. The path is inactive until a HEARTBEAT
+    // is answered again, and new data moves to another path.
+    pub fn on_icmp_unreachable(&mut self, dst: &IpAddr, quoted_header: &[u8]) -> Result<()> {
+        let pathid = self.validate_icmp(dst, quoted_header)?;
+        trace!("{} ICMP unreachable pathid={}", self.trace_id, pathid);
+        self.recovery.on_path_unreachable(pathid)?;
+        self.update_active_path();
+        Ok(())
+    }
+
+    // The path MTU discovered so far, including the IP and UDP headers
+    pub fn get_pmtu(&self, pathid: usize) -> Result<usize> {
+        self.recovery.get_pmtu(pathid)
+    }
+
+    pub fn get_active_path(&self) -> Option<usize> {
+        self.recovery.get_active_path()
+    }
+
+    pub fn set_switch_back_policy(&mut self, policy: SctpSwitchBackPolicy) {
+        self.recovery.set_switch_back_policy(policy);
+    }
+
+    // Every path starts over with a new controller of the algorithm.
+    pub fn set_congestion_control(&mut self, congestion_control: SctpCongestionControl) {
+        self.recovery.set_congestion_control(congestion_control);
+    }
+
+    // Make the alternate path the new primary path instead of switching back
+    pub fn set_primary_switchover(&mut self, enabled: bool) {
+        self.recovery.set_primary_switchover(enabled);
+    }
+
+    // Concurrent Multipath Transfer (CMT): new data is spread over every
+    // confirmed active path instead of only the primary path. Both ends
+    // should enable it, as SACKs report the packets they acknowledge for
+    // Delayed Ack for CMT.
+    pub fn set_cmt(&mut self, enabled: bool) {
+        self.recovery.set_cmt(enabled);
+    }
+
+    // Spread new data over the RTT instead of sending it in bursts. send()
+    // returns Done while the path waits for its pacing time.
+    pub fn set_pacing(&mut self, enabled: bool) {
+        self.recovery.set_pacing(enabled);
+    }
+
+    // Like SCTP_NODELAY. When disabled, small messages are held back while
+    // data is outstanding until a full packet can be filled.
+    pub fn set_nodelay(&mut self, enabled: bool) {
+        self.nodelay = enabled;
+    }
+
+    // Max.Burst: packets of new data sent in response to a single SACK.
+    // 0 disables the limit.
+    pub fn set_max_burst(&mut self, max_burst: usize) {
+        self.max_burst = max_burst;
+    }
+
+    // Sends a HEARTBEAT to the path now, e.g. to confirm an address added by
+    // the peer. Err(Done) if one is already pending or verification is rate
+    // limited.
+    pub fn request_heartbeat(&mut self, pathid: usize) -> Result<()> {
+        self.recovery.request_heartbeat(pathid, Instant::now())
+    }
+
+    pub fn get_path_params(&self, pathid: usize) -> Result<SctpPathParams> {
+        self.recovery.get_path_params(pathid)
+    }
+
+    // Running timers of the path are shortened if they exceed the new RTO.
+    pub fn set_path_params(&mut self, pathid: usize, params: &SctpPathParams) -> Result<()> {
+        self.recovery
+            .set_path_params(pathid, params, Instant::now())
+    }
+
+    pub fn get_path_status(&self, pathid: usize) -> Result<SctpPathStatus> {
+        self.recovery.get_path_status(pathid)
+    }
+
+    pub fn get_status(&self) -> SctpStatus {
+        SctpStatus {
+            state: self.state,
+            peer_rwnd: self.recovery.get_peer_rwnd(),
+            a_rwnd: self.get_rwnd(),
+            unacked_data: self.recovery.get_unacked_count(),
+            pending_data: self.get_waiting_num(false)
+                + self.get_waiting_num(true)
+                + self.get_readble_num(false)
+                + self.get_readble_num(true),
+            next_tsn: self.recovery.get_largest_tsn().wrapping_add(1),
+            cumulative_tsn_ack: self.recovery.get_cum_ack(),
+            cumulative_tsn: self.mapping_array.cummulative_tsn.0,
+            inbound_streams: self.stream_in.len(),
+            outbound_streams: self.stream_out.len(),
+            primary_path: self.get_primary_path(),
+        }
+    }
+
+    // Consecutive timeouts before a path is potentially failed, Path.Max.Retrans disables it
+    pub fn set_pf_max_retrans(&mut self, pf_max_retrans: u32) {
+        self.recovery.set_pf_max_retrans(pf_max_retrans);
+    }
+
+    pub fn poll_event(&mut self) -> Option<SctpEvent> {
+        self.events.pop_front()
+    }
+
+    fn update_active_path(&mut self) {
+        if let Some(event) = self.recovery.update_active_path() {
+            trace!("{} {:?}", self.trace_id, event);
+            self.events.push_back(event);
+        }
+    }
+
+    pub fn get_timeout(&self) -> Option<Duration> {
+        let mut timeouts = Vec::new();
+        if let Some(timeout) = self.delayed_ack_timeout {
+            timeouts.push(timeout);
+        }
+        if let Some(timeout) = self.recovery.get_timeout() {
+            timeouts.push(timeout);
+        }
+        if self.get_pending().next().is_some() {
+            if let Some(timeout) = self.recovery.get_pacing_timeout(Instant::now()) {
+                timeouts.push(timeout);
+            }
+        }
+
+        let min_timeout = timeouts.into_iter().min();
+        if let Some(timeout) = min_timeout {
+            let now = Instant::now();
+            if timeout <= now {
+                return Some(Duration::new(0, 0));
+            } else {
+                return Some(timeout.duration_since(now));
+            }
+        } else {
+            return None;
+        }
+    }
+
+    pub fn on_timeout(&mut self) {
+        self.on_timeout_at(Instant::now());
+    }
+
+    fn on_timeout_at(&mut self, now: Instant) {
+        self.on_delayed_ack_timeout(now);
+        // A SHUTDOWN retransmitted on T2 expiry carries the last TSN
+        // received in sequence.
+        self.recovery
+            .update_peer_cumulative_tsn_ack(self.mapping_array.cummulative_tsn.0);
+        self.recovery.on_timeout(now);
+        self.update_active_path();
+
+        if self.state == SctpAssociationState::Closed {
+            return;
+        }
+        if self.recovery.is_shutdown_guard_expired() {
+            trace!("{} shutdown guard timeout expired", self.trace_id);
+            self.send_abort = true;
+        } else if self.recovery.is_closing() {
+            match self.state {
+                SctpAssociationState::CookieWait | SctpAssociationState::CookieEchoed => {
+                    trace!("{} handshake timed out", self.trace_id);
+                    self.set_closed(SctpCloseReason::HandshakeTimeout);
+                }
+                _ => {
+                    trace!("{} retransmission limit exceeded", self.trace_id);
+                    self.set_closed(SctpCloseReason::RetransmissionLimit);
+                }
+            }
+        }
+    }
+
+    fn on_delayed_ack_timeout(&mut self, now: Instant) {
+        if let Some(timeout) = self.delayed_ack_timeout {
+            if timeout <= now {
+                trace!("{} delayed ack timeout expired", self.trace_id);
+                self.send_sack = true;
+                self.delayed_ack_timeout = None;
+            }
+        }
+    }
+    fn set_delayed_ack_timer(&mut self) -> bool {
+        if self.delayed_ack_timeout.is_none() {
+            trace!(
+                "{} set delayed ack timeout ack_delay={:?}",
+                self.trace_id,
+                self.ack_delay
+            );
+            self.delayed_ack_timeout = Some(Instant::now() + self.ack_delay);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn set_checksum(sbuf: &mut Vec<u8>) {
+        let checksum = crc32::checksum_castagnoli(sbuf);
+        let bytes = sbuf.as_mut_slice();
+        bytes[0x08] = ((checksum >> 0) & 0x000000FF) as u8;
+        bytes[0x09] = ((checksum >> 8) & 0x000000FF) as u8;
+        bytes[0x0a] = ((checksum >> 16) & 0x000000FF) as u8;
+        bytes[0x0b] = ((checksum >> 24) & 0x000000FF) as u8;
+    }
+
+    pub fn recv(&mut self, from: &IpAddr, rbuf: &[u8], sbuf: &mut Vec<u8>) -> Result<usize> {
+        let info = SctpRecvInfo {
+            from: *from,
+            ce: false,
+        };
+        self.recv_with_info(rbuf, sbuf, &info)
+    }
+
+    pub fn recv_with_info(
+        &mut self,
+        rbuf: &[u8],
+        sbuf: &mut Vec<u8>,
+        info: &SctpRecvInfo,
+    ) -> Result<usize> {
+        let from = &info.from;
+        let mut off = 0;
+        let recv_time = Instant::now();
+        // A packet from an unknown address is silently discarded, unless it is
+        // an authenticated ASCONF adding that address.
+        let pathid = self
+            .get_pathid(from)
+            .or_else(|| self.find_asconf_path(rbuf));
+        if pathid.is_none() {
+            trace!(
+                "{} discard packet from unknown from={}",
+                self.trace_id,
+                from
+            );
+            return Err(SctpError::OOTB);
+        }
+
+        let mut data_appears = false;
+        let mut lowest_data_tsn: Option<SerialNumber<u32>> = None;
+        let mut authenticated = false;
+        while off < rbuf.len() {
+            let (chunk, consumed) = match SctpChunk::from_bytes(&rbuf[off..]) {
+                Ok(v) => v,
+                Err(e) => {
+                    return Err(e.add_offset(off));
+                }
+            };
+            let chunk_off = off;
+            off += consumed;
+
+            trace!(
+                "{} recv CHUNK type={}, from={}",
+                self.trace_id,
+                chunk.get_type(),
+                from
+            );
+            if chunk.is_control() && data_appears {
+                self.write_abort(
+                    sbuf,
+                    SctpCloseReason::ProtocolViolation(SctpErrorCause::ProtocolViolation(
+                        Vec::from(
+                            format!(
+                                "DATA chunk followed by CONTROL chunk type={}",
+                                chunk.get_type()
+                            )
+                            .as_str(),
+                        ),
+                    )),
+                );
+                return Err(SctpError::ProtocolViolation);
+            }
+
+            if !self.is_chunk_acceptable(chunk.get_type()) {
+                trace!(
+                    "{} discard CHUNK type={}, state={:?}",
+                    self.trace_id,
+                    chunk.get_type(),
+                    self.state
+                );
+                if chunk.get_type() == SctpChunkType::ShutdownAck
+                    && (self.state == SctpAssociationState::CookieWait
+                        || self.state == SctpAssociationState::CookieEchoed)
+                {
+                    // It is treated as an OOTB packet (RFC 9260 Section 8.5.1).
+                    self.send_ootb_shutdown_complete(sbuf);
+                    return Err(SctpError::OOTB);
+                }
+                continue;
+            }
+
+            match chunk {
                 SctpChunk::Data(data_chunk) => {
                     self.recv_data_count += 1;
 
-                    let stream_id = data_chunk.proto_id;
+                    let stream_id = data_chunk.stream_id;
                     let tsn = data_chunk.tsn;
 
+                    if data_chunk.data.is_empty() {
+                        self.write_abort(
+                            sbuf,
+                            SctpCloseReason::ProtocolViolation(SctpErrorCause::NoUserData(tsn)),
+                        );
+                        return Err(SctpError::ProtocolViolation);
+                    }
+                    let lower = match lowest_data_tsn {
+                        Some(lowest) => SerialNumber(tsn) < lowest,
+                        None => true,
+                    };
+                    if lower {
+                        lowest_data_tsn = Some(SerialNumber(tsn));
+                    }
+
+                    if data_chunk.i_bit {
+                        self.send_sack = true;
+                    }
+
+                    let duplicate = self.mapping_array.is_duplicate(tsn);
                     if let Ok(_) = self.mapping_array.update(tsn) {
                         let stream_in = match self.stream_in.get_mut(stream_id as usize) {
                             Some(v) => v,
@@ -820,13 +2053,26 @@ impl SctpAssociation {
                         stream_in.recv(data_chunk)?;
 
                         self.num_data_pkts_seen += 1;
-                        if !self.delayed_ack || self.num_data_pkts_seen >= self.ack_freq {
+                        // Gaps are reported at once (RFC 9260 Section 6.7),
+                        // but not with CMT where DATA sent to different paths
+                        // arrives out of order. Its SACK reports the packets
+                        // acknowledged instead (Delayed Ack for CMT).
+                        let gap_reported = !self.recovery.is_cmt_enabled()
+                            && self.mapping_array.largest_tsn != self.mapping_array.cummulative_tsn;
+                        if !self.delayed_ack
+                            || self.num_data_pkts_seen >= self.ack_freq
+                            || gap_reported
+                        {
                             self.send_sack = true;
                         } else {
                             self.set_delayed_ack_timer();
                         }
                         data_appears = true;
                         self.last_data_from = pathid;
+                    } else if duplicate {
+                        // Reported without delay (RFC 9260 Section 6.2)
+                        self.send_sack = true;
+                        self.last_data_from = pathid;
                     }
                 }
                 SctpChunk::InitAck(initack) => {
@@ -839,6 +2085,11 @@ impl SctpAssociation {
                             _ => None,
                         })
                         .collect();
+                    let auth_key =
+                        SctpAssociation::generate_auth_key(&self.auth_params, &initack.params);
+                    let asconf_supported =
+                        auth_key.is_some() && SctpAssociation::is_asconf_supported(&initack.params);
+                    let ecn_supported = SctpAssociation::is_ecn_supported(&initack.params);
 
                     let cookie = initack
                         .params
@@ -856,14 +2107,36 @@ impl SctpAssociation {
                         return Err(SctpError::ProtocolViolation);
                     }
 
+                    if initack.init_tag == 0
+                        || initack.num_out_strm == 0
+                        || initack.num_in_strm == 0
+                    {
+                        self.write_abort(
+                            sbuf,
+                            SctpCloseReason::ProtocolViolation(SctpErrorCause::ProtocolViolation(Vec::from(
+                                format!(
+                                    "INIT-ACK with invalid parameter init_tag={}, num_out_strm={}, num_in_strm={}",
+                                    initack.init_tag, initack.num_out_strm, initack.num_in_strm
+                                )
+                                .as_str(),
+                            ))),
+                        );
+                        return Err(SctpError::ProtocolViolation);
+                    }
+
                     let init = match self.recovery.on_t1_chunk_received(recv_time) {
                         Some(SctpChunk::Init(v)) => v,
                         Some(_) | None => {
-                            panic!("{} Cannot find an INIT chunk", self.trace_id);
+                            trace!("{} Cannot find an INIT chunk", self.trace_id);
+                            continue;
                         }
                     };
                     self.mapping_array.initialize(initack.init_tsn).unwrap();
                     self.peer_vtag = initack.init_tag;
+                    self.peer_asconf_serial = SerialNumber(initack.init_tsn.wrapping_sub(1));
+                    self.auth_key = auth_key;
+                    self.asconf_supported = asconf_supported;
+                    self.ecn_supported = ecn_supported;
                     self.generate_stream_in(cmp::min(init.num_in_strm, initack.num_out_strm))
                         .unwrap();
                     self.generate_stream_out(cmp::min(init.num_out_strm, initack.num_in_strm))
@@ -905,15 +2178,17 @@ impl SctpAssociation {
                     self.next_control_sequence += 1;
                 }
                 SctpChunk::Sack(..) => {
-                    self.recovery.on_sack_received(chunk, recv_time);
-                    if self.state == SctpAssociationState::ShutdownPending {
-                        if self
-                            .recovery
-                            .on_enter_shutdown(self.mapping_array.cummulative_tsn.0)
-                        {
-                            self.state = SctpAssociationState::ShutdownSent;
-                        }
+                    if let Err(e) = self.recovery.on_sack_received(chunk, recv_time) {
+                        self.write_abort(
+                            sbuf,
+                            SctpCloseReason::ProtocolViolation(SctpErrorCause::ProtocolViolation(
+                                Vec::from("SACK acknowledges unsent TSN".as_bytes()),
+                            )),
+                        );
+                        return Err(e);
                     }
+                    self.send_burst_count = 0;
+                    self.check_shutdown_ready();
                 }
                 SctpChunk::Heartbeat(hbinfo) => {
                     self.control_waiting_trans.insert(
@@ -923,43 +2198,238 @@ impl SctpAssociation {
                     self.next_control_sequence += 1;
                 }
                 SctpChunk::HeartbeatAckWithInfo(..) => {
-                    self.recovery.on_heartbeatack_received(chunk, recv_time);
+                    if let Some(event) = self.recovery.on_heartbeatack_received(chunk, recv_time) {
+                        self.events.push_back(event);
+                    }
                 }
                 SctpChunk::Abort(abort) => {
-                    self.error_cause = abort.error_cause;
-                    self.state = SctpAssociationState::Closed;
+                    self.set_closed(SctpCloseReason::PeerAbort(abort.error_cause));
                     break;
                 }
-                SctpChunk::Shutdown(_) => {
-                    self.state = SctpAssociationState::ShutdownReceived;
-                    self.recovery.on_shutdown_received();
+                SctpChunk::Shutdown(cumulative_tsn_ack) => {
+                    if let Err(e) = self
+                        .recovery
+                        .on_shutdown_received(cumulative_tsn_ack, recv_time)
+                    {
+                        self.write_abort(
+                            sbuf,
+                            SctpCloseReason::ProtocolViolation(SctpErrorCause::ProtocolViolation(
+                                Vec::from(
+                                    format!(
+                                        "SHUTDOWN acknowledges unsent TSN cumulative_tsn_ack={}",
+                                        cumulative_tsn_ack
+                                    )
+                                    .as_str(),
+                                ),
+                            )),
+                        );
+                        return Err(e);
+                    }
+                    match self.state {
+                        SctpAssociationState::Established
+                        | SctpAssociationState::ShutdownPending => {
+                            self.state = SctpAssociationState::ShutdownReceived;
+                            self.check_shutdown_ready();
+                        }
+                        SctpAssociationState::ShutdownReceived => {
+                            self.check_shutdown_ready();
+                        }
+                        SctpAssociationState::ShutdownSent => {
+                            // Both endpoints sent SHUTDOWN at the same time.
+                            self.recovery.send_shutdown_ack();
+                            self.state = SctpAssociationState::ShutdownAckSent;
+                        }
+                        _ => {}
+                    }
                 }
                 SctpChunk::ShutdownAck => {
-                    if self.state == SctpAssociationState::ShutdownSent {
-                        self.state = SctpAssociationState::Closed;
-                        self.recovery.on_shutdown_ack_received();
-                        self.send_shutdown_complete(sbuf);
+                    self.recovery.on_shutdown_completed();
+                    self.send_shutdown_complete(sbuf);
+                    break;
+                }
+                SctpChunk::Init(init) => {
+                    if self.state == SctpAssociationState::ShutdownAckSent {
+                        // SHUTDOWN-COMPLETE may be lost (RFC 9260 Section 9.2).
+                        self.recovery.send_shutdown_ack();
+                    } else {
+                        // A restart or an INIT collision (RFC 9260 Section 5.2.2)
+                        // needs a State Cookie, which only the endpoint can make.
+                        // The INIT is refused and this association is kept.
+                        self.send_init_abort(sbuf, init.init_tag);
+                        return Ok(rbuf.len());
                     }
                 }
+                SctpChunk::CookieEcho(..) => {
+                    // COOKIE-ACK may be lost (RFC 9260 Section 5.2.4, Action D).
+                    self.control_waiting_trans.insert(
+                        self.next_control_sequence.0,
+                        (SctpChunk::CookieAck, pathid.unwrap()),
+                    );
+                    self.next_control_sequence += 1;
+                }
                 SctpChunk::CookieAck => {
                     match self.recovery.on_t1_chunk_received(recv_time) {
                         Some(SctpChunk::CookieEcho(..)) => {}
                         Some(_) | None => {
-                            panic!("{} Cannot find a COOKIE-ECHO chunk", self.trace_id);
+                            trace!("{} Cannot find a COOKIE-ECHO chunk", self.trace_id);
+                            continue;
                         }
                     };
                     self.recovery.establish();
                     self.state = SctpAssociationState::Established;
                 }
                 SctpChunk::ShutdownComplete(_) => {
-                    self.state = SctpAssociationState::Closed;
+                    self.recovery.on_shutdown_completed();
+                    self.set_closed(SctpCloseReason::Shutdown);
+                    break;
+                }
+                SctpChunk::Auth(auth) => {
+                    if !self.verify_auth(&auth, &rbuf[chunk_off..]) {
+                        // The AUTH chunk and all the chunks after it are
+                        // silently discarded (RFC 4895 Section 6.3).
+                        trace!("{} discard CHUNK after invalid AUTH", self.trace_id);
+                        off = rbuf.len();
+                        break;
+                    }
+                    authenticated = true;
+                }
+                SctpChunk::Asconf(asconf) if authenticated => {
+                    self.on_asconf_received(asconf, from, pathid.unwrap());
+                }
+                SctpChunk::Ecne(lowest_tsn) if self.ecn_supported => {
+                    self.recovery.on_ecne_received(lowest_tsn, recv_time);
+                }
+                SctpChunk::Cwr(lowest_tsn)
+                    if matches!(
+                        self.ecne_tsn,
+                        Some(ecne_tsn) if SerialNumber(lowest_tsn) >= ecne_tsn
+                    ) =>
+                {
+                    trace!("{} stop ECNE lowest_tsn={}", self.trace_id, lowest_tsn);
+                    self.ecne_tsn = None;
+                }
+                SctpChunk::AsconfAck(ack) if authenticated => {
+                    // An ASCONF-ACK for an ASCONF never sent (RFC 5061 Section 5.3)
+                    if SerialNumber(ack.serial) >= self.asconf_serial {
+                        self.write_abort(
+                            sbuf,
+                            SctpCloseReason::ProtocolViolation(SctpErrorCause::IllegalAsconfAck),
+                        );
+                        return Err(SctpError::ProtocolViolation);
+                    }
+                    self.on_asconf_ack_received(ack);
+                }
+                SctpChunk::Asconf(_) | SctpChunk::AsconfAck(_) => {
+                    trace!("{} discard CHUNK without AUTH", self.trace_id);
+                }
+                // The highest-order 2 bits of the Chunk Type specify the action.
+                SctpChunk::Unknown(chunk_type, _, _)
+                    if chunk_type != SctpChunkType::Error && chunk_type.0 & 0x80 == 0 =>
+                {
+                    trace!(
+                        "{} stop processing the packet unrecognized CHUNK type={}",
+                        self.trace_id,
+                        chunk_type
+                    );
+                    return Ok(rbuf.len());
                 }
                 _ => {}
             }
         }
+
+        // HEARTBEAT-ACK and SACK may bring an inactive path back
+        self.update_active_path();
+
+        if data_appears {
+            self.dac_pkts_seen = self.dac_pkts_seen.saturating_add(1);
+        }
+
+        if info.ce && self.ecn_supported {
+            if let Some(lowest_tsn) = lowest_data_tsn {
+                self.on_ce_received(lowest_tsn);
+            }
+        }
+
+        if data_appears && self.state == SctpAssociationState::ShutdownSent {
+            // SHUTDOWN is sent in response to DATA, along with SACK only if there are gaps.
+            self.recovery
+                .send_shutdown(self.mapping_array.cummulative_tsn.0);
+            if self.mapping_array.largest_tsn == self.mapping_array.cummulative_tsn {
+                self.send_sack = false;
+                self.delayed_ack_timeout = None;
+                self.num_data_pkts_seen = 0;
+            } else {
+                self.send_sack = true;
+            }
+        }
         return Ok(off);
     }
 
+    // The receiver side of ECN (RFC 9260 Appendix A). The ECNE goes out with
+    // an immediate SACK.
+    fn on_ce_received(&mut self, lowest_tsn: SerialNumber<u32>) {
+        trace!("{} recv CE lowest_tsn={}", self.trace_id, lowest_tsn.0);
+        let higher = match self.ecne_tsn {
+            Some(ecne_tsn) => lowest_tsn > ecne_tsn,
+            None => true,
+        };
+        if higher {
+            self.ecne_tsn = Some(lowest_tsn);
+        }
+        self.send_sack = true;
+    }
+
+    // Chunks acceptable in each state (RFC 9260 Section 5 and 9). Others are discarded.
+    fn is_chunk_acceptable(&self, chunk_type: SctpChunkType) -> bool {
+        match chunk_type {
+            SctpChunkType::Data => matches!(
+                self.state,
+                SctpAssociationState::Established
+                    | SctpAssociationState::ShutdownPending
+                    | SctpAssociationState::ShutdownSent
+            ),
+            SctpChunkType::Init => !matches!(
+                self.state,
+                SctpAssociationState::Closed | SctpAssociationState::CookieWait
+            ),
+            SctpChunkType::InitAck => self.state == SctpAssociationState::CookieWait,
+            SctpChunkType::Sack | SctpChunkType::Ecne | SctpChunkType::Cwr => matches!(
+                self.state,
+                SctpAssociationState::Established
+                    | SctpAssociationState::ShutdownPending
+                    | SctpAssociationState::ShutdownSent
+                    | SctpAssociationState::ShutdownReceived
+            ),
+            SctpChunkType::Heartbeat | SctpChunkType::HeartbeatAck => !matches!(
+                self.state,
+                SctpAssociationState::Closed | SctpAssociationState::CookieWait
+            ),
+            SctpChunkType::Shutdown => matches!(
+                self.state,
+                SctpAssociationState::Established
+                    | SctpAssociationState::ShutdownPending
+                    | SctpAssociationState::ShutdownSent
+                    | SctpAssociationState::ShutdownReceived
+                    | SctpAssociationState::ShutdownAckSent
+            ),
+            SctpChunkType::ShutdownAck => matches!(
+                self.state,
+                SctpAssociationState::ShutdownSent | SctpAssociationState::ShutdownAckSent
+            ),
+            SctpChunkType::CookieEcho => self.state == SctpAssociationState::Established,
+            SctpChunkType::CookieAck => self.state == SctpAssociationState::CookieEchoed,
+            SctpChunkType::ShutdownComplete => self.state == SctpAssociationState::ShutdownAckSent,
+            SctpChunkType::Asconf | SctpChunkType::AsconfAck => matches!(
+                self.state,
+                SctpAssociationState::Established
+                    | SctpAssociationState::ShutdownPending
+                    | SctpAssociationState::ShutdownSent
+                    | SctpAssociationState::ShutdownReceived
+            ),
+            _ => self.state != SctpAssociationState::Closed,
+        }
+    }
+
     pub fn read_from_stream(&mut self, stream_id: u16, wbuf: &mut Vec<u8>) -> Result<usize> {
         let stream_in = match self.stream_in.get_mut(stream_id as usize) {
             Some(v) => v,
@@ -994,6 +2464,32 @@ impl SctpAssociation {
         is_unordered: bool,
         is_complete: bool,
     ) -> Result<usize> {
+        self.write_into_stream_with_flags(stream_id, rbuf, is_unordered, is_complete, false)
+    }
+
+    // With sack_immediately, the last DATA chunk of the message carries the
+    // I bit and the peer acknowledges it without delay (RFC 7053), e.g. for
+    // the last message before going idle.
+    pub fn write_into_stream_with_flags(
+        &mut self,
+        stream_id: u16,
+        rbuf: &[u8],
+        is_unordered: bool,
+        is_complete: bool,
+        sack_immediately: bool,
+    ) -> Result<usize> {
+        // New data is not accepted once the shutdown sequence starts.
+        match self.state {
+            SctpAssociationState::CookieEchoed | SctpAssociationState::Established => {}
+            _ => {
+                trace!(
+                    "{} cannot write into stream state={:?}",
+                    self.trace_id,
+                    self.state
+                );
+                return Err(SctpError::InvalidState(self.state));
+            }
+        }
         let stream_out = match self.stream_out.get_mut(stream_id as usize) {
             Some(v) => v,
             None => {
@@ -1001,7 +2497,7 @@ impl SctpAssociation {
                 return Err(SctpError::InvalidStreamId);
             }
         };
-        let len = match stream_out.write(rbuf, is_unordered, is_complete) {
+        let len = match stream_out.write(rbuf, is_unordered, is_complete, sack_immediately) {
             Ok(v) => v,
             Err(e) => {
                 return Err(e);
@@ -1035,8 +2531,35 @@ impl SctpAssociation {
     }
 
     pub fn send(&mut self, sbuf: &mut Vec<u8>) -> Result<(usize, IpAddr)> {
-        let send_time = Instant::now();
+        self.send_with_info(sbuf)
+            .map(|(written, info)| (written, info.to))
+    }
+
+    // Only packets with new DATA are ECN-capable, as retransmissions and
+    // control chunks are not (like RFC 3168 Section 6.1.5).
+    pub fn send_with_info(&mut self, sbuf: &mut Vec<u8>) -> Result<(usize, SctpSendInfo)> {
+        self.send_with_info_at(sbuf, Instant::now())
+    }
+
+    fn send_with_info_at(
+        &mut self,
+        sbuf: &mut Vec<u8>,
+        send_time: Instant,
+    ) -> Result<(usize, SctpSendInfo)> {
         let old_len = sbuf.len();
+        let old_sent_data_count = self.sent_data_count;
+
+        if self.send_abort {
+            self.send_abort = false;
+            let cause = self.abort_cause.take();
+            let written = self.write_abort(sbuf, SctpCloseReason::LocalAbort(cause));
+            let pathid = self.get_active_path().unwrap_or(0);
+            let info = SctpSendInfo {
+                to: self.get_remote_ip(pathid).unwrap(),
+                ect: false,
+            };
+            return Ok((written, info));
+        }
 
         let header = SctpCommonHeader {
             src_port: self.src_port,
@@ -1053,15 +2576,34 @@ impl SctpAssociation {
                 if let Ok((pathid1, written)) =
                     self.send_for_first_fast_retransmission(sbuf, send_time)
                 {
+                    self.recovery.on_packet_sent(pathid1, sbuf.len() - old_len);
                     SctpAssociation::set_checksum(sbuf);
-                    return Ok((written, self.get_remote_ip(pathid1).unwrap()));
+                    let info = SctpSendInfo {
+                        to: self.get_remote_ip(pathid1).unwrap(),
+                        ect: false,
+                    };
+                    return Ok((written, info));
                 }
 
                 if let Ok((pathid1, written)) =
                     self.send_for_first_t3_retransmission(sbuf, send_time)
                 {
+                    self.recovery.on_packet_sent(pathid1, sbuf.len() - old_len);
+                    SctpAssociation::set_checksum(sbuf);
+                    let info = SctpSendInfo {
+                        to: self.get_remote_ip(pathid1).unwrap(),
+                        ect: false,
+                    };
+                    return Ok((written, info));
+                }
+
+                if let Ok((pathid1, written)) = self.send_for_pmtu_probe(sbuf, send_time) {
                     SctpAssociation::set_checksum(sbuf);
-                    return Ok((written, self.get_remote_ip(pathid1).unwrap()));
+                    let info = SctpSendInfo {
+                        to: self.get_remote_ip(pathid1).unwrap(),
+                        ect: false,
+                    };
+                    return Ok((written, info));
                 }
             }
             _ => {}
@@ -1106,32 +2648,42 @@ impl SctpAssociation {
             _ => {}
         }
 
-        if self.state == SctpAssociationState::Established && !sent_for_retrans {
-            self.send_burst_count += 1;
-
-            if self.send_burst_count > MAX_BURST {
-                sbuf.clear();
-                self.send_burst_count = 0;
-                return Err(SctpError::Done);
-            }
-
+        let transmission_allowed = matches!(
+            self.state,
+            SctpAssociationState::Established
+                | SctpAssociationState::ShutdownPending
+                | SctpAssociationState::ShutdownReceived
+        );
+        if self.recovery.is_all_data_acked() {
+            // No SACK is expected to open the next burst.
+            self.send_burst_count = 0;
+        }
+        let burst_allowed = self.max_burst == 0 || self.send_burst_count < self.max_burst;
+        if transmission_allowed && !sent_for_retrans && burst_allowed {
             if let Ok((pathid1, _)) = self.send_for_transmission(sbuf, pathid, send_time) {
                 if pathid.is_none() {
                     pathid = Some(pathid1);
                 }
+                self.send_burst_count += 1;
             }
         }
 
-        if pathid.is_some() && sbuf.len() > old_len {
-            SctpAssociation::set_checksum(sbuf);
-            Ok((
-                sbuf.len() - old_len,
-                self.get_remote_ip(pathid.unwrap()).unwrap(),
-            ))
-        } else {
-            sbuf.clear();
-            self.send_burst_count = 0;
-            Err(SctpError::Done)
+        match pathid {
+            Some(pathid) if sbuf.len() > old_len => {
+                self.recovery.on_packet_sent(pathid, sbuf.len() - old_len);
+                self.sign_auth(sbuf);
+                SctpAssociation::set_checksum(sbuf);
+                let info = SctpSendInfo {
+                    to: self.get_remote_ip(pathid).unwrap(),
+                    ect: self.ecn_supported && self.sent_data_count > old_sent_data_count,
+                };
+                Ok((sbuf.len() - old_len, info))
+            }
+            _ => {
+                self.auth_offset = None;
+                sbuf.clear();
+                Err(SctpError::Done)
+            }
         }
     }
 
@@ -1165,6 +2717,30 @@ impl SctpAssociation {
         )
     }
 
+    // A probe is sent alone so that its loss is not mistaken for congestion
+    fn send_for_pmtu_probe(
+        &mut self,
+        sbuf: &mut Vec<u8>,
+        send_time: Instant,
+    ) -> Result<(usize, usize)> {
+        let (chunk, pathid, size) = match self.recovery.pmtu_probe_waiting_trans.pop_front() {
+            Some(v) => v,
+            None => return Err(SctpError::Done),
+        };
+        let old_len = sbuf.len();
+        trace!(
+            "{} transmission PMTU probe pathid={}, size={}",
+            self.trace_id,
+            pathid,
+            size
+        );
+        chunk.to_bytes(sbuf).unwrap();
+        let padding = (size + old_len).saturating_sub(sbuf.len() + 4);
+        SctpChunk::Pad(padding).to_bytes(sbuf).unwrap();
+        self.recovery.on_control_sent(chunk, pathid, send_time);
+        Ok((pathid, sbuf.len() - old_len))
+    }
+
     fn send_for_control_transmission(
         &mut self,
         sbuf: &mut Vec<u8>,
@@ -1178,6 +2754,8 @@ impl SctpAssociation {
             sbuf,
             pathid,
             send_time,
+            self.auth_key,
+            self.auth_offset,
             self.trace_id
         ) {
             pathid = Some(pathid1);
@@ -1189,6 +2767,8 @@ impl SctpAssociation {
             sbuf,
             pathid,
             send_time,
+            self.auth_key,
+            self.auth_offset,
             self.trace_id
         ) {
             pathid = Some(pathid1);
@@ -1244,15 +2824,46 @@ impl SctpAssociation {
         let pending: Vec<u16> = self.get_pending().collect();
         let old_len = sbuf.len();
         let mut mtu = None;
+        let shutdown_pending = matches!(
+            self.state,
+            SctpAssociationState::ShutdownPending | SctpAssociationState::ShutdownReceived
+        );
+
+        if !self.nodelay && !self.recovery.is_all_data_acked() {
+            let pending_len: usize = pending
+                .iter()
+                .filter_map(|strmid| self.stream_out.get(*strmid as usize))
+                .map(|strmout| strmout.pending_chunks_len())
+                .sum();
+            let data_path = pathid.or_else(|| self.recovery.get_active_path());
+            if let Some(data_path) = data_path {
+                // Hold small messages until a full packet can be filled or
+                // the outstanding data is acked.
+                if pending_len < self.recovery.get_path_mtu(data_path)? {
+                    trace!("{} delayed pending={}", self.trace_id, pending_len);
+                    return Err(SctpError::Done);
+                }
+            }
+        }
         for strmid in pending {
             if pathid.is_none() {
-                pathid = self.get_primary_path();
+                pathid = self.recovery.get_data_path();
             }
 
-            if pathid.unwrap() != self.get_primary_path().unwrap() {
+            if !self.recovery.is_data_path(pathid.unwrap()) {
                 continue;
             }
 
+            // Once started, the packet is filled up.
+            if sbuf.len() == old_len && self.recovery.is_paced(pathid.unwrap(), send_time) {
+                trace!("{} paced pathid={}", self.trace_id, pathid.unwrap());
+                break;
+            }
+
+            let others_pending = self
+                .stream_out
+                .iter()
+                .any(|v| v.stream_id != strmid && v.is_pending());
             let strmout = match self.stream_out.get_mut(strmid as usize) {
                 Some(v) => v,
                 None => {
@@ -1277,7 +2888,7 @@ impl SctpAssociation {
                     break;
                 }
                 let fragment_point = available_space - 16;
-                let data_chunk =
+                let mut data_chunk =
                     match strmout.generate_data(self.recovery.get_next_tsn(), fragment_point) {
                         Ok(Some(v)) => v,
                         Ok(None) => {
@@ -1287,6 +2898,11 @@ impl SctpAssociation {
                             break;
                         }
                     };
+                // The final DATA before SHUTDOWN is acked without delay.
+                if shutdown_pending && data_chunk.e_bit && !others_pending && !strmout.is_pending()
+                {
+                    data_chunk.i_bit = true;
+                }
                 trace!("{} transmission tsn={}", self.trace_id, data_chunk.tsn);
 
                 let chunk = SctpChunk::Data(data_chunk);
@@ -1297,6 +2913,12 @@ impl SctpAssociation {
             }
         }
 
+        if let Some(pathid) = pathid {
+            if self.get_pending().next().is_none() {
+                self.recovery.on_out_of_data(pathid);
+            }
+        }
+
         if pathid.is_some() && sbuf.len() > old_len {
             Ok((pathid.unwrap(), sbuf.len() - old_len))
         } else {
@@ -1305,60 +2927,117 @@ impl SctpAssociation {
     }
 
     fn send_sack(&mut self) {
-        let chunk = self.mapping_array.genarate_sack(self.get_rwnd()).unwrap();
+        let mut chunk = self.mapping_array.genarate_sack(self.get_rwnd()).unwrap();
+        if let SctpChunk::Sack(sack) = &mut chunk {
+            if self.recovery.is_cmt_enabled() {
+                sack.dac_pkts = cmp::min(self.dac_pkts_seen, 2);
+            }
+        }
         let mut pathid = self
             .last_data_from
-            .unwrap_or(self.get_primary_path().unwrap());
+            .unwrap_or(self.get_active_path().unwrap());
         if let Ok((path_confirmed, path_state)) = self.recovery.get_path_state(pathid) {
-            // TODO: We should find alternate path
-            if !path_confirmed || path_state == SctpPathState::InActive {
-                pathid = self.get_primary_path().unwrap();
+            if !path_confirmed || path_state != SctpPathState::Active {
+                pathid = self.get_active_path().unwrap();
             }
         } else {
             // Invalid pathid
-            pathid = self.get_primary_path().unwrap();
+            pathid = self.get_active_path().unwrap();
         }
         self.control_waiting_trans
             .insert(self.next_control_sequence.0, (chunk, pathid));
         self.next_control_sequence += 1;
+        if let Some(ecne_tsn) = self.ecne_tsn {
+            self.control_waiting_trans.insert(
+                self.next_control_sequence.0,
+                (SctpChunk::Ecne(ecne_tsn.0), pathid),
+            );
+            self.next_control_sequence += 1;
+        }
         self.num_data_pkts_seen = 0;
+        self.dac_pkts_seen = 0;
         self.send_sack = false;
         self.delayed_ack_timeout = None;
     }
 
-    fn abort(&mut self, sbuf: &mut Vec<u8>, error_cause: Option<SctpErrorCause>) -> usize {
+    fn write_abort(&mut self, sbuf: &mut Vec<u8>, reason: SctpCloseReason) -> usize {
+        let old_len = sbuf.len();
+        let error_cause = match &reason {
+            SctpCloseReason::LocalAbort(cause) => cause.clone(),
+            SctpCloseReason::ProtocolViolation(cause) => Some(cause.clone()),
+            _ => None,
+        };
+        let header = SctpCommonHeader {
+            src_port: self.src_port,
+            dst_port: self.dst_port,
+            vtag: self.peer_vtag,
+            checksum: 0,
+        };
+        let abort = SctpChunk::Abort(SctpAbortChunk {
+            t_bit: false,
+            error_cause: error_cause,
+        });
+        header.to_bytes(sbuf).unwrap();
+        abort.to_bytes(sbuf).unwrap();
+        SctpAssociation::set_checksum(sbuf);
+        self.set_closed(reason);
+
+        sbuf.len() - old_len
+    }
+
+    fn send_shutdown_complete(&mut self, sbuf: &mut Vec<u8>) -> usize {
+        let old_len = sbuf.len();
+        let header = SctpCommonHeader {
+            src_port: self.src_port,
+            dst_port: self.dst_port,
+            vtag: self.peer_vtag,
+            checksum: 0,
+        };
+        // Send Shutdown-Complete
+        let shutdown_complete = SctpChunk::ShutdownComplete(false);
+        trace!("{} send SHUTDONW-COMPLETION", self.trace_id);
+        header.to_bytes(sbuf).unwrap();
+        shutdown_complete.to_bytes(sbuf).unwrap();
+        SctpAssociation::set_checksum(sbuf);
+        self.set_closed(SctpCloseReason::Shutdown);
+
+        sbuf.len() - old_len
+    }
+
+    // The ABORT carries the Initiate Tag of the INIT (RFC 9260 Section 8.5.1).
+    fn send_init_abort(&self, sbuf: &mut Vec<u8>, init_tag: u32) -> usize {
         let old_len = sbuf.len();
         let header = SctpCommonHeader {
             src_port: self.src_port,
             dst_port: self.dst_port,
-            vtag: self.peer_vtag,
+            vtag: init_tag,
             checksum: 0,
         };
         let abort = SctpChunk::Abort(SctpAbortChunk {
             t_bit: false,
-            error_cause: error_cause,
+            error_cause: None,
         });
+        trace!("{} send ABORT for INIT", self.trace_id);
         header.to_bytes(sbuf).unwrap();
         abort.to_bytes(sbuf).unwrap();
-        self.state = SctpAssociationState::Closed;
+        SctpAssociation::set_checksum(sbuf);
 
         sbuf.len() - old_len
     }
 
-    fn send_shutdown_complete(&mut self, sbuf: &mut Vec<u8>) -> usize {
+    fn send_ootb_shutdown_complete(&mut self, sbuf: &mut Vec<u8>) -> usize {
         let old_len = sbuf.len();
         let header = SctpCommonHeader {
             src_port: self.src_port,
             dst_port: self.dst_port,
-            vtag: self.peer_vtag,
+            vtag: self.my_vtag,
             checksum: 0,
         };
-        // Send Shutdown-Complete
-        let shutdown_complete = SctpChunk::ShutdownComplete(false);
-        trace!("{} send SHUTDONW-COMPLETION", self.trace_id);
+        let shutdown_complete = SctpChunk::ShutdownComplete(true);
+        trace!("{} send SHUTDONW-COMPLETION with T bit", self.trace_id);
         header.to_bytes(sbuf).unwrap();
         shutdown_complete.to_bytes(sbuf).unwrap();
-        self.state = SctpAssociationState::Closed;
+        SctpAssociation::set_checksum(sbuf);
 
         sbuf.len() - old_len
     }
@@ -1366,17 +3045,12 @@ impl SctpAssociation {
     pub fn close(&mut self) -> Result<()> {
         match self.state {
             SctpAssociationState::CookieWait | SctpAssociationState::CookieEchoed => {
-                Err(SctpError::InvalidState)
+                Err(SctpError::InvalidState(self.state))
             }
             SctpAssociationState::Established => {
-                if self
-                    .recovery
-                    .on_enter_shutdown(self.mapping_array.cummulative_tsn.0)
-                {
-                    self.state = SctpAssociationState::ShutdownSent;
-                } else {
-                    self.state = SctpAssociationState::ShutdownPending;
-                }
+                self.recovery.on_enter_shutdown();
+                self.state = SctpAssociationState::ShutdownPending;
+                self.check_shutdown_ready();
                 Ok(())
             }
             SctpAssociationState::Closed
@@ -1387,6 +3061,54 @@ impl SctpAssociation {
         }
     }
 
+    // Aborts the association. ABORT is written by the next send() unless the peer's tag is
+    // still unknown.
+    pub fn abort(&mut self, reason: &[u8]) -> Result<()> {
+        let cause = SctpErrorCause::UserInitiatedAbort(Vec::from(reason));
+        match self.state {
+            SctpAssociationState::Closed => Err(SctpError::InvalidState(self.state)),
+            SctpAssociationState::CookieWait => {
+                self.set_closed(SctpCloseReason::LocalAbort(Some(cause)));
+                Ok(())
+            }
+            _ => {
+                self.abort_cause = Some(cause);
+                self.send_abort = true;
+                Ok(())
+            }
+        }
+    }
+
+    fn set_closed(&mut self, reason: SctpCloseReason) {
+        trace!("{} association closed reason={:?}", self.trace_id, reason);
+        self.state = SctpAssociationState::Closed;
+        if self.close_reason.is_none() {
+            self.close_reason = Some(reason);
+        }
+    }
+
+    pub fn get_close_reason(&self) -> Option<&SctpCloseReason> {
+        self.close_reason.as_ref()
+    }
+
+    fn check_shutdown_ready(&mut self) {
+        if self.get_pending().len() > 0 || !self.recovery.is_all_data_acked() {
+            return;
+        }
+        match self.state {
+            SctpAssociationState::ShutdownPending => {
+                self.recovery
+                    .send_shutdown(self.mapping_array.cummulative_tsn.0);
+                self.state = SctpAssociationState::ShutdownSent;
+            }
+            SctpAssociationState::ShutdownReceived => {
+                self.recovery.send_shutdown_ack();
+                self.state = SctpAssociationState::ShutdownAckSent;
+            }
+            _ => {}
+        }
+    }
+
     fn get_rwnd(&self) -> u32 {
         let len: usize = self.stream_in.iter().map(|v| v.len()).sum();
         let rwnd: u32 = if self.a_rwnd > len as u32 {
@@ -1405,3 +3127,1905 @@ impl SctpAssociation {
         return self.state == SctpAssociationState::Closed;
     }
 }
+
+#[cfg(test)]
+fn test_deliver(assoc: &mut SctpAssociation, from: &IpAddr, pkt: &[u8]) -> Result<Vec<u8>> {
+    let mut sbuf = Vec::new();
+    let (_, mut off) = SctpCommonHeader::from_bytes(pkt)?;
+    while off < pkt.len() && !assoc.is_closed() {
+        off += assoc.recv(from, &pkt[off..], &mut sbuf)?;
+    }
+    Ok(sbuf)
+}
+
+#[cfg(test)]
+fn test_transfer(
+    sender: &mut SctpAssociation,
+    sender_ip: &IpAddr,
+    receiver: &mut SctpAssociation,
+) -> usize {
+    let mut num_pkts = 0;
+    loop {
+        let mut sbuf = Vec::new();
+        match sender.send(&mut sbuf) {
+            Ok(_) => {
+                test_deliver(receiver, sender_ip, &sbuf).unwrap();
+                num_pkts += 1;
+            }
+            Err(_) => {
+                break;
+            }
+        }
+    }
+    num_pkts
+}
+
+#[cfg(test)]
+fn test_establish() -> (SctpAssociation, IpAddr, SctpAssociation, IpAddr) {
+    let client_ip: IpAddr = "192.0.2.1".parse().unwrap();
+    let server_ip: IpAddr = "192.0.2.2".parse().unwrap();
+    let secret_key = [0u8; 32];
+
+    let mut client = SctpAssociation::connect(5001, 5002, &vec![client_ip], &server_ip).unwrap();
+
+    // INIT and INIT-ACK
+    let mut sbuf = Vec::new();
+    client.send(&mut sbuf).unwrap();
+    let (header, consumed) = SctpCommonHeader::from_bytes(&sbuf).unwrap();
+    let mut rbuf = Vec::new();
+    let (server, _) = SctpAssociation::accept(
+        &client_ip,
+        &header,
+        &sbuf[consumed..],
+        &mut rbuf,
+        &secret_key,
+    )
+    .unwrap();
+    assert!(server.is_none());
+    test_deliver(&mut client, &server_ip, &rbuf).unwrap();
+
+    // COOKIE-ECHO and COOKIE-ACK
+    let mut sbuf = Vec::new();
+    client.send(&mut sbuf).unwrap();
+    let (header, consumed) = SctpCommonHeader::from_bytes(&sbuf).unwrap();
+    let mut rbuf = Vec::new();
+    let (server, _) = SctpAssociation::accept(
+        &client_ip,
+        &header,
+        &sbuf[consumed..],
+        &mut rbuf,
+        &secret_key,
+    )
+    .unwrap();
+    let mut server = server.unwrap();
+    test_transfer(&mut server, &server_ip, &mut client);
+    assert!(client.is_established());
+    assert!(server.is_established());
+
+    (client, client_ip, server, server_ip)
+}
+
+#[test]
+fn test_association_establish_and_transfer() {
+    let (mut client, client_ip, mut server, server_ip) = test_establish();
+    let mut rbuf = Vec::new();
+
+    client
+        .write_into_stream(0, &[1u8; 100], false, true)
+        .unwrap();
+    assert!(test_transfer(&mut client, &client_ip, &mut server) > 0);
+    assert_eq!(server.read_from_stream(0, &mut rbuf), Ok(100));
+    test_transfer(&mut server, &server_ip, &mut client);
+    assert!(client.recovery.is_all_data_acked());
+}
+
+#[test]
+fn test_association_graceful_shutdown() {
+    let (mut client, client_ip, mut server, server_ip) = test_establish();
+    let mut rbuf = Vec::new();
+
+    client
+        .write_into_stream(0, &[1u8; 100], false, true)
+        .unwrap();
+    client.close().unwrap();
+    assert_eq!(client.state, SctpAssociationState::ShutdownPending);
+    assert_eq!(
+        client.write_into_stream(0, &[1u8; 100], false, true),
+        Err(SctpError::InvalidState(
+            SctpAssociationState::ShutdownPending
+        ))
+    );
+
+    // Queued DATA is still sent before SHUTDOWN.
+    test_transfer(&mut client, &client_ip, &mut server);
+    assert_eq!(server.read_from_stream(0, &mut rbuf), Ok(100));
+    assert_eq!(client.state, SctpAssociationState::ShutdownPending);
+
+    // SACK, then SHUTDOWN, then SHUTDOWN-ACK.
+    test_transfer(&mut server, &server_ip, &mut client);
+    assert_eq!(client.state, SctpAssociationState::ShutdownSent);
+    test_transfer(&mut client, &client_ip, &mut server);
+    assert_eq!(server.state, SctpAssociationState::ShutdownAckSent);
+    assert_eq!(
+        server.write_into_stream(0, &[1u8; 100], false, true),
+        Err(SctpError::InvalidState(
+            SctpAssociationState::ShutdownAckSent
+        ))
+    );
+
+    let mut sbuf = Vec::new();
+    server.send(&mut sbuf).unwrap();
+    let shutdown_complete = test_deliver(&mut client, &server_ip, &sbuf).unwrap();
+    assert!(client.is_closed());
+    test_deliver(&mut server, &client_ip, &shutdown_complete).unwrap();
+    assert!(server.is_closed());
+    assert_eq!(client.get_close_reason(), Some(&SctpCloseReason::Shutdown));
+    assert_eq!(server.get_close_reason(), Some(&SctpCloseReason::Shutdown));
+}
+
+#[test]
+fn test_association_shutdown_with_outstanding_data() {
+    let (mut client, client_ip, mut server, server_ip) = test_establish();
+
+    // The server has DATA in flight when the client's SHUTDOWN arrives.
+    server
+        .write_into_stream(0, &[1u8; 100], false, true)
+        .unwrap();
+    let mut data = Vec::new();
+    server.send(&mut data).unwrap();
+    client.close().unwrap();
+    assert_eq!(client.state, SctpAssociationState::ShutdownSent);
+    test_transfer(&mut client, &client_ip, &mut server);
+    assert_eq!(server.state, SctpAssociationState::ShutdownReceived);
+
+    // The DATA is acknowledged by a SHUTDOWN carrying the new Cumulative TSN Ack.
+    test_deliver(&mut client, &server_ip, &data).unwrap();
+    test_transfer(&mut client, &client_ip, &mut server);
+    assert!(server.recovery.is_all_data_acked());
+    assert_eq!(server.state, SctpAssociationState::ShutdownAckSent);
+}
+
+#[test]
+fn test_association_shutdown_retransmission() {
+    let (mut client, _, _, _) = test_establish();
+    client.close().unwrap();
+    let mut sbuf = Vec::new();
+    client.send(&mut sbuf).unwrap();
+    let cum_tsn = client.mapping_array.cummulative_tsn.0;
+    assert_eq!(test_chunk_types(&sbuf), vec![SctpChunkType::Shutdown]);
+
+    // DATA accepted after the first SHUTDOWN went out
+    client
+        .mapping_array
+        .update(cum_tsn.wrapping_add(1))
+        .unwrap();
+    client.on_timeout_at(Instant::now() + Duration::from_secs(60));
+    let mut sbuf = Vec::new();
+    client.send(&mut sbuf).unwrap();
+    let (_, mut off) = SctpCommonHeader::from_bytes(&sbuf).unwrap();
+    let mut shutdown = None;
+    while off < sbuf.len() {
+        let (chunk, consumed) = SctpChunk::from_bytes(&sbuf[off..]).unwrap();
+        if let SctpChunk::Shutdown(cum_tsn) = chunk {
+            shutdown = Some(cum_tsn);
+        }
+        off += consumed;
+    }
+    assert_eq!(shutdown, Some(cum_tsn.wrapping_add(1)));
+}
+
+#[test]
+fn test_association_shutdown_collision() {
+    let (mut client, client_ip, mut server, server_ip) = test_establish();
+
+    client.close().unwrap();
+    server.close().unwrap();
+    let mut client_shutdown = Vec::new();
+    client.send(&mut client_shutdown).unwrap();
+    let mut server_shutdown = Vec::new();
+    server.send(&mut server_shutdown).unwrap();
+    test_deliver(&mut client, &server_ip, &server_shutdown).unwrap();
+    test_deliver(&mut server, &client_ip, &client_shutdown).unwrap();
+    assert_eq!(client.state, SctpAssociationState::ShutdownAckSent);
+    assert_eq!(server.state, SctpAssociationState::ShutdownAckSent);
+
+    let mut client_shutdown_ack = Vec::new();
+    client.send(&mut client_shutdown_ack).unwrap();
+    let mut server_shutdown_ack = Vec::new();
+    server.send(&mut server_shutdown_ack).unwrap();
+    test_deliver(&mut client, &server_ip, &server_shutdown_ack).unwrap();
+    test_deliver(&mut server, &client_ip, &client_shutdown_ack).unwrap();
+    assert!(client.is_closed());
+    assert!(server.is_closed());
+}
+
+#[cfg(test)]
+fn test_packet(assoc: &SctpAssociation, chunks: Vec<SctpChunk>) -> Vec<u8> {
+    let mut pkt = Vec::new();
+    let header = SctpCommonHeader {
+        src_port: assoc.dst_port,
+        dst_port: assoc.src_port,
+        vtag: assoc.my_vtag,
+        checksum: 0,
+    };
+    header.to_bytes(&mut pkt).unwrap();
+    for chunk in chunks {
+        chunk.to_bytes(&mut pkt).unwrap();
+    }
+    pkt
+}
+
+#[cfg(test)]
+fn test_auth_packet(assoc: &SctpAssociation, mut chunks: Vec<SctpChunk>) -> Vec<u8> {
+    chunks.insert(0, SctpAssociation::auth_chunk());
+    let mut pkt = test_packet(assoc, chunks);
+    let hmac = SctpAssociation::compute_auth_hmac(assoc.auth_key.as_ref().unwrap(), &pkt[12..]);
+    pkt[20..20 + AUTH_HMAC_LEN].copy_from_slice(hmac.code());
+    pkt
+}
+
+#[test]
+fn test_association_discard_unexpected_chunks() {
+    let (mut client, _, _, server_ip) = test_establish();
+
+    let init_ack = SctpChunk::InitAck(SctpInitChunk {
+        init_tag: 1,
+        a_rwnd: 65536,
+        num_out_strm: 1,
+        num_in_strm: 1,
+        init_tsn: 1,
+        params: Vec::new(),
+    });
+    let pkt = test_packet(
+        &client,
+        vec![
+            init_ack,
+            SctpChunk::CookieAck,
+            SctpChunk::ShutdownAck,
+            SctpChunk::ShutdownComplete(false),
+        ],
+    );
+    assert_eq!(test_deliver(&mut client, &server_ip, &pkt), Ok(Vec::new()));
+    assert!(client.is_established());
+}
+
+#[test]
+fn test_association_init_in_established() {
+    let (_, client_ip, mut server, _) = test_establish();
+
+    // An INIT from the peer, as after a restart, is answered with an ABORT
+    // carrying its Initiate Tag.
+    let init = SctpChunk::Init(SctpInitChunk {
+        init_tag: 5678,
+        a_rwnd: 65536,
+        num_out_strm: 1,
+        num_in_strm: 1,
+        init_tsn: 1,
+        params: Vec::new(),
+    });
+    let pkt = test_packet(&server, vec![init]);
+    let sbuf = test_deliver(&mut server, &client_ip, &pkt).unwrap();
+    let (header, consumed) = SctpCommonHeader::from_bytes(&sbuf).unwrap();
+    assert_eq!(header.vtag, 5678);
+    match SctpChunk::from_bytes(&sbuf[consumed..]) {
+        Ok((SctpChunk::Abort(abort), _)) => assert!(!abort.t_bit),
+        v => panic!("unexpected chunk {:?}", v),
+    }
+    assert!(server.is_established());
+}
+
+#[test]
+fn test_association_discard_chunks_before_established() {
+    let client_ip: IpAddr = "192.0.2.1".parse().unwrap();
+    let server_ip: IpAddr = "192.0.2.2".parse().unwrap();
+    let mut client = SctpAssociation::connect(5001, 5002, &vec![client_ip], &server_ip).unwrap();
+
+    let sack = SctpChunk::Sack(SctpSackChunk {
+        cum_ack: 0,
+        a_rwnd: 65536,
+        num_gap_ack: 0,
+        num_dup_ack: 0,
+        gap_acks: Vec::new(),
+        dup_acks: Vec::new(),
+        dac_pkts: 0,
+    });
+    let data = SctpChunk::Data(SctpDataChunk {
+        i_bit: false,
+        u_bit: false,
+        b_bit: true,
+        e_bit: true,
+        tsn: 0,
+        stream_id: 0,
+        stream_seq: 0,
+        proto_id: 0,
+        data: vec![1u8],
+    });
+    let pkt = test_packet(&client, vec![sack, SctpChunk::CookieAck, data]);
+    assert_eq!(test_deliver(&mut client, &server_ip, &pkt), Ok(Vec::new()));
+    assert_eq!(client.state, SctpAssociationState::CookieWait);
+
+    // SHUTDOWN-ACK is answered as an OOTB packet.
+    let pkt = test_packet(&client, vec![SctpChunk::ShutdownAck]);
+    let mut sbuf = Vec::new();
+    assert_eq!(
+        client.recv(&server_ip, &pkt[12..], &mut sbuf),
+        Err(SctpError::OOTB)
+    );
+    assert_eq!(
+        SctpChunk::from_bytes(&sbuf[12..]),
+        Ok((SctpChunk::ShutdownComplete(true), 4))
+    );
+    assert_eq!(client.state, SctpAssociationState::CookieWait);
+}
+
+#[test]
+fn test_association_abort_on_invalid_chunks() {
+    let (mut client, client_ip, mut server, server_ip) = test_establish();
+    let server_largest_tsn = client.mapping_array.cummulative_tsn.0;
+
+    let data = SctpChunk::Data(SctpDataChunk {
+        i_bit: false,
+        u_bit: false,
+        b_bit: true,
+        e_bit: true,
+        tsn: client.mapping_array.cummulative_tsn.0 + 1,
+        stream_id: 0,
+        stream_seq: 0,
+        proto_id: 0,
+        data: Vec::new(),
+    });
+    let pkt = test_packet(&client, vec![data]);
+    assert_eq!(
+        test_deliver(&mut client, &server_ip, &pkt),
+        Err(SctpError::ProtocolViolation)
+    );
+    assert!(client.is_closed());
+
+    let sack = SctpChunk::Sack(SctpSackChunk {
+        cum_ack: server_largest_tsn + 1,
+        a_rwnd: 65536,
+        num_gap_ack: 0,
+        num_dup_ack: 0,
+        gap_acks: Vec::new(),
+        dup_acks: Vec::new(),
+        dac_pkts: 0,
+    });
+    let pkt = test_packet(&server, vec![sack]);
+    assert_eq!(
+        test_deliver(&mut server, &client_ip, &pkt),
+        Err(SctpError::ProtocolViolation)
+    );
+    assert!(server.is_closed());
+    match server.get_close_reason() {
+        Some(SctpCloseReason::ProtocolViolation(SctpErrorCause::ProtocolViolation(_))) => {}
+        v => panic!("unexpected close reason {:?}", v),
+    }
+}
+
+#[test]
+fn test_association_close_reasons() {
+    // The peer is told why the association was aborted.
+    let (mut client, client_ip, mut server, _) = test_establish();
+    client.abort(b"bye").unwrap();
+    assert!(!client.is_closed());
+    test_transfer(&mut client, &client_ip, &mut server);
+    assert_eq!(
+        client.get_close_reason(),
+        Some(&SctpCloseReason::LocalAbort(Some(
+            SctpErrorCause::UserInitiatedAbort(Vec::from("bye"))
+        )))
+    );
+    assert_eq!(
+        server.get_close_reason(),
+        Some(&SctpCloseReason::PeerAbort(Some(
+            SctpErrorCause::UserInitiatedAbort(Vec::from("bye"))
+        )))
+    );
+    assert_eq!(
+        client.abort(b""),
+        Err(SctpError::InvalidState(SctpAssociationState::Closed))
+    );
+
+    // INIT is never answered.
+    let client_ip: IpAddr = "192.0.2.1".parse().unwrap();
+    let server_ip: IpAddr = "192.0.2.2".parse().unwrap();
+    let mut client = SctpAssociation::connect(5001, 5002, &vec![client_ip], &server_ip).unwrap();
+    for i in 0..10 {
+        while client.send(&mut Vec::new()).is_ok() {}
+        client
+            .recovery
+            .on_timeout(Instant::now() + Duration::from_secs(3600 * (i + 1)));
+    }
+    client.on_timeout();
+    assert_eq!(
+        client.get_close_reason(),
+        Some(&SctpCloseReason::HandshakeTimeout)
+    );
+
+    // DATA is never acknowledged.
+    let (mut client, _, _, _) = test_establish();
+    client
+        .write_into_stream(0, &[1u8; 100], false, true)
+        .unwrap();
+    for i in 0..12 {
+        while client.send(&mut Vec::new()).is_ok() {}
+        client
+            .recovery
+            .on_timeout(Instant::now() + Duration::from_secs(3600 * (i + 1)));
+    }
+    client.on_timeout();
+    assert_eq!(
+        client.get_close_reason(),
+        Some(&SctpCloseReason::RetransmissionLimit)
+    );
+}
+
+#[test]
+fn test_association_error_context() {
+    fn close(assoc: &mut SctpAssociation) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        assoc.close()?;
+        Ok(())
+    }
+
+    let (mut client, _, _, server_ip) = test_establish();
+
+    let mut pkt = test_packet(&client, vec![SctpChunk::ShutdownAck]);
+    pkt.extend_from_slice(&[3, 0, 0, 2]);
+    let ret = client.recv(&server_ip, &pkt[12..], &mut Vec::new());
+    assert_eq!(
+        ret,
+        Err(SctpError::InvalidChunk(SctpParseError {
+            chunk_type: Some(SctpChunkType::Sack),
+            param_type: None,
+            offset: 4,
+        }))
+    );
+    assert_eq!(ret.unwrap_err().to_c(), -3);
+    assert_eq!(
+        ret.unwrap_err().to_string(),
+        "invalid chunk type=Sack at offset 4"
+    );
+
+    let mut assoc = SctpAssociation::connect(5001, 5002, &vec![server_ip], &server_ip).unwrap();
+    let err = close(&mut assoc).unwrap_err();
+    assert_eq!(err.to_string(), "invalid association state CookieWait");
+}
+
+#[cfg(test)]
+fn test_transfer_with_loss(
+    sender: &mut SctpAssociation,
+    sender_ip: &IpAddr,
+    receiver: &mut SctpAssociation,
+    receiver_ip: &IpAddr,
+    lost: Option<IpAddr>,
+) -> Vec<IpAddr> {
+    let mut dsts = Vec::new();
+    loop {
+        let mut sbuf = Vec::new();
+        match sender.send(&mut sbuf) {
+            Ok((_, dst)) => {
+                if Some(dst) != lost {
+                    test_deliver(receiver, sender_ip, &sbuf).unwrap();
+                }
+                dsts.push(dst);
+            }
+            Err(_) => {
+                break;
+            }
+        }
+    }
+    test_transfer(receiver, receiver_ip, sender);
+    dsts
+}
+
+#[cfg(test)]
+fn test_establish_multihomed() -> (SctpAssociation, IpAddr, SctpAssociation, IpAddr, IpAddr) {
+    let (mut client, client_ip, mut server, server_ip) = test_establish();
+    let server_ip2: IpAddr = "192.0.2.4".parse().unwrap();
+    let pathid = client.add_raddr(&server_ip2).unwrap();
+
+    // The new address is confirmed by a HEARTBEAT.
+    client.on_timeout();
+    test_transfer(&mut client, &client_ip, &mut server);
+    test_transfer(&mut server, &server_ip, &mut client);
+    assert_eq!(
+        client.recovery.get_path_state(pathid),
+        Ok((true, SctpPathState::Active))
+    );
+    assert_eq!(
+        client.poll_event(),
+        Some(SctpEvent::PathConfirmed { pathid })
+    );
+    (client, client_ip, server, server_ip, server_ip2)
+}
+
+#[test]
+fn test_association_primary_path_failover() {
+    for policy in [SctpSwitchBackPolicy::Immediate, SctpSwitchBackPolicy::Never].iter() {
+        let (mut client, client_ip, mut server, server_ip, server_ip2) =
+            test_establish_multihomed();
+        client.set_switch_back_policy(*policy);
+        assert_eq!(client.get_active_path(), Some(0));
+
+        // Everything sent to the primary address is lost.
+        client
+            .write_into_stream(0, &[1u8; 100], false, true)
+            .unwrap();
+        for i in 0..5 {
+            test_transfer_with_loss(
+                &mut client,
+                &client_ip,
+                &mut server,
+                &server_ip,
+                Some(server_ip),
+            );
+            client
+                .recovery
+                .on_timeout(Instant::now() + Duration::from_secs(3600 * (i + 1)));
+            client.on_timeout();
+        }
+        assert_eq!(
+            client.recovery.get_path_state(0),
+            Ok((true, SctpPathState::InActive))
+        );
+        assert_eq!(
+            client.poll_event(),
+            Some(SctpEvent::PathFailover { from: 0, to: 1 })
+        );
+        assert_eq!(client.poll_event(), None);
+        assert_eq!(client.get_primary_path(), Some(0));
+        assert_eq!(client.get_active_path(), Some(1));
+
+        // New data goes to the alternate address.
+        client
+            .write_into_stream(0, &[2u8; 100], false, true)
+            .unwrap();
+        let dsts = test_transfer_with_loss(
+            &mut client,
+            &client_ip,
+            &mut server,
+            &server_ip,
+            Some(server_ip),
+        );
+        assert!(dsts.contains(&server_ip2));
+        assert!(!client.is_closed());
+
+        // A HEARTBEAT to the primary address is answered again.
+        client
+            .recovery
+            .on_timeout(Instant::now() + Duration::from_secs(3600 * 10));
+        client.on_timeout();
+        test_transfer_with_loss(&mut client, &client_ip, &mut server, &server_ip, None);
+        assert_eq!(
+            client.recovery.get_path_state(0),
+            Ok((true, SctpPathState::Active))
+        );
+        if *policy == SctpSwitchBackPolicy::Immediate {
+            assert_eq!(
+                client.poll_event(),
+                Some(SctpEvent::PathSwitchBack { from: 1, to: 0 })
+            );
+            assert_eq!(client.get_active_path(), Some(0));
+        } else {
+            assert_eq!(client.poll_event(), None);
+            assert_eq!(client.get_active_path(), Some(1));
+        }
+    }
+}
+
+#[test]
+fn test_association_retransmit_to_alternate_path() {
+    let (mut client, client_ip, mut server, server_ip, server_ip2) = test_establish_multihomed();
+    let mut rbuf = Vec::new();
+
+    client
+        .write_into_stream(0, &[1u8; 100], false, true)
+        .unwrap();
+    let dsts = test_transfer_with_loss(
+        &mut client,
+        &client_ip,
+        &mut server,
+        &server_ip,
+        Some(server_ip),
+    );
+    assert_eq!(dsts, vec![server_ip]);
+
+    // The timed out DATA is retransmitted to the other address.
+    client
+        .recovery
+        .on_timeout(Instant::now() + Duration::from_secs(3600));
+    let dsts = test_transfer_with_loss(
+        &mut client,
+        &client_ip,
+        &mut server,
+        &server_ip,
+        Some(server_ip),
+    );
+    assert_eq!(dsts[0], server_ip2);
+    assert_eq!(server.read_from_stream(0, &mut rbuf), Ok(100));
+    assert!(client.recovery.is_all_data_acked());
+}
+
+#[test]
+fn test_association_potentially_failed_path() {
+    for (pf_max_retrans, switchover) in [(0, false), (0, true), (5, false)].iter() {
+        let (mut client, client_ip, mut server, server_ip, _) = test_establish_multihomed();
+        client.set_pf_max_retrans(*pf_max_retrans);
+        client.set_primary_switchover(*switchover);
+
+        client
+            .write_into_stream(0, &[1u8; 100], false, true)
+            .unwrap();
+        test_transfer_with_loss(
+            &mut client,
+            &client_ip,
+            &mut server,
+            &server_ip,
+            Some(server_ip),
+        );
+        client
+            .recovery
+            .on_timeout(Instant::now() + Duration::from_secs(3600));
+        client.on_timeout();
+        test_transfer_with_loss(
+            &mut client,
+            &client_ip,
+            &mut server,
+            &server_ip,
+            Some(server_ip),
+        );
+        if *pf_max_retrans == 5 {
+            // Disabled, the primary path stays active after a single timeout.
+            assert_eq!(
+                client.recovery.get_path_state(0),
+                Ok((true, SctpPathState::Active))
+            );
+            assert_eq!(client.poll_event(), None);
+            continue;
+        }
+
+        // No DATA is sent to a potentially failed path.
+        assert_eq!(
+            client.recovery.get_path_state(0),
+            Ok((true, SctpPathState::PotentiallyFailed))
+        );
+        if *switchover {
+            assert_eq!(
+                client.poll_event(),
+                Some(SctpEvent::PrimaryPathSwitchover { from: 0, to: 1 })
+            );
+            assert_eq!(client.get_primary_path(), Some(1));
+        } else {
+            assert_eq!(
+                client.poll_event(),
+                Some(SctpEvent::PathFailover { from: 0, to: 1 })
+            );
+            assert_eq!(client.get_primary_path(), Some(0));
+        }
+        assert_eq!(client.get_active_path(), Some(1));
+
+        // The first HEARTBEAT-ACK makes it active again.
+        client
+            .recovery
+            .on_timeout(Instant::now() + Duration::from_secs(3600 * 2));
+        client.on_timeout();
+        test_transfer_with_loss(&mut client, &client_ip, &mut server, &server_ip, None);
+        assert_eq!(
+            client.recovery.get_path_state(0),
+            Ok((true, SctpPathState::Active))
+        );
+        if *switchover {
+            assert_eq!(client.poll_event(), None);
+            assert_eq!(client.get_active_path(), Some(1));
+        } else {
+            assert_eq!(
+                client.poll_event(),
+                Some(SctpEvent::PathSwitchBack { from: 1, to: 0 })
+            );
+            assert_eq!(client.get_active_path(), Some(0));
+        }
+    }
+}
+
+#[test]
+fn test_association_pmtu_discovery() {
+    let (mut client, _, mut server, server_ip) = test_establish();
+    let client_ip: IpAddr = "192.0.2.1".parse().unwrap();
+    let now = Instant::now();
+    assert_eq!(client.get_pmtu(0), Ok(1200));
+
+    // Packets larger than 1400 bytes are dropped, 28 bytes for IPv4 and UDP headers.
+    let mut link_mtu = 1400;
+    for i in 1..30 {
+        client
+            .recovery
+            .on_timeout(now + Duration::from_secs(10 * i));
+        let mut sbuf = Vec::new();
+        while client.send(&mut sbuf).is_ok() {
+            if sbuf.len() + 28 <= link_mtu {
+                test_deliver(&mut server, &client_ip, &sbuf).unwrap();
+            }
+            sbuf.clear();
+        }
+        test_transfer(&mut server, &server_ip, &mut client);
+    }
+    assert_eq!(client.get_pmtu(0), Ok(1400));
+    assert_eq!(client.recovery.get_path_mtu(0), Ok(1400 - 20 - 8 - 12));
+    assert!(!client.is_closed());
+
+    // Full-size DATA is lost after the path MTU shrinks.
+    link_mtu = 1300;
+    client
+        .write_into_stream(0, &[1u8; 3000], false, true)
+        .unwrap();
+    for i in 1..4 {
+        let mut sbuf = Vec::new();
+        while client.send(&mut sbuf).is_ok() {
+            if sbuf.len() + 28 <= link_mtu {
+                test_deliver(&mut server, &client_ip, &sbuf).unwrap();
+            }
+            sbuf.clear();
+        }
+        test_transfer(&mut server, &server_ip, &mut client);
+        client
+            .recovery
+            .on_timeout(now + Duration::from_secs(3600 * i));
+    }
+    assert_eq!(client.get_pmtu(0), Ok(1200));
+    assert!(!client.is_closed());
+}
+
+#[test]
+fn test_association_pmtu_black_hole_bundled() {
+    let (mut client, _, mut server, server_ip) = test_establish();
+    let client_ip: IpAddr = "192.0.2.1".parse().unwrap();
+    let now = Instant::now();
+    let mut link_mtu = 1400;
+    for i in 1..30 {
+        client
+            .recovery
+            .on_timeout(now + Duration::from_secs(10 * i));
+        let mut sbuf = Vec::new();
+        while client.send(&mut sbuf).is_ok() {
+            if sbuf.len() + 28 <= link_mtu {
+                test_deliver(&mut server, &client_ip, &sbuf).unwrap();
+            }
+            sbuf.clear();
+        }
+        test_transfer(&mut server, &server_ip, &mut client);
+    }
+    assert_eq!(client.get_pmtu(0), Ok(1400));
+
+    // Small messages bundled into full-size packets are lost.
+    link_mtu = 1240;
+    for _ in 0..60 {
+        client
+            .write_into_stream(0, &[1u8; 100], false, true)
+            .unwrap();
+    }
+    for i in 1..4 {
+        let mut sbuf = Vec::new();
+        while client.send(&mut sbuf).is_ok() {
+            if sbuf.len() + 28 <= link_mtu {
+                test_deliver(&mut server, &client_ip, &sbuf).unwrap();
+            }
+            sbuf.clear();
+        }
+        test_transfer(&mut server, &server_ip, &mut client);
+        client
+            .recovery
+            .on_timeout(now + Duration::from_secs(3600 * i));
+    }
+    assert_eq!(client.get_pmtu(0), Ok(1200));
+    assert!(!client.is_closed());
+}
+
+#[test]
+fn test_association_icmp() {
+    let (mut client, client_ip, mut server, server_ip, server_ip2) = test_establish_multihomed();
+    client
+        .write_into_stream(0, &[1u8; 100], false, true)
+        .unwrap();
+    let mut quoted = Vec::new();
+    client.send(&mut quoted).unwrap();
+    quoted.truncate(8);
+
+    // The quoted tag must be the peer's.
+    let mut forged = quoted.clone();
+    forged[7] ^= 0xff;
+    assert_eq!(
+        client.on_icmp_packet_too_big(&server_ip, 1280, &forged),
+        Err(SctpError::OOTB)
+    );
+    assert_eq!(
+        client.on_icmp_packet_too_big(&client_ip, 1280, &quoted),
+        Err(SctpError::InvalidPathId)
+    );
+    for i in 1..10 {
+        client
+            .recovery
+            .on_timeout(Instant::now() + Duration::from_secs(10 * i));
+        test_transfer(&mut client, &client_ip, &mut server);
+        test_transfer(&mut server, &server_ip, &mut client);
+    }
+    assert_eq!(client.get_pmtu(0), Ok(1500));
+
+    // DATA written afterwards fits the new path MTU.
+    assert_eq!(
+        client.on_icmp_packet_too_big(&server_ip, 1280, &quoted),
+        Ok(())
+    );
+    assert_eq!(client.get_pmtu(0), Ok(1280));
+    client
+        .write_into_stream(0, &[2u8; 3000], false, true)
+        .unwrap();
+    let mut sbuf = Vec::new();
+    while client.send(&mut sbuf).is_ok() {
+        assert!(sbuf.len() + 20 + 8 <= 1280);
+        test_deliver(&mut server, &client_ip, &sbuf).unwrap();
+        sbuf.clear();
+    }
+
+    // New data moves away from an unreachable address.
+    assert_eq!(client.on_icmp_unreachable(&server_ip, &quoted), Ok(()));
+    assert_eq!(
+        client.poll_event(),
+        Some(SctpEvent::PathFailover { from: 0, to: 1 })
+    );
+    client
+        .write_into_stream(0, &[3u8; 100], false, true)
+        .unwrap();
+    let mut sbuf = Vec::new();
+    assert_eq!(client.send(&mut sbuf).unwrap().1, server_ip2);
+
+    // An INIT has no tag, its Initiate Tag is checked instead.
+    let mut client = SctpAssociation::connect(5001, 5002, &vec![client_ip], &server_ip).unwrap();
+    let mut quoted = Vec::new();
+    client.send(&mut quoted).unwrap();
+    assert_eq!(
+        client.on_icmp_unreachable(&server_ip, &quoted[..8]),
+        Err(SctpError::OOTB)
+    );
+    assert_eq!(
+        client.on_icmp_unreachable(&server_ip, &quoted[..20]),
+        Ok(())
+    );
+}
+
+#[cfg(test)]
+fn test_poll_events(assoc: &mut SctpAssociation) -> Vec<SctpEvent> {
+    let mut events = Vec::new();
+    while let Some(event) = assoc.poll_event() {
+        events.push(event);
+    }
+    events
+}
+
+#[test]
+fn test_association_asconf() {
+    let (mut client, client_ip, mut server, server_ip) = test_establish();
+    let client_ip2: IpAddr = "192.0.2.3".parse().unwrap();
+    let client_ip3: IpAddr = "192.0.2.5".parse().unwrap();
+
+    // Add IP
+    assert_eq!(client.add_laddr(&client_ip2), Ok(()));
+    assert_eq!(client.add_laddr(&client_ip2), Err(SctpError::Done));
+    test_transfer(&mut client, &client_ip, &mut server);
+    assert!(
+        test_poll_events(&mut server).contains(&SctpEvent::PeerAddressAdded {
+            addr: client_ip2,
+            pathid: 1
+        })
+    );
+    test_transfer(&mut server, &server_ip, &mut client);
+    assert_eq!(
+        test_poll_events(&mut client),
+        vec![SctpEvent::AsconfSucceeded {
+            request: SctpParameter::AddIp(0, client_ip2)
+        }]
+    );
+
+    // Set Primary takes effect once the new address is confirmed.
+    assert_eq!(
+        client.request_peer_primary(&client_ip3),
+        Err(SctpError::InvalidAddress)
+    );
+    assert_eq!(client.request_peer_primary(&client_ip2), Ok(()));
+    test_transfer(&mut client, &client_ip, &mut server);
+    assert!(
+        test_poll_events(&mut server).contains(&SctpEvent::PeerSetPrimary {
+            addr: client_ip2,
+            pathid: 1
+        })
+    );
+    assert_eq!(server.get_primary_path(), Some(0));
+    server.on_timeout();
+    test_transfer(&mut server, &server_ip, &mut client);
+    test_transfer(&mut client, &client_ip2, &mut server);
+    assert_eq!(server.get_primary_path(), Some(1));
+    assert_eq!(server.get_active_path(), Some(1));
+
+    // Delete IP, which must not be the source address of the ASCONF.
+    assert_eq!(client.remove_laddr(&client_ip), Ok(()));
+    assert_eq!(
+        client.remove_laddr(&client_ip2),
+        Err(SctpError::InvalidAddress)
+    );
+    test_transfer(&mut client, &client_ip2, &mut server);
+    assert!(
+        test_poll_events(&mut server).contains(&SctpEvent::PeerAddressRemoved {
+            addr: client_ip,
+            pathid: 0
+        })
+    );
+    assert_eq!(server.get_pathid(&client_ip), None);
+    test_transfer(&mut server, &server_ip, &mut client);
+    test_poll_events(&mut client);
+
+    assert_eq!(client.add_laddr(&client_ip), Ok(()));
+    test_transfer(&mut client, &client_ip2, &mut server);
+    test_transfer(&mut server, &server_ip, &mut client);
+    assert_eq!(server.get_pathid(&client_ip), Some(0));
+    assert_eq!(client.remove_laddr(&client_ip2), Ok(()));
+    test_transfer(&mut client, &client_ip2, &mut server);
+    test_transfer(&mut server, &server_ip, &mut client);
+    let events = test_poll_events(&mut client);
+    match events.last() {
+        Some(SctpEvent::AsconfFailed {
+            request: SctpParameter::DeleteIp(_, addr),
+            cause: Some(SctpErrorCause::DeleteSourceAddr(_)),
+        }) => assert_eq!(*addr, client_ip2),
+        _ => panic!("unexpected events {:?}", events),
+    }
+    assert_eq!(client.remove_laddr(&client_ip2), Ok(()));
+    test_poll_events(&mut server);
+
+    // A lost ASCONF is retransmitted, and a duplicate is not processed again.
+    let mut sbuf = Vec::new();
+    client.send(&mut sbuf).unwrap();
+    client
+        .recovery
+        .on_timeout(Instant::now() + Duration::from_secs(3600));
+    let mut sbuf = Vec::new();
+    client.send(&mut sbuf).unwrap();
+    test_deliver(&mut server, &client_ip, &sbuf).unwrap();
+    test_deliver(&mut server, &client_ip, &sbuf).unwrap();
+    assert_eq!(
+        test_poll_events(&mut server),
+        vec![SctpEvent::PeerAddressRemoved {
+            addr: client_ip2,
+            pathid: 1
+        }]
+    );
+    test_transfer(&mut server, &server_ip, &mut client);
+    assert!(
+        test_poll_events(&mut client).contains(&SctpEvent::AsconfSucceeded {
+            request: SctpParameter::DeleteIp(5, client_ip2)
+        })
+    );
+
+    // An ASCONF-ACK for an ASCONF never sent
+    let pkt = test_auth_packet(
+        &client,
+        vec![SctpChunk::AsconfAck(SctpAsconfAckChunk {
+            serial: client.asconf_serial.0,
+            params: Vec::new(),
+        })],
+    );
+    assert_eq!(
+        test_deliver(&mut client, &server_ip, &pkt),
+        Err(SctpError::ProtocolViolation)
+    );
+    assert_eq!(
+        client.get_close_reason(),
+        Some(&SctpCloseReason::ProtocolViolation(
+            SctpErrorCause::IllegalAsconfAck
+        ))
+    );
+}
+
+#[test]
+fn test_association_auth() {
+    let (mut client, client_ip, mut server, server_ip) = test_establish();
+    assert!(client.auth_key.is_some());
+    assert_eq!(client.auth_key, server.auth_key);
+    assert!(client.asconf_supported);
+    assert!(server.asconf_supported);
+
+    // ASCONF is not used with a peer not supporting AUTH.
+    let params = vec![SctpAssociation::supported_extensions()];
+    assert_eq!(
+        SctpAssociation::generate_auth_key(&client.auth_params, &params),
+        None
+    );
+    assert!(!SctpAssociation::is_asconf_supported(&params));
+
+    // An ASCONF-ACK without AUTH or with a wrong HMAC is discarded.
+    let ack = SctpChunk::AsconfAck(SctpAsconfAckChunk {
+        serial: client.asconf_serial.0,
+        params: Vec::new(),
+    });
+    let pkt = test_packet(&client, vec![ack.clone()]);
+    assert_eq!(test_deliver(&mut client, &server_ip, &pkt), Ok(Vec::new()));
+    let mut pkt = test_auth_packet(&client, vec![ack]);
+    pkt[20] ^= 0xff;
+    assert_eq!(test_deliver(&mut client, &server_ip, &pkt), Ok(Vec::new()));
+    assert!(client.is_established());
+
+    // A packet from an unknown address is silently discarded.
+    let client_ip2: IpAddr = "192.0.2.3".parse().unwrap();
+    let pkt = test_packet(&server, vec![SctpChunk::Heartbeat(Vec::new())]);
+    let mut sbuf = Vec::new();
+    assert_eq!(
+        server.recv(&client_ip2, &pkt[12..], &mut sbuf),
+        Err(SctpError::OOTB)
+    );
+    assert!(sbuf.is_empty());
+    assert!(server.is_established());
+
+    // So is an ASCONF without AUTH from it.
+    let asconf = SctpChunk::Asconf(SctpAsconfChunk {
+        serial: server.peer_asconf_serial.0.wrapping_add(1),
+        addr: client_ip,
+        params: vec![SctpParameter::AddIp(0, client_ip2)],
+    });
+    let pkt = test_packet(&server, vec![asconf.clone()]);
+    assert_eq!(
+        server.recv(&client_ip2, &pkt[12..], &mut sbuf),
+        Err(SctpError::OOTB)
+    );
+    assert!(sbuf.is_empty());
+
+    // An authenticated ASCONF from the address being added is found by the
+    // address parameter.
+    let pkt = test_auth_packet(&server, vec![asconf]);
+    assert_eq!(test_deliver(&mut server, &client_ip2, &pkt), Ok(Vec::new()));
+    assert!(server.is_established());
+    assert!(server.get_pathid(&client_ip2).is_some());
+}
+
+#[test]
+fn test_association_remove_raddr() {
+    let (mut client, client_ip, mut server, server_ip, server_ip2) = test_establish_multihomed();
+    assert_eq!(
+        client.list_paths(),
+        vec![
+            SctpPathInfo {
+                pathid: 0,
+                addr: server_ip,
+                confirmed: true,
+                state: SctpPathState::Active,
+                primary: true,
+            },
+            SctpPathInfo {
+                pathid: 1,
+                addr: server_ip2,
+                confirmed: true,
+                state: SctpPathState::Active,
+                primary: false,
+            },
+        ]
+    );
+
+    // DATA in flight to the removed address is retransmitted to the other one.
+    client
+        .write_into_stream(0, &[1u8; 100], false, true)
+        .unwrap();
+    let mut sbuf = Vec::new();
+    assert_eq!(client.send(&mut sbuf).unwrap().1, server_ip);
+    assert_eq!(client.remove_raddr(&server_ip), Ok(()));
+    assert_eq!(
+        client.remove_raddr(&server_ip),
+        Err(SctpError::InvalidAddress)
+    );
+    assert_eq!(
+        client.list_paths(),
+        vec![SctpPathInfo {
+            pathid: 1,
+            addr: server_ip2,
+            confirmed: true,
+            state: SctpPathState::Active,
+            primary: true,
+        }]
+    );
+    assert_eq!(client.get_active_path(), Some(1));
+    let dsts = test_transfer_with_loss(&mut client, &client_ip, &mut server, &server_ip2, None);
+    assert_eq!(dsts, vec![server_ip2]);
+    let mut rbuf = Vec::new();
+    assert_eq!(server.read_from_stream(0, &mut rbuf), Ok(100));
+    assert_eq!(client.recovery.get_path_count(), 2);
+
+    // The last usable address is kept.
+    assert_eq!(
+        client.remove_raddr(&server_ip2),
+        Err(SctpError::InvalidAddress)
+    );
+
+    // A removed address can be added again.
+    assert_eq!(client.add_raddr(&server_ip), Ok(0));
+    assert_eq!(
+        client.recovery.get_path_state(0),
+        Ok((false, SctpPathState::Active))
+    );
+    assert_eq!(
+        client.remove_raddr(&server_ip2),
+        Err(SctpError::InvalidAddress)
+    );
+}
+
+#[test]
+fn test_association_cmt() {
+    let (mut client, client_ip, mut server, server_ip, server_ip2) = test_establish_multihomed();
+    client.set_cmt(true);
+    server.set_cmt(true);
+    client
+        .write_into_stream(0, &[1u8; 30000], false, true)
+        .unwrap();
+
+    // Packets to the primary address arrive late. As no DATA sent to it is
+    // acknowledged meanwhile, its TSNs are not fast retransmitted.
+    let mut delayed = Vec::new();
+    let mut dsts = Vec::new();
+    for _ in 0..4 {
+        loop {
+            let mut sbuf = Vec::new();
+            match client.send(&mut sbuf) {
+                Ok((_, dst)) => {
+                    dsts.push(dst);
+                    if dst == server_ip {
+                        delayed.push(sbuf);
+                    } else {
+                        test_deliver(&mut server, &client_ip, &sbuf).unwrap();
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+        let mut sbuf = Vec::new();
+        if server.send(&mut sbuf).is_ok() {
+            if let Ok((SctpChunk::Sack(sack), _)) = SctpChunk::from_bytes(&sbuf[12..]) {
+                assert!(sack.num_gap_ack > 0);
+                assert_eq!(sack.dac_pkts, 2);
+            }
+            test_deliver(&mut client, &server_ip2, &sbuf).unwrap();
+        }
+        test_transfer(&mut server, &server_ip2, &mut client);
+        assert!(client.recovery.tsn_waiting_fast_retrans.is_empty());
+    }
+    assert!(dsts.contains(&server_ip));
+    assert!(dsts.contains(&server_ip2));
+
+    for sbuf in delayed {
+        test_deliver(&mut server, &client_ip, &sbuf).unwrap();
+    }
+    for _ in 0..10 {
+        test_transfer_with_loss(&mut client, &client_ip, &mut server, &server_ip, None);
+    }
+    assert!(client.recovery.is_all_data_acked());
+    let mut rbuf = Vec::new();
+    assert_eq!(server.read_from_stream(0, &mut rbuf), Ok(30000));
+}
+
+#[test]
+fn test_association_rwnd_shared_by_paths() {
+    let (mut client, _, _, server_ip, _) = test_establish_multihomed();
+    client.recovery.initialize(3000);
+    client
+        .write_into_stream(0, &[1u8; 10000], false, true)
+        .unwrap();
+    let mut sbuf = Vec::new();
+    assert_eq!(client.send(&mut sbuf).unwrap().1, server_ip);
+    let flight = client.get_path_status(0).unwrap().flight;
+    assert!(flight > 0);
+
+    // DATA in flight to the primary address limits new DATA to the other
+    // one even without CMT.
+    assert_eq!(client.recovery.get_available_window(1), Ok(3000 - flight));
+}
+
+#[test]
+fn test_association_cmt_delayed_ack() {
+    for cmt in vec![false, true] {
+        let (mut client, client_ip, mut server, _) = test_establish();
+        client.set_cmt(cmt);
+        server.set_cmt(cmt);
+        let mut pkts = Vec::new();
+        for _ in 0..3 {
+            client
+                .write_into_stream(0, &[1u8; 100], false, true)
+                .unwrap();
+            let mut sbuf = Vec::new();
+            client.send(&mut sbuf).unwrap();
+            pkts.push(sbuf);
+        }
+
+        // A gap is reported at once, unless it comes from CMT reordering.
+        test_deliver(&mut server, &client_ip, &pkts[1]).unwrap();
+        assert_eq!(server.send_sack, !cmt);
+        assert_eq!(server.delayed_ack_timeout.is_some(), cmt);
+        test_deliver(&mut server, &client_ip, &pkts[2]).unwrap();
+        assert!(server.send_sack);
+        let mut sbuf = Vec::new();
+        server.send(&mut sbuf).unwrap();
+        match SctpChunk::from_bytes(&sbuf[12..]) {
+            Ok((SctpChunk::Sack(sack), _)) => {
+                assert_eq!(sack.num_gap_ack, 1);
+                assert_eq!(sack.dac_pkts, if cmt { 2 } else { 0 });
+            }
+            v => panic!("unexpected chunk {:?}", v),
+        }
+    }
+}
+
+#[test]
+fn test_association_path_params() {
+    let (mut client, client_ip, mut server, server_ip) = test_establish();
+    let mut params = client.get_path_params(0).unwrap();
+    assert_eq!(params.hb_interval, Duration::from_secs(30));
+    assert!(params.hb_enabled);
+    assert_eq!(params.max_retrans, 5);
+    assert!(params.pmtud_enabled);
+    assert_eq!(params.pmtu, client.get_pmtu(0).unwrap());
+    assert_eq!(params.rto_initial, Duration::from_secs(3));
+    assert_eq!(params.rto_min, Duration::from_secs(1));
+    assert_eq!(params.rto_max, Duration::from_secs(60));
+
+    assert_eq!(
+        client.set_path_params(1, &params),
+        Err(SctpError::InvalidPathId)
+    );
+    params.pmtud_enabled = false;
+    params.pmtu = 100;
+    assert_eq!(
+        client.set_path_params(0, &params),
+        Err(SctpError::InvalidValue)
+    );
+    params.pmtu = 1280;
+    params.rto_min = Duration::from_secs(5);
+    assert_eq!(
+        client.set_path_params(0, &params),
+        Err(SctpError::InvalidValue)
+    );
+
+    // A fixed path MTU, without PMTU probes or HEARTBEATs
+    params.rto_min = Duration::from_secs(1);
+    params.hb_enabled = false;
+    assert_eq!(client.set_path_params(0, &params), Ok(()));
+    assert_eq!(client.get_pmtu(0), Ok(1280));
+    assert_eq!(client.get_path_params(0), Ok(params.clone()));
+    test_transfer(&mut client, &client_ip, &mut server);
+    test_transfer(&mut server, &server_ip, &mut client);
+    assert_eq!(client.get_timeout(), None);
+
+    // The running T3-rtx timer follows a smaller RTO.Max.
+    client
+        .write_into_stream(0, &[1u8; 100], false, true)
+        .unwrap();
+    let mut sbuf = Vec::new();
+    client.send(&mut sbuf).unwrap();
+    assert!(client.get_timeout().unwrap() > Duration::from_millis(500));
+    params.rto_min = Duration::from_millis(100);
+    params.rto_initial = Duration::from_millis(100);
+    params.rto_max = Duration::from_millis(200);
+    assert_eq!(client.set_path_params(0, &params), Ok(()));
+    assert!(client.get_timeout().unwrap() <= Duration::from_millis(200));
+
+    // PMTUD starts over when enabled again.
+    params.pmtud_enabled = true;
+    assert_eq!(client.set_path_params(0, &params), Ok(()));
+    assert_eq!(client.get_pmtu(0), Ok(1200));
+
+    // The RTO backoff saturates at RTO.Max with a large Path.Max.Retrans.
+    params.max_retrans = 40;
+    assert_eq!(
+        client.set_path_params(0, &params),
+        Err(SctpError::InvalidValue)
+    );
+    params.max_retrans = 31;
+    assert_eq!(client.set_path_params(0, &params), Ok(()));
+    client.recovery.on_path_unreachable(0).unwrap();
+    assert_eq!(client.get_path_status(0).unwrap().rto, params.rto_max);
+}
+
+#[test]
+fn test_association_path_verification() {
+    let (mut client, client_ip, mut server, server_ip) = test_establish();
+    let server_ip2: IpAddr = "192.0.2.4".parse().unwrap();
+    let server_ip3: IpAddr = "192.0.2.6".parse().unwrap();
+    assert_eq!(client.add_raddr(&server_ip2), Ok(1));
+    assert_eq!(client.add_raddr(&server_ip3), Ok(2));
+
+    // One HEARTBEAT to an unconfirmed address per RTO
+    client.on_timeout();
+    let mut hbs = Vec::new();
+    loop {
+        let mut sbuf = Vec::new();
+        match client.send(&mut sbuf) {
+            Ok((_, dst)) if dst == server_ip => {}
+            Ok((_, dst)) => hbs.push((dst, sbuf)),
+            Err(_) => break,
+        }
+    }
+    assert_eq!(hbs.len(), 1);
+    let (dst, sbuf) = hbs.pop().unwrap();
+    assert_eq!(dst, server_ip2);
+    assert_eq!(client.request_heartbeat(2), Err(SctpError::Done));
+    assert_eq!(client.request_heartbeat(3), Err(SctpError::InvalidPathId));
+
+    // The HEARTBEAT-ACK must echo the nonce.
+    let mut hbinfo = match SctpChunk::from_bytes(&sbuf[12..]) {
+        Ok((SctpChunk::Heartbeat(v), _)) => v,
+        v => panic!("unexpected chunk {:?}", v),
+    };
+    *hbinfo.last_mut().unwrap() ^= 1;
+    let forged = test_packet(&client, vec![SctpChunk::HeartbeatAck(hbinfo)]);
+    test_deliver(&mut client, &server_ip2, &forged).unwrap();
+    assert_eq!(
+        client.recovery.get_path_state(1),
+        Ok((false, SctpPathState::Active))
+    );
+    assert_eq!(client.poll_event(), None);
+
+    test_deliver(&mut server, &client_ip, &sbuf).unwrap();
+    test_transfer(&mut server, &server_ip2, &mut client);
+    assert_eq!(
+        client.recovery.get_path_state(1),
+        Ok((true, SctpPathState::Active))
+    );
+    assert_eq!(
+        client.poll_event(),
+        Some(SctpEvent::PathConfirmed { pathid: 1 })
+    );
+
+    // An unreachable address does not count toward Association.Max.Retrans.
+    for i in 1..20 {
+        client
+            .recovery
+            .on_timeout(Instant::now() + Duration::from_secs(3600 * i));
+        client.on_timeout();
+        test_transfer_with_loss(
+            &mut client,
+            &client_ip,
+            &mut server,
+            &server_ip,
+            Some(server_ip3),
+        );
+    }
+    assert!(!client.is_closed());
+    assert_eq!(
+        client.recovery.get_path_state(2),
+        Ok((false, SctpPathState::InActive))
+    );
+
+    // On demand
+    assert_eq!(client.request_heartbeat(0), Ok(()));
+    assert_eq!(client.request_heartbeat(0), Err(SctpError::Done));
+    let mut sbuf = Vec::new();
+    assert_eq!(client.send(&mut sbuf).unwrap().1, server_ip);
+}
+
+#[test]
+fn test_association_status() {
+    let (mut client, client_ip, mut server, server_ip) = test_establish();
+    test_transfer(&mut client, &client_ip, &mut server);
+    test_transfer(&mut server, &server_ip, &mut client);
+    assert_eq!(client.get_path_status(1), Err(SctpError::InvalidPathId));
+    let status = client.get_status();
+    assert_eq!(status.state, SctpAssociationState::Established);
+    assert_eq!(status.unacked_data, 0);
+    assert_eq!(status.primary_path, Some(0));
+    assert_eq!(status.next_tsn, status.cumulative_tsn_ack.wrapping_add(1));
+    let server_status = server.get_status();
+    assert_eq!(server_status.cumulative_tsn, status.cumulative_tsn_ack);
+    assert_eq!(server_status.inbound_streams, status.outbound_streams);
+    assert_eq!(server_status.outbound_streams, status.inbound_streams);
+    assert_eq!(status.peer_rwnd, server_status.a_rwnd as usize);
+
+    let path_status = client.get_path_status(0).unwrap();
+    assert!(path_status.confirmed);
+    assert_eq!(path_status.state, SctpPathState::Active);
+    assert!(path_status.srtt.is_some());
+    assert!(path_status.rto >= Duration::from_secs(1));
+    assert_eq!(path_status.flight, 0);
+    assert_eq!(path_status.pmtu, client.get_pmtu(0).unwrap());
+    assert_eq!(path_status.error_count, 0);
+
+    for _ in 0..3 {
+        client
+            .write_into_stream(0, &[1u8; 100], false, true)
+            .unwrap();
+    }
+    let mut sbuf = Vec::new();
+    client.send(&mut sbuf).unwrap();
+    let status = client.get_status();
+    assert_eq!(status.unacked_data, 3);
+    assert_eq!(status.next_tsn, status.cumulative_tsn_ack.wrapping_add(4));
+    // Including the DATA chunk headers
+    assert_eq!(client.get_path_status(0).unwrap().flight, 3 * (16 + 100));
+
+    test_deliver(&mut server, &client_ip, &sbuf).unwrap();
+    let server_status = server.get_status();
+    assert_eq!(server_status.pending_data, 3);
+    assert_eq!(server_status.cumulative_tsn, status.next_tsn - 1);
+    assert_eq!(server_status.a_rwnd, status.peer_rwnd as u32 - 300);
+
+    server.on_timeout();
+    test_transfer(&mut server, &server_ip, &mut client);
+    let status = client.get_status();
+    assert_eq!(status.unacked_data, 0);
+    assert_eq!(status.cumulative_tsn_ack, server_status.cumulative_tsn);
+    assert_eq!(client.get_path_status(0).unwrap().flight, 0);
+}
+
+#[cfg(test)]
+#[derive(Debug)]
+struct TestFixedWindow {
+    cwnd: usize,
+    pacing_rate: Option<u64>,
+}
+
+#[cfg(test)]
+impl CongestionController for TestFixedWindow {
+    fn get_cwnd(&self) -> usize {
+        self.cwnd
+    }
+
+    fn get_ssthresh(&self) -> usize {
+        self.cwnd
+    }
+
+    fn on_ack(&mut self, _acked: usize, _flight: usize, _in_recovery: bool, _now: Instant) {}
+
+    fn on_congestion_event(&mut self, _now: Instant) {}
+
+    fn on_rto(&mut self, _now: Instant) {}
+
+    fn on_idle(&mut self, _now: Instant) {}
+
+    fn on_mtu_changed(&mut self, _mtu: usize) {}
+
+    fn get_pacing_rate(&self) -> Option<u64> {
+        self.pacing_rate
+    }
+}
+
+#[test]
+fn test_association_congestion_control() {
+    let (mut client, client_ip, mut server, server_ip) = test_establish();
+    test_transfer(&mut client, &client_ip, &mut server);
+    test_transfer(&mut server, &server_ip, &mut client);
+    let status = client.get_path_status(0).unwrap();
+    assert_eq!(status.ssthresh, usize::MAX);
+
+    client.set_congestion_control(SctpCongestionControl::Custom(|mtu| {
+        Box::new(TestFixedWindow {
+            cwnd: mtu,
+            pacing_rate: None,
+        })
+    }));
+    let status = client.get_path_status(0).unwrap();
+    assert_eq!(status.cwnd, status.ssthresh);
+
+    // Only a single full packet fits in the window.
+    client
+        .write_into_stream(0, &[1u8; 10000], false, true)
+        .unwrap();
+    let mut sbuf = Vec::new();
+    client.send(&mut sbuf).unwrap();
+    let mut sbuf1 = Vec::new();
+    assert_eq!(client.send(&mut sbuf1), Err(SctpError::Done));
+    test_deliver(&mut server, &client_ip, &sbuf).unwrap();
+    server.on_timeout();
+    test_transfer(&mut server, &server_ip, &mut client);
+    assert_eq!(client.get_path_status(0).unwrap().cwnd, status.cwnd);
+    test_transfer(&mut client, &client_ip, &mut server);
+    let flight = client.get_path_status(0).unwrap().flight;
+    assert!(flight > 0 && flight <= status.cwnd);
+
+    // Back to the default
+    client.set_congestion_control(SctpCongestionControl::Reno);
+    assert_eq!(client.get_path_status(0).unwrap().cwnd, 4 * status.cwnd);
+
+    client.set_congestion_control(SctpCongestionControl::Cubic);
+    assert_eq!(client.get_path_status(0).unwrap().cwnd, 4 * status.cwnd);
+    // Only the window limits the flight.
+    client.set_max_burst(0);
+    client
+        .write_into_stream(0, &[1u8; 20000], false, true)
+        .unwrap();
+    for _ in 0..10 {
+        test_transfer(&mut client, &client_ip, &mut server);
+        server.on_timeout();
+        test_transfer(&mut server, &server_ip, &mut client);
+    }
+    assert_eq!(client.get_status().unacked_data, 0);
+    assert!(client.get_path_status(0).unwrap().cwnd > 4 * status.cwnd);
+    assert_eq!(client.get_path_status(0).unwrap().pacing_rate, None);
+
+    // BBR paces at a rate derived from the window until it has a bandwidth
+    // estimate.
+    client.set_congestion_control(SctpCongestionControl::Bbr);
+    client
+        .write_into_stream(0, &[1u8; 20000], false, true)
+        .unwrap();
+    for _ in 0..10 {
+        test_transfer(&mut client, &client_ip, &mut server);
+        server.on_timeout();
+        test_transfer(&mut server, &server_ip, &mut client);
+    }
+    assert_eq!(client.get_status().unacked_data, 0);
+    let path_status = client.get_path_status(0).unwrap();
+    assert_eq!(path_status.ssthresh, usize::MAX);
+    assert!(path_status.pacing_rate.is_some());
+}
+
+#[cfg(test)]
+fn test_chunk_types(pkt: &[u8]) -> Vec<SctpChunkType> {
+    let (_, mut off) = SctpCommonHeader::from_bytes(pkt).unwrap();
+    let mut chunk_types = Vec::new();
+    while off < pkt.len() {
+        let (chunk, consumed) = SctpChunk::from_bytes(&pkt[off..]).unwrap();
+        chunk_types.push(chunk.get_type());
+        off += consumed;
+    }
+    chunk_types
+}
+
+#[test]
+fn test_association_ecn() {
+    let (mut client, client_ip, mut server, server_ip) = test_establish();
+    assert!(client.ecn_supported);
+    assert!(server.ecn_supported);
+
+    client
+        .write_into_stream(0, &[1u8; 20000], false, true)
+        .unwrap();
+    for _ in 0..10 {
+        test_transfer(&mut client, &client_ip, &mut server);
+        server.on_timeout();
+        test_transfer(&mut server, &server_ip, &mut client);
+    }
+    let cwnd = client.get_path_status(0).unwrap().cwnd;
+    assert!(cwnd > 4 * client.get_pmtu(0).unwrap());
+
+    // A packet marked CE is answered right away with a SACK and an ECNE.
+    client
+        .write_into_stream(0, &[1u8; 100], false, true)
+        .unwrap();
+    let mut sbuf = Vec::new();
+    let (_, info) = client.send_with_info(&mut sbuf).unwrap();
+    assert_eq!(
+        info,
+        SctpSendInfo {
+            to: server_ip,
+            ect: true
+        }
+    );
+    let ce = SctpRecvInfo {
+        from: client_ip,
+        ce: true,
+    };
+    server
+        .recv_with_info(&sbuf[12..], &mut Vec::new(), &ce)
+        .unwrap();
+    let mut sbuf = Vec::new();
+    let (_, info) = server.send_with_info(&mut sbuf).unwrap();
+    assert!(!info.ect);
+    assert_eq!(
+        test_chunk_types(&sbuf),
+        vec![SctpChunkType::Sack, SctpChunkType::Ecne]
+    );
+    test_deliver(&mut client, &server_ip, &sbuf).unwrap();
+    let status = client.get_path_status(0).unwrap();
+    assert_eq!(status.cwnd, cwnd / 2);
+
+    // The ECNE is repeated while the CWR is lost, but the cwnd is reduced
+    // only once per window.
+    client
+        .write_into_stream(0, &[1u8; 100], false, true)
+        .unwrap();
+    let mut sbuf = Vec::new();
+    client.send(&mut sbuf).unwrap();
+    assert_eq!(
+        test_chunk_types(&sbuf),
+        vec![SctpChunkType::Cwr, SctpChunkType::Data]
+    );
+    client
+        .write_into_stream(0, &[1u8; 100], false, true)
+        .unwrap();
+    let mut sbuf = Vec::new();
+    client.send(&mut sbuf).unwrap();
+    test_deliver(&mut server, &client_ip, &sbuf).unwrap();
+    server.on_timeout();
+    let mut sbuf = Vec::new();
+    server.send(&mut sbuf).unwrap();
+    assert_eq!(
+        test_chunk_types(&sbuf),
+        vec![SctpChunkType::Sack, SctpChunkType::Ecne]
+    );
+    test_deliver(&mut client, &server_ip, &sbuf).unwrap();
+    assert_eq!(client.get_path_status(0).unwrap().cwnd, status.cwnd);
+
+    client
+        .write_into_stream(0, &[1u8; 100], false, true)
+        .unwrap();
+    test_transfer(&mut client, &client_ip, &mut server);
+    server.on_timeout();
+    let mut sbuf = Vec::new();
+    server.send(&mut sbuf).unwrap();
+    assert_eq!(test_chunk_types(&sbuf), vec![SctpChunkType::Sack]);
+}
+
+#[test]
+fn test_association_pacing() {
+    let (mut client, client_ip, mut server, server_ip) = test_establish();
+    client.set_congestion_control(SctpCongestionControl::Custom(|mtu| {
+        Box::new(TestFixedWindow {
+            cwnd: 100 * mtu,
+            pacing_rate: Some(100_000),
+        })
+    }));
+    client.set_pacing(true);
+    let mut params = client.get_path_params(0).unwrap();
+    params.pmtud_enabled = false;
+    params.pmtu = 1500;
+    client.set_path_params(0, &params).unwrap();
+    client
+        .write_into_stream(0, &[1u8; 10000], false, true)
+        .unwrap();
+
+    // A full packet takes about 15ms at 100kB/s.
+    let now = Instant::now();
+    let mut sbuf = Vec::new();
+    client.send_with_info_at(&mut sbuf, now).unwrap();
+    let pacing_time = client.recovery.get_pacing_timeout(now).unwrap();
+    assert!(pacing_time > now + Duration::from_millis(10));
+    assert!(pacing_time <= now + Duration::from_millis(15));
+    let mut sbuf1 = Vec::new();
+    let before = pacing_time - Duration::from_millis(1);
+    assert!(client.recovery.is_paced(0, before));
+    assert_eq!(
+        client.send_with_info_at(&mut sbuf1, before),
+        Err(SctpError::Done)
+    );
+    assert!(!client.recovery.is_paced(0, pacing_time));
+    assert_eq!(client.recovery.get_pacing_timeout(pacing_time), None);
+    client.send_with_info_at(&mut sbuf1, pacing_time).unwrap();
+    test_deliver(&mut server, &client_ip, &sbuf).unwrap();
+    test_deliver(&mut server, &client_ip, &sbuf1).unwrap();
+    test_transfer(&mut server, &server_ip, &mut client);
+
+    // Back to bursts of packets
+    client.set_pacing(false);
+    let mut sbuf = Vec::new();
+    client.send(&mut sbuf).unwrap();
+    let mut sbuf = Vec::new();
+    client.send(&mut sbuf).unwrap();
+    assert!(client.get_timeout().unwrap() > Duration::from_millis(100));
+}
+
+#[test]
+fn test_association_nodelay() {
+    let (mut client, client_ip, mut server, server_ip) = test_establish();
+    client.set_nodelay(false);
+    client
+        .write_into_stream(0, &[1u8; 100], false, true)
+        .unwrap();
+    let mut sbuf = Vec::new();
+    client.send(&mut sbuf).unwrap();
+
+    // Held back while the first message is outstanding
+    client
+        .write_into_stream(0, &[1u8; 100], false, true)
+        .unwrap();
+    let mut sbuf1 = Vec::new();
+    assert_eq!(client.send(&mut sbuf1), Err(SctpError::Done));
+    test_deliver(&mut server, &client_ip, &sbuf).unwrap();
+    server.on_timeout();
+    test_transfer(&mut server, &server_ip, &mut client);
+    assert_eq!(test_transfer(&mut client, &client_ip, &mut server), 1);
+
+    // A full packet is not delayed.
+    client
+        .write_into_stream(0, &[1u8; 100], false, true)
+        .unwrap();
+    assert_eq!(test_transfer(&mut client, &client_ip, &mut server), 0);
+    client
+        .write_into_stream(0, &[1u8; 2000], false, true)
+        .unwrap();
+    assert_eq!(test_transfer(&mut client, &client_ip, &mut server), 1);
+}
+
+#[test]
+fn test_association_nodelay_full_packet() {
+    let (mut client, client_ip, mut server, _) = test_establish();
+    client.set_nodelay(false);
+    let mtu = client.recovery.get_path_mtu(0).unwrap();
+    client
+        .write_into_stream(0, &[1u8; 100], false, true)
+        .unwrap();
+    let mut sbuf = Vec::new();
+    client.send(&mut sbuf).unwrap();
+
+    // Two DATA chunks one byte short of a full packet are held back.
+    client
+        .write_into_stream(0, &[1u8; 100], false, true)
+        .unwrap();
+    let len = mtu - (16 + 100) - 16;
+    client
+        .write_into_stream(0, &vec![1u8; len - 1], false, false)
+        .unwrap();
+    let mut sbuf = Vec::new();
+    assert_eq!(client.send(&mut sbuf), Err(SctpError::Done));
+
+    // They are sent once they fill the packet.
+    client.write_into_stream(0, &[1u8], false, true).unwrap();
+    let mut sbuf = Vec::new();
+    client.send(&mut sbuf).unwrap();
+    assert_eq!(sbuf.len(), 12 + mtu);
+    assert_eq!(test_chunk_types(&sbuf), vec![SctpChunkType::Data; 2]);
+    test_deliver(&mut server, &client_ip, &sbuf).unwrap();
+}
+
+#[test]
+fn test_association_max_burst() {
+    let (mut client, client_ip, mut server, server_ip) = test_establish();
+    client.set_max_burst(2);
+    client
+        .write_into_stream(0, &[1u8; 20000], false, true)
+        .unwrap();
+    assert_eq!(test_transfer(&mut client, &client_ip, &mut server), 2);
+    assert_eq!(test_transfer(&mut client, &client_ip, &mut server), 0);
+
+    // The SACK allows the next burst.
+    assert_eq!(test_transfer(&mut server, &server_ip, &mut client), 1);
+    assert_eq!(test_transfer(&mut client, &client_ip, &mut server), 2);
+}
+
+#[test]
+fn test_association_sack_immediately() {
+    let (mut client, client_ip, mut server, server_ip) = test_establish();
+    let data_i_bits = |pkt: &[u8]| {
+        let mut i_bits = Vec::new();
+        let mut off = 12;
+        while off < pkt.len() {
+            let (chunk, consumed) = SctpChunk::from_bytes(&pkt[off..]).unwrap();
+            if let SctpChunk::Data(data_chunk) = chunk {
+                i_bits.push(data_chunk.i_bit);
+            }
+            off += consumed;
+        }
+        i_bits
+    };
+    client
+        .write_into_stream(0, &[1u8; 100], false, true)
+        .unwrap();
+    test_transfer(&mut client, &client_ip, &mut server);
+    assert!(!server.send_sack);
+    assert!(server.delayed_ack_timeout.is_some());
+    assert_eq!(test_transfer(&mut server, &server_ip, &mut client), 1);
+
+    // Acked without waiting for the delayed ack timer
+    client
+        .write_into_stream_with_flags(0, &[1u8; 100], false, true, true)
+        .unwrap();
+    let mut sbuf = Vec::new();
+    client.send(&mut sbuf).unwrap();
+    assert_eq!(data_i_bits(&sbuf), vec![true]);
+    test_deliver(&mut server, &client_ip, &sbuf).unwrap();
+    assert!(server.send_sack);
+    assert_eq!(test_transfer(&mut server, &server_ip, &mut client), 1);
+    assert_eq!(client.get_status().unacked_data, 0);
+
+    // Set on the final DATA before SHUTDOWN
+    client
+        .write_into_stream(0, &[1u8; 2000], false, true)
+        .unwrap();
+    client.close().unwrap();
+    let mut i_bits = Vec::new();
+    loop {
+        let mut sbuf = Vec::new();
+        if client.send(&mut sbuf).is_err() {
+            break;
+        }
+        i_bits.append(&mut data_i_bits(&sbuf));
+        test_deliver(&mut server, &client_ip, &sbuf).unwrap();
+    }
+    assert_eq!(i_bits, vec![false, true]);
+}
+
+#[test]
+fn test_association_dup_tsns() {
+    let (mut client, client_ip, mut server, _) = test_establish();
+    client
+        .write_into_stream(0, &[1u8; 100], false, true)
+        .unwrap();
+    let mut sbuf = Vec::new();
+    client.send(&mut sbuf).unwrap();
+    test_deliver(&mut server, &client_ip, &sbuf).unwrap();
+    assert!(!server.send_sack);
+
+    // A duplicate is reported by an immediate SACK.
+    test_deliver(&mut server, &client_ip, &sbuf).unwrap();
+    assert!(server.send_sack);
+    let tsn = client.recovery.get_largest_tsn();
+    let mut sbuf = Vec::new();
+    server.send(&mut sbuf).unwrap();
+    match SctpChunk::from_bytes(&sbuf[12..]) {
+        Ok((SctpChunk::Sack(sack), _)) => {
+            assert_eq!(sack.cum_ack, tsn);
+            assert_eq!(sack.num_dup_ack, 1);
+            assert_eq!(sack.dup_acks, vec![tsn]);
+        }
+        v => panic!("unexpected chunk {:?}", v),
+    }
+    // Delivered only once
+    assert_eq!(server.get_status().pending_data, 1);
+}
+
+#[test]
+fn test_association_cwnd_validation() {
+    let (mut client, client_ip, mut server, server_ip) = test_establish();
+    let mtu = client.recovery.get_path_mtu(0).unwrap();
+    client
+        .write_into_stream(0, &[1u8; 20000], false, true)
+        .unwrap();
+    // Sending until the cwnd is full
+    client.set_max_burst(0);
+    for _ in 0..10 {
+        test_transfer(&mut client, &client_ip, &mut server);
+        server.on_timeout();
+        test_transfer(&mut server, &server_ip, &mut client);
+    }
+    assert!(client.get_path_status(0).unwrap().cwnd > 4 * mtu);
+
+    // Into congestion avoidance
+    let largest_tsn = client.recovery.get_largest_tsn();
+    client
+        .recovery
+        .on_ecne_received(largest_tsn, Instant::now());
+    client
+        .write_into_stream(0, &[1u8; 20000], false, true)
+        .unwrap();
+    for _ in 0..10 {
+        test_transfer(&mut client, &client_ip, &mut server);
+        server.on_timeout();
+        test_transfer(&mut server, &server_ip, &mut client);
+    }
+    assert_eq!(client.get_status().unacked_data, 0);
+    let status = client.get_path_status(0).unwrap();
+    assert!(status.cwnd > status.ssthresh);
+
+    // The cwnd does not grow while most of it is unused.
+    for _ in 0..5 {
+        client
+            .write_into_stream(0, &[1u8; 100], false, true)
+            .unwrap();
+        test_transfer(&mut client, &client_ip, &mut server);
+        server.on_timeout();
+        test_transfer(&mut server, &server_ip, &mut client);
+    }
+    let status1 = client.get_path_status(0).unwrap();
+    assert_eq!(status1.flight, 0);
+    assert_eq!(status1.cwnd, status.cwnd);
+    assert_eq!(status1.partial_bytes_acked, status.partial_bytes_acked);
+
+    // Halved for every RTO the path was idle
+    let now = Instant::now() + 2 * status1.rto;
+    let data_chunk = SctpDataChunk {
+        i_bit: false,
+        u_bit: false,
+        b_bit: true,
+        e_bit: true,
+        tsn: client.recovery.get_next_tsn(),
+        stream_id: 0,
+        stream_seq: 0,
+        proto_id: 0,
+        data: vec![1u8; 100],
+    };
+    client
+        .recovery
+        .on_data_sent(SctpChunk::Data(data_chunk), 0, now, false);
+    let cwnd = cmp::max(cmp::max(status1.cwnd / 2, 4 * mtu) / 2, 4 * mtu);
+    assert_eq!(client.get_path_status(0).unwrap().cwnd, cwnd);
+}
@@ -0,0 +1,438 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::sctp_congestion::CongestionController;
+
+// 2 / ln(2), the smallest gain that doubles the delivery rate every round
+const BBR_HIGH_GAIN: f64 = 2.885;
+const BBR_CWND_GAIN: f64 = 2.0;
+const BBR_PACING_GAINS: [f64; 8] = [1.25, 0.75, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0];
+// The bandwidth estimate is the max over this many rounds.
+const BBR_BW_WINDOW: u64 = 10;
+const BBR_MIN_RTT_WINDOW: Duration = Duration::from_secs(10);
+const BBR_PROBE_RTT_DURATION: Duration = Duration::from_millis(200);
+// Startup ends once the bandwidth grew less than 25% in three rounds.
+const BBR_FULL_BW_THRESH: f64 = 1.25;
+const BBR_FULL_BW_ROUNDS: u32 = 3;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SctpBbrMode {
+    // Doubling the sending rate every round to find the bandwidth
+    Startup,
+    // Draining the queue built up in Startup
+    Drain,
+    // Cycling the pacing gain around the estimated bandwidth
+    ProbeBw,
+    // Cutting the flight to a minimum to measure the RTT without a queue
+    ProbeRtt,
+}
+
+// A BBR-style congestion control. Instead of reacting to loss, it models the
+// path from the delivery rate of SACKed bytes and the min RTT, and paces
+// packets at the estimated bandwidth.
+#[derive(Debug)]
+pub struct SctpBbr {
+    mtu: usize,
+    cwnd: usize,
+    mode: SctpBbrMode,
+
+    // A round ends once the bytes in flight at its start are acknowledged,
+    // and gives a delivery rate sample.
+    round_count: u64,
+    round_start: Option<Instant>,
+    round_remaining: usize,
+    round_delivered: usize,
+
+    // (round, bytes per second)
+    bw_samples: VecDeque<(u64, f64)>,
+    min_rtt: Option<Duration>,
+    min_rtt_stamp: Option<Instant>,
+    // The min RTT was not measured again within BBR_MIN_RTT_WINDOW.
+    min_rtt_expired: bool,
+    srtt: Option<Duration>,
+
+    full_bw: f64,
+    full_bw_rounds: u32,
+    cycle_index: usize,
+    cycle_start: Option<Instant>,
+    probe_rtt_done: Option<Instant>,
+    // The window before ProbeRTT or a timeout, restored afterwards
+    prior_cwnd: usize,
+    restore_cwnd: bool,
+}
+
+impl SctpBbr {
+    pub fn new(mtu: usize) -> Self {
+        SctpBbr {
+            mtu,
+            cwnd: mtu * 4,
+            mode: SctpBbrMode::Startup,
+            round_count: 0,
+            round_start: None,
+            round_remaining: 0,
+            round_delivered: 0,
+            bw_samples: VecDeque::new(),
+            min_rtt: None,
+            min_rtt_stamp: None,
+            min_rtt_expired: false,
+            srtt: None,
+            full_bw: 0.0,
+            full_bw_rounds: 0,
+            cycle_index: 0,
+            cycle_start: None,
+            probe_rtt_done: None,
+            prior_cwnd: 0,
+            restore_cwnd: false,
+        }
+    }
+
+    pub fn get_mode(&self) -> SctpBbrMode {
+        self.mode
+    }
+
+    // The estimated bottleneck bandwidth in bytes per second
+    pub fn get_max_bw(&self) -> Option<f64> {
+        self.bw_samples
+            .iter()
+            .map(|(_, bw)| *bw)
+            .fold(None, |max, bw| Some(max.map_or(bw, |v: f64| v.max(bw))))
+    }
+
+    pub fn get_min_rtt(&self) -> Option<Duration> {
+        self.min_rtt
+    }
+
+    fn get_bdp(&self) -> Option<f64> {
+        Some(self.get_max_bw()? * self.min_rtt?.as_secs_f64())
+    }
+
+    fn get_pacing_gain(&self) -> f64 {
+        match self.mode {
+            SctpBbrMode::Startup => BBR_HIGH_GAIN,
+            SctpBbrMode::Drain => 1.0 / BBR_HIGH_GAIN,
+            SctpBbrMode::ProbeBw => BBR_PACING_GAINS[self.cycle_index],
+            SctpBbrMode::ProbeRtt => 1.0,
+        }
+    }
+
+    fn get_cwnd_gain(&self) -> f64 {
+        match self.mode {
+            SctpBbrMode::Startup | SctpBbrMode::Drain => BBR_HIGH_GAIN,
+            _ => BBR_CWND_GAIN,
+        }
+    }
+
+    fn is_full_bw_reached(&self) -> bool {
+        self.full_bw_rounds >= BBR_FULL_BW_ROUNDS
+    }
+
+    // Returns true at the end of a round.
+    fn update_round(&mut self, acked: usize, flight: usize, now: Instant) -> bool {
+        let round_start = match self.round_start {
+            Some(v) => v,
+            None => {
+                self.round_start = Some(now);
+                self.round_remaining = flight;
+                return false;
+            }
+        };
+        self.round_delivered += acked;
+        self.round_remaining = self.round_remaining.saturating_sub(acked);
+        if self.round_remaining > 0 {
+            return false;
+        }
+
+        // Rounds shorter than the min RTT, e.g. after an idle period, would
+        // overestimate the bandwidth.
+        let elapsed = now.duration_since(round_start);
+        if matches!(self.min_rtt, Some(min_rtt) if elapsed >= min_rtt) {
+            let bw = self.round_delivered as f64 / elapsed.as_secs_f64();
+            self.bw_samples.push_back((self.round_count, bw));
+            while let Some((round, _)) = self.bw_samples.front() {
+                if round + BBR_BW_WINDOW > self.round_count {
+                    break;
+                }
+                self.bw_samples.pop_front();
+            }
+        }
+        self.round_count += 1;
+        self.round_start = Some(now);
+        self.round_remaining = flight;
+        self.round_delivered = 0;
+        true
+    }
+
+    fn check_full_bw(&mut self) {
+        let max_bw = match self.get_max_bw() {
+            Some(v) => v,
+            None => return,
+        };
+        if max_bw >= self.full_bw * BBR_FULL_BW_THRESH {
+            self.full_bw = max_bw;
+            self.full_bw_rounds = 0;
+        } else {
+            self.full_bw_rounds += 1;
+        }
+    }
+
+    fn enter_probe_bw(&mut self, now: Instant) {
+        self.mode = SctpBbrMode::ProbeBw;
+        // Start cruising, probing up after the cycle wraps around.
+        self.cycle_index = 2;
+        self.cycle_start = Some(now);
+    }
+
+    fn update_mode(&mut self, flight: usize, round_end: bool, now: Instant) {
+        match self.mode {
+            SctpBbrMode::Startup => {
+                if round_end {
+                    self.check_full_bw();
+                }
+                if self.is_full_bw_reached() {
+                    self.mode = SctpBbrMode::Drain;
+                }
+            }
+            SctpBbrMode::Drain => {
+                if matches!(self.get_bdp(), Some(bdp) if flight as f64 <= bdp) {
+                    self.enter_probe_bw(now);
+                }
+            }
+            SctpBbrMode::ProbeBw => {
+                let min_rtt = self.min_rtt.unwrap_or_default();
+                if matches!(self.cycle_start, Some(start) if now.duration_since(start) > min_rtt) {
+                    self.cycle_index = (self.cycle_index + 1) % BBR_PACING_GAINS.len();
+                    self.cycle_start = Some(now);
+                }
+            }
+            SctpBbrMode::ProbeRtt => {
+                if self.probe_rtt_done.is_none() && flight <= 4 * self.mtu {
+                    self.probe_rtt_done = Some(now + BBR_PROBE_RTT_DURATION);
+                }
+                if matches!(self.probe_rtt_done, Some(done) if now >= done) {
+                    self.probe_rtt_done = None;
+                    self.min_rtt_stamp = Some(now);
+                    self.cwnd = std::cmp::max(self.cwnd, self.prior_cwnd);
+                    if self.is_full_bw_reached() {
+                        self.enter_probe_bw(now);
+                    } else {
+                        self.mode = SctpBbrMode::Startup;
+                    }
+                }
+            }
+        }
+
+        if self.min_rtt_expired && self.mode != SctpBbrMode::ProbeRtt {
+            self.min_rtt_expired = false;
+            self.mode = SctpBbrMode::ProbeRtt;
+            self.prior_cwnd = self.cwnd;
+            self.probe_rtt_done = None;
+        }
+    }
+
+    fn update_cwnd(&mut self, acked: usize) {
+        let min_cwnd = 4 * self.mtu;
+        if self.mode == SctpBbrMode::ProbeRtt {
+            self.cwnd = min_cwnd;
+            return;
+        }
+        match self.get_bdp() {
+            Some(bdp) => {
+                let target = std::cmp::max((self.get_cwnd_gain() * bdp) as usize, min_cwnd);
+                if self.is_full_bw_reached() {
+                    self.cwnd = std::cmp::min(self.cwnd + acked, target);
+                } else if self.cwnd < target {
+                    self.cwnd += acked;
+                }
+            }
+            None => {
+                self.cwnd += acked;
+            }
+        }
+        self.cwnd = std::cmp::max(self.cwnd, min_cwnd);
+    }
+}
+
+impl CongestionController for SctpBbr {
+    fn get_cwnd(&self) -> usize {
+        self.cwnd
+    }
+
+    // There is no slow start threshold.
+    fn get_ssthresh(&self) -> usize {
+        usize::MAX
+    }
+
+    fn on_ack(&mut self, acked: usize, flight: usize, _in_recovery: bool, now: Instant) {
+        if acked == 0 {
+            return;
+        }
+        if self.restore_cwnd {
+            self.restore_cwnd = false;
+            self.cwnd = std::cmp::max(self.cwnd, self.prior_cwnd);
+        }
+        let round_end = self.update_round(acked, flight, now);
+        self.update_mode(flight, round_end, now);
+        self.update_cwnd(acked);
+    }
+
+    // The delivery rate is still sampled, as it only raises the max filter,
+    // but such rounds do not count toward the end of Startup.
+    fn on_app_limited_ack(&mut self, acked: usize, flight: usize, now: Instant) {
+        if acked == 0 {
+            return;
+        }
+        self.update_round(acked, flight, now);
+    }
+
+    // Loss is not taken as a sign of congestion.
+    fn on_congestion_event(&mut self, _now: Instant) {}
+
+    // Only a single packet goes out until data is acknowledged again.
+    fn on_rto(&mut self, _now: Instant) {
+        if self.mode != SctpBbrMode::ProbeRtt {
+            self.prior_cwnd = self.cwnd;
+        }
+        self.restore_cwnd = true;
+        self.cwnd = self.mtu;
+    }
+
+    // Pacing at the estimated bandwidth keeps the first flight after an
+    // idle period from building a queue.
+    fn on_idle(&mut self, _now: Instant) {}
+
+    fn on_mtu_changed(&mut self, mtu: usize) {
+        self.mtu = mtu;
+    }
+
+    fn on_rtt_sample(&mut self, rtt: Duration, srtt: Duration, now: Instant) {
+        self.srtt = Some(srtt);
+        let expired = matches!(
+            self.min_rtt_stamp,
+            Some(stamp) if now.duration_since(stamp) > BBR_MIN_RTT_WINDOW
+        );
+        let lower = match self.min_rtt {
+            Some(min_rtt) => rtt <= min_rtt,
+            None => true,
+        };
+        if lower || expired {
+            self.min_rtt = Some(rtt);
+            self.min_rtt_stamp = Some(now);
+        }
+        if expired {
+            self.min_rtt_expired = true;
+        }
+    }
+
+    fn get_pacing_rate(&self) -> Option<u64> {
+        let bw = match self.get_max_bw() {
+            Some(v) => v,
+            None => self.cwnd as f64 / self.srtt?.as_secs_f64(),
+        };
+        Some((self.get_pacing_gain() * bw) as u64)
+    }
+}
+
+// Acknowledges a window in MTU-sized packets over a bottleneck of `bw` bytes
+// per second. The sender keeps the window full as far as the pacing rate
+// allows, and a window beyond the BDP queues up and lengthens the RTT.
+#[cfg(test)]
+fn test_bbr_round(bbr: &mut SctpBbr, now: &mut Instant, bw: f64, base_rtt: Duration) {
+    let mtu = bbr.mtu;
+    let window = match bbr.get_pacing_rate() {
+        Some(rate) => std::cmp::min(
+            bbr.get_cwnd(),
+            (rate as f64 * base_rtt.as_secs_f64()) as usize,
+        ),
+        None => bbr.get_cwnd(),
+    };
+    let rtt = base_rtt.max(Duration::from_secs_f64(window as f64 / bw));
+    let packets = std::cmp::max(window / mtu, 1);
+    for _ in 0..packets {
+        *now += rtt / packets as u32;
+        bbr.on_rtt_sample(rtt, rtt, *now);
+        bbr.on_ack(mtu, window.saturating_sub(mtu), false, *now);
+    }
+}
+
+#[test]
+fn test_bbr_startup() {
+    let mut now = Instant::now();
+    let mtu = 1000;
+    let mut bbr = SctpBbr::new(mtu);
+    // 10 MB/s, with a BDP of 500 kB
+    let bw = 10_000_000.0;
+    let base_rtt = Duration::from_millis(50);
+    assert_eq!(bbr.get_pacing_rate(), None);
+
+    let mut rounds = 0;
+    while bbr.get_mode() == SctpBbrMode::Startup {
+        test_bbr_round(&mut bbr, &mut now, bw, base_rtt);
+        rounds += 1;
+        assert!(rounds < 30);
+    }
+    let max_bw = bbr.get_max_bw().unwrap();
+    assert!(max_bw > bw * 0.9 && max_bw <= bw * 1.01);
+    assert_eq!(bbr.get_min_rtt(), Some(base_rtt));
+
+    for _ in 0..10 {
+        test_bbr_round(&mut bbr, &mut now, bw, base_rtt);
+    }
+    assert_eq!(bbr.get_mode(), SctpBbrMode::ProbeBw);
+    // Twice the BDP
+    let cwnd = bbr.get_cwnd();
+    assert!(cwnd > 900_000 && cwnd <= 1_010_000);
+    let pacing_rate = bbr.get_pacing_rate().unwrap() as f64;
+    assert!(pacing_rate >= max_bw * 0.75 && pacing_rate <= max_bw * 1.25);
+}
+
+#[test]
+fn test_bbr_random_loss() {
+    let mut now = Instant::now();
+    let mut bbr = SctpBbr::new(1000);
+    let bw = 10_000_000.0;
+    let base_rtt = Duration::from_millis(50);
+    for _ in 0..30 {
+        test_bbr_round(&mut bbr, &mut now, bw, base_rtt);
+    }
+    let cwnd = bbr.get_cwnd();
+    bbr.on_congestion_event(now);
+    assert_eq!(bbr.get_cwnd(), cwnd);
+
+    // The window comes back once data is acknowledged after a timeout.
+    bbr.on_rto(now);
+    assert_eq!(bbr.get_cwnd(), 1000);
+    bbr.on_ack(1000, 0, false, now);
+    assert_eq!(bbr.get_ssthresh(), usize::MAX);
+    assert_eq!(bbr.get_cwnd(), cwnd);
+}
+
+#[test]
+fn test_bbr_probe_rtt() {
+    let mut now = Instant::now();
+    let mtu = 1000;
+    let mut bbr = SctpBbr::new(mtu);
+    let bw = 10_000_000.0;
+    for _ in 0..20 {
+        test_bbr_round(&mut bbr, &mut now, bw, Duration::from_millis(50));
+    }
+    assert_eq!(bbr.get_mode(), SctpBbrMode::ProbeBw);
+    let cwnd = bbr.get_cwnd();
+
+    // Competing traffic keeps a queue, so the min RTT is not seen again.
+    let base_rtt = Duration::from_millis(70);
+    let start = now;
+    while bbr.get_mode() != SctpBbrMode::ProbeRtt {
+        test_bbr_round(&mut bbr, &mut now, bw, base_rtt);
+        assert!(now.duration_since(start) < Duration::from_secs(11));
+    }
+    test_bbr_round(&mut bbr, &mut now, bw, base_rtt);
+    assert_eq!(bbr.get_cwnd(), 4 * mtu);
+
+    while bbr.get_mode() == SctpBbrMode::ProbeRtt {
+        test_bbr_round(&mut bbr, &mut now, bw, base_rtt);
+    }
+    assert_eq!(bbr.get_mode(), SctpBbrMode::ProbeBw);
+    assert_eq!(bbr.get_min_rtt(), Some(base_rtt));
+    test_bbr_round(&mut bbr, &mut now, bw, base_rtt);
+    assert!(bbr.get_cwnd() > cwnd);
+}
@@ -0,0 +1,173 @@
+use std::time::{Duration, Instant};
+
+use crate::sctp_bbr::SctpBbr;
+use crate::sctp_cubic::SctpCubic;
+
+// The congestion control algorithm of a path. Sizes are in bytes of DATA
+// chunks, as counted in flight, and the MTU excludes the IP, UDP and SCTP
+// common headers.
+pub trait CongestionController: std::fmt::Debug + Send {
+    fn get_cwnd(&self) -> usize;
+
+    fn get_ssthresh(&self) -> usize;
+
+    fn get_partial_bytes_acked(&self) -> usize {
+        0
+    }
+
+    // A SACK acknowledged `acked` bytes outstanding on the path, leaving
+    // `flight` bytes. `in_recovery` is set during fast recovery.
+    fn on_ack(&mut self, acked: usize, flight: usize, in_recovery: bool, now: Instant);
+
+    // An ACK while the path did not use its cwnd (RFC 7661), which then must
+    // not grow
+    fn on_app_limited_ack(&mut self, _acked: usize, _flight: usize, _now: Instant) {}
+
+    // Fast retransmit, at most once per window of data
+    fn on_congestion_event(&mut self, now: Instant);
+
+    // The T3-rtx timer expired.
+    fn on_rto(&mut self, now: Instant);
+
+    // The path was idle for an RTO.
+    fn on_idle(&mut self, now: Instant);
+
+    fn on_mtu_changed(&mut self, mtu: usize);
+
+    // An RTT measurement of the path and the smoothed RTT including it
+    fn on_rtt_sample(&mut self, _rtt: Duration, _srtt: Duration, _now: Instant) {}
+
+    // The rate in bytes per second to pace packets at, if the algorithm
+    // has one
+    fn get_pacing_rate(&self) -> Option<u64> {
+        None
+    }
+}
+
+// The congestion control algorithm of the paths of an association
+#[derive(Clone, Copy, Debug)]
+pub enum SctpCongestionControl {
+    Reno,
+    // CUBIC (RFC 9438) with HyStart++ (RFC 9406)
+    Cubic,
+    // A BBR-style model of the bottleneck bandwidth and min RTT
+    Bbr,
+    // A user-supplied algorithm, created for each path with its MTU
+    Custom(fn(usize) -> Box<dyn CongestionController>),
+}
+
+impl SctpCongestionControl {
+    pub fn new_controller(&self, mtu: usize) -> Box<dyn CongestionController> {
+        match self {
+            SctpCongestionControl::Reno => Box::new(SctpReno::new(mtu)),
+            SctpCongestionControl::Cubic => Box::new(SctpCubic::new(mtu)),
+            SctpCongestionControl::Bbr => Box::new(SctpBbr::new(mtu)),
+            SctpCongestionControl::Custom(new) => new(mtu),
+        }
+    }
+}
+
+// The congestion control of RFC 9260 Section 7.2
+#[derive(Debug)]
+pub struct SctpReno {
+    mtu: usize,
+    cwnd: usize,
+    ssthresh: usize,
+    partial_bytes_acked: usize,
+}
+
+impl SctpReno {
+    pub fn new(mtu: usize) -> Self {
+        SctpReno {
+            mtu,
+            cwnd: mtu * 4,
+            ssthresh: usize::MAX,
+            partial_bytes_acked: 0,
+        }
+    }
+}
+
+impl CongestionController for SctpReno {
+    fn get_cwnd(&self) -> usize {
+        self.cwnd
+    }
+
+    fn get_ssthresh(&self) -> usize {
+        self.ssthresh
+    }
+
+    fn get_partial_bytes_acked(&self) -> usize {
+        self.partial_bytes_acked
+    }
+
+    fn on_ack(&mut self, acked: usize, flight: usize, in_recovery: bool, _now: Instant) {
+        if in_recovery {
+            return;
+        }
+        if self.cwnd <= self.ssthresh {
+            if flight + acked >= self.cwnd {
+                self.cwnd += std::cmp::max(acked, self.mtu);
+            }
+        } else {
+            self.partial_bytes_acked += acked;
+            if self.partial_bytes_acked >= self.cwnd {
+                self.cwnd += self.mtu;
+                self.partial_bytes_acked = self.partial_bytes_acked.saturating_sub(self.cwnd);
+            }
+        }
+    }
+
+    fn on_congestion_event(&mut self, _now: Instant) {
+        self.ssthresh = std::cmp::max(self.cwnd / 2, 4 * self.mtu);
+        self.cwnd = self.ssthresh;
+    }
+
+    fn on_rto(&mut self, _now: Instant) {
+        self.ssthresh = std::cmp::max(self.mtu.saturating_mul(4), self.cwnd / 2);
+        self.cwnd = self.mtu;
+    }
+
+    fn on_idle(&mut self, _now: Instant) {
+        self.cwnd = std::cmp::max(self.cwnd / 2, 4 * self.mtu);
+    }
+
+    fn on_mtu_changed(&mut self, mtu: usize) {
+        self.mtu = mtu;
+    }
+}
+
+#[test]
+fn test_reno() {
+    let now = Instant::now();
+    let mut reno = SctpReno::new(1000);
+    assert_eq!(reno.get_cwnd(), 4000);
+
+    // Slow start only while the cwnd is fully used
+    reno.on_ack(1000, 0, false, now);
+    assert_eq!(reno.get_cwnd(), 4000);
+    reno.on_ack(1000, 3000, false, now);
+    assert_eq!(reno.get_cwnd(), 5000);
+
+    reno.on_congestion_event(now);
+    assert_eq!(reno.get_ssthresh(), 4000);
+    assert_eq!(reno.get_cwnd(), 4000);
+    reno.on_ack(4000, 4000, true, now);
+    assert_eq!(reno.get_cwnd(), 4000);
+
+    // Slow start up to ssthresh, then one MTU per cwnd of acknowledged bytes
+    reno.on_ack(1000, 3000, false, now);
+    assert_eq!(reno.get_cwnd(), 5000);
+    reno.on_ack(4000, 5000, false, now);
+    assert_eq!(reno.get_cwnd(), 5000);
+    assert_eq!(reno.get_partial_bytes_acked(), 4000);
+    reno.on_ack(1000, 5000, false, now);
+    assert_eq!(reno.get_cwnd(), 6000);
+
+    reno.on_rto(now);
+    assert_eq!(reno.get_ssthresh(), 4000);
+    assert_eq!(reno.get_cwnd(), 1000);
+
+    reno.on_mtu_changed(1200);
+    reno.on_idle(now);
+    assert_eq!(reno.get_cwnd(), 4800);
+}
@@ -0,0 +1,427 @@
+use std::time::{Duration, Instant};
+
+use crate::sctp_congestion::CongestionController;
+
+// RFC 9438 Section 5.1
+const CUBIC_C: f64 = 0.4;
+const CUBIC_BETA: f64 = 0.7;
+
+// RFC 9406 Section 4.3
+const MIN_RTT_THRESH: Duration = Duration::from_millis(4);
+const MAX_RTT_THRESH: Duration = Duration::from_millis(16);
+const MIN_RTT_DIVISOR: u32 = 8;
+const N_RTT_SAMPLE: u32 = 8;
+const CSS_GROWTH_DIVISOR: usize = 4;
+const CSS_ROUNDS: u32 = 5;
+// The slow start increase limit L without pacing
+const SS_LIMIT: usize = 8;
+
+// HyStart++ leaves slow start once the RTT grows, through Conservative Slow
+// Start (CSS) to tell a real increase from jitter.
+#[derive(Debug)]
+struct SctpHystart {
+    // A round ends once the bytes in flight at its start are acknowledged.
+    round_remaining: usize,
+    last_round_min_rtt: Option<Duration>,
+    current_round_min_rtt: Option<Duration>,
+    rtt_sample_count: u32,
+    // Set while in CSS
+    css_baseline_min_rtt: Option<Duration>,
+    css_rounds: u32,
+}
+
+impl SctpHystart {
+    fn new() -> Self {
+        SctpHystart {
+            round_remaining: 0,
+            last_round_min_rtt: None,
+            current_round_min_rtt: None,
+            rtt_sample_count: 0,
+            css_baseline_min_rtt: None,
+            css_rounds: 0,
+        }
+    }
+
+    fn is_css(&self) -> bool {
+        self.css_baseline_min_rtt.is_some()
+    }
+
+    fn on_rtt_sample(&mut self, rtt: Duration) {
+        self.current_round_min_rtt = Some(match self.current_round_min_rtt {
+            Some(v) => std::cmp::min(v, rtt),
+            None => rtt,
+        });
+        self.rtt_sample_count += 1;
+    }
+
+    // Returns true when slow start should end.
+    fn on_ack(&mut self, acked: usize, flight: usize) -> bool {
+        if self.rtt_sample_count >= N_RTT_SAMPLE {
+            if let (Some(current), Some(last)) =
+                (self.current_round_min_rtt, self.last_round_min_rtt)
+            {
+                match self.css_baseline_min_rtt {
+                    None => {
+                        let thresh = (last / MIN_RTT_DIVISOR).clamp(MIN_RTT_THRESH, MAX_RTT_THRESH);
+                        if current >= last + thresh {
+                            self.css_baseline_min_rtt = Some(current);
+                            self.css_rounds = 0;
+                        }
+                    }
+                    Some(baseline) => {
+                        // The RTT increase was spurious.
+                        if current < baseline {
+                            self.css_baseline_min_rtt = None;
+                        }
+                    }
+                }
+            }
+        }
+
+        self.round_remaining = self.round_remaining.saturating_sub(acked);
+        if self.round_remaining > 0 {
+            return false;
+        }
+        self.round_remaining = flight;
+        self.last_round_min_rtt = self.current_round_min_rtt.take();
+        self.rtt_sample_count = 0;
+        if self.is_css() {
+            self.css_rounds += 1;
+            return self.css_rounds >= CSS_ROUNDS;
+        }
+        false
+    }
+}
+
+// CUBIC congestion control (RFC 9438). The window grows with the time since
+// the last congestion event, which recovers a large window much faster than
+// one MTU per RTT, and not slower than Reno would.
+#[derive(Debug)]
+pub struct SctpCubic {
+    mtu: usize,
+    cwnd: usize,
+    ssthresh: usize,
+    // Growth smaller than a byte, carried over to the next ACK
+    cwnd_carry: f64,
+
+    // W_max, the window before the last congestion event
+    w_max: f64,
+    // The time W_cubic takes to grow back to W_max
+    k: f64,
+    // The window Reno would reach since the congestion event
+    w_est: f64,
+    epoch_start: Option<Instant>,
+    srtt: Option<Duration>,
+
+    // Only in the initial slow start
+    hystart: Option<SctpHystart>,
+}
+
+impl SctpCubic {
+    pub fn new(mtu: usize) -> Self {
+        SctpCubic {
+            mtu,
+            cwnd: mtu * 4,
+            ssthresh: usize::MAX,
+            cwnd_carry: 0.0,
+            w_max: 0.0,
+            k: 0.0,
+            w_est: 0.0,
+            epoch_start: None,
+            srtt: None,
+            hystart: Some(SctpHystart::new()),
+        }
+    }
+
+    // W_cubic(t) in bytes, t in seconds since the epoch started
+    fn w_cubic(&self, t: f64) -> f64 {
+        CUBIC_C * (t - self.k).powi(3) * self.mtu as f64 + self.w_max
+    }
+
+    fn slow_start(&mut self, acked: usize, flight: usize) {
+        let (increment, exit) = match self.hystart.as_mut() {
+            Some(hystart) => {
+                let exit = hystart.on_ack(acked, flight);
+                if hystart.is_css() {
+                    (acked / CSS_GROWTH_DIVISOR, exit)
+                } else {
+                    (std::cmp::min(acked, SS_LIMIT * self.mtu), exit)
+                }
+            }
+            None => (std::cmp::min(acked, SS_LIMIT * self.mtu), false),
+        };
+        if flight + acked >= self.cwnd {
+            self.cwnd += increment;
+        }
+        if exit {
+            // Congestion avoidance starts from the current window.
+            self.hystart = None;
+            self.ssthresh = self.cwnd;
+            self.w_max = self.cwnd as f64;
+            self.epoch_start = None;
+        }
+    }
+
+    fn congestion_avoidance(&mut self, acked: usize, now: Instant) {
+        let cwnd = self.cwnd as f64;
+        let mtu = self.mtu as f64;
+        let epoch_start = match self.epoch_start {
+            Some(v) => v,
+            None => {
+                if cwnd < self.w_max {
+                    self.k = ((self.w_max - cwnd) / (CUBIC_C * mtu)).cbrt();
+                } else {
+                    self.k = 0.0;
+                    self.w_max = cwnd;
+                }
+                self.w_est = cwnd;
+                self.epoch_start = Some(now);
+                now
+            }
+        };
+        let t = now.duration_since(epoch_start);
+        let rtt = self.srtt.unwrap_or_default();
+
+        // RFC 9438 Section 4.3, the Reno-friendly region
+        let alpha = if self.w_est >= self.w_max {
+            1.0
+        } else {
+            3.0 * (1.0 - CUBIC_BETA) / (1.0 + CUBIC_BETA)
+        };
+        self.w_est += alpha * mtu * acked as f64 / cwnd;
+
+        let increment = if self.w_cubic(t.as_secs_f64()) < self.w_est {
+            (self.w_est - cwnd).max(0.0)
+        } else {
+            // RFC 9438 Sections 4.4 and 4.5, the concave and convex regions
+            let target = self
+                .w_cubic((t + rtt).as_secs_f64())
+                .clamp(cwnd, 1.5 * cwnd);
+            (target - cwnd) * acked as f64 / cwnd
+        };
+        self.cwnd_carry += increment;
+        let whole = self.cwnd_carry.floor();
+        self.cwnd += whole as usize;
+        self.cwnd_carry -= whole;
+    }
+
+    // RFC 9438 Sections 4.6 and 4.7, with fast convergence
+    fn reduce(&mut self) {
+        let cwnd = self.cwnd as f64;
+        self.w_max = if cwnd < self.w_max {
+            cwnd * (1.0 + CUBIC_BETA) / 2.0
+        } else {
+            cwnd
+        };
+        self.ssthresh = std::cmp::max((cwnd * CUBIC_BETA) as usize, 4 * self.mtu);
+        self.epoch_start = None;
+        self.cwnd_carry = 0.0;
+        self.hystart = None;
+    }
+}
+
+impl CongestionController for SctpCubic {
+    fn get_cwnd(&self) -> usize {
+        self.cwnd
+    }
+
+    fn get_ssthresh(&self) -> usize {
+        self.ssthresh
+    }
+
+    fn on_ack(&mut self, acked: usize, flight: usize, in_recovery: bool, now: Instant) {
+        if in_recovery || acked == 0 {
+            return;
+        }
+        if self.cwnd <= self.ssthresh {
+            self.slow_start(acked, flight);
+        } else {
+            self.congestion_avoidance(acked, now);
+        }
+    }
+
+    // The time spent application limited does not count toward the growth
+    // of W_cubic (RFC 9438 Section 5.8).
+    fn on_app_limited_ack(&mut self, _acked: usize, _flight: usize, _now: Instant) {
+        self.epoch_start = None;
+    }
+
+    fn on_congestion_event(&mut self, _now: Instant) {
+        self.reduce();
+        self.cwnd = self.ssthresh;
+    }
+
+    fn on_rto(&mut self, _now: Instant) {
+        self.reduce();
+        self.cwnd = self.mtu;
+    }
+
+    fn on_idle(&mut self, _now: Instant) {
+        self.cwnd = std::cmp::max(self.cwnd / 2, 4 * self.mtu);
+        self.epoch_start = None;
+    }
+
+    fn on_mtu_changed(&mut self, mtu: usize) {
+        self.mtu = mtu;
+    }
+
+    fn on_rtt_sample(&mut self, rtt: Duration, srtt: Duration, _now: Instant) {
+        self.srtt = Some(srtt);
+        if let Some(hystart) = self.hystart.as_mut() {
+            hystart.on_rtt_sample(rtt);
+        }
+    }
+}
+
+// Acknowledges a full window in MTU-sized packets, each with an RTT sample,
+// as the sender keeps the window full.
+#[cfg(test)]
+fn test_cubic_round(cubic: &mut SctpCubic, now: &mut Instant, rtt: Duration) {
+    let cwnd = cubic.get_cwnd();
+    let mtu = cubic.mtu;
+    *now += rtt;
+    let mut acked = 0;
+    while acked + mtu <= cwnd {
+        cubic.on_rtt_sample(rtt, rtt, *now);
+        let flight = cubic.get_cwnd() - mtu;
+        cubic.on_ack(mtu, flight, false, *now);
+        acked += mtu;
+    }
+}
+
+#[test]
+fn test_cubic_hystart() {
+    let mut now = Instant::now();
+    let mut cubic = SctpCubic::new(1000);
+    let rtt = Duration::from_millis(50);
+    for _ in 0..4 {
+        test_cubic_round(&mut cubic, &mut now, rtt);
+    }
+    assert!(matches!(&cubic.hystart, Some(v) if !v.is_css()));
+    assert_eq!(cubic.get_ssthresh(), usize::MAX);
+
+    // An RTT increase over 50 / 8 ms starts CSS, which grows a quarter as fast.
+    let rtt = Duration::from_millis(60);
+    test_cubic_round(&mut cubic, &mut now, rtt);
+    assert!(matches!(&cubic.hystart, Some(v) if v.is_css()));
+    let cwnd = cubic.get_cwnd();
+    test_cubic_round(&mut cubic, &mut now, rtt);
+    assert!(cubic.get_cwnd() - cwnd <= cwnd / 4 + 1000);
+
+    // Slow start ends after CSS_ROUNDS.
+    while cubic.get_ssthresh() == usize::MAX {
+        test_cubic_round(&mut cubic, &mut now, rtt);
+    }
+    assert!(cubic.hystart.is_none());
+    assert!(cubic.get_cwnd() - cubic.get_ssthresh() < 2 * 1000);
+}
+
+#[test]
+fn test_cubic_hystart_spurious() {
+    let mut now = Instant::now();
+    let mut cubic = SctpCubic::new(1000);
+    for _ in 0..4 {
+        test_cubic_round(&mut cubic, &mut now, Duration::from_millis(50));
+    }
+    test_cubic_round(&mut cubic, &mut now, Duration::from_millis(60));
+    assert!(matches!(&cubic.hystart, Some(v) if v.is_css()));
+
+    // The RTT is back below the CSS baseline.
+    test_cubic_round(&mut cubic, &mut now, Duration::from_millis(50));
+    test_cubic_round(&mut cubic, &mut now, Duration::from_millis(50));
+    assert!(matches!(&cubic.hystart, Some(v) if !v.is_css()));
+    assert_eq!(cubic.get_ssthresh(), usize::MAX);
+}
+
+#[test]
+fn test_cubic_window_growth() {
+    let mut now = Instant::now();
+    let mtu = 1000;
+    let mut cubic = SctpCubic::new(mtu);
+    let rtt = Duration::from_millis(100);
+    while cubic.get_cwnd() < 100 * mtu {
+        test_cubic_round(&mut cubic, &mut now, Duration::from_millis(100));
+    }
+    let w_max = cubic.get_cwnd();
+    cubic.on_congestion_event(now);
+    assert_eq!(cubic.get_cwnd(), w_max * 7 / 10);
+
+    // Concave growth back to W_max within K, about 4.5 s here
+    test_cubic_round(&mut cubic, &mut now, rtt);
+    let mut rounds = 1;
+    let mut last_increment = w_max;
+    while cubic.get_cwnd() < w_max * 99 / 100 {
+        let cwnd = cubic.get_cwnd();
+        test_cubic_round(&mut cubic, &mut now, rtt);
+        assert!(cubic.get_cwnd() - cwnd <= last_increment + mtu);
+        last_increment = cubic.get_cwnd() - cwnd;
+        rounds += 1;
+    }
+    assert!(rounds > 20 && rounds <= 45);
+
+    // Convex growth beyond it
+    for _ in 0..30 {
+        test_cubic_round(&mut cubic, &mut now, rtt);
+    }
+    let cwnd = cubic.get_cwnd();
+    test_cubic_round(&mut cubic, &mut now, rtt);
+    let increment = cubic.get_cwnd() - cwnd;
+    test_cubic_round(&mut cubic, &mut now, rtt);
+    assert!(cubic.get_cwnd() - cwnd - increment > increment);
+
+    // Fast convergence lowers W_max after consecutive reductions.
+    cubic.on_congestion_event(now);
+    let cwnd = cubic.get_cwnd();
+    cubic.on_congestion_event(now);
+    assert!(cubic.w_max < cwnd as f64);
+    assert!(cubic.get_ssthresh() >= 4 * mtu);
+}
+
+#[test]
+fn test_cubic_reno_friendly() {
+    let mut now = Instant::now();
+    let mtu = 1000;
+    let mut cubic = SctpCubic::new(mtu);
+    while cubic.get_cwnd() < 20 * mtu {
+        test_cubic_round(&mut cubic, &mut now, Duration::from_millis(1));
+    }
+    cubic.on_congestion_event(now);
+    let cwnd = cubic.get_cwnd();
+
+    // With a short RTT the cubic function grows slower than Reno would, so
+    // the window follows the Reno estimate of about 0.53 MTU per RTT.
+    for _ in 0..15 {
+        test_cubic_round(&mut cubic, &mut now, Duration::from_millis(1));
+    }
+    let increment = cubic.get_cwnd() - cwnd;
+    assert!(increment >= 7 * mtu && increment <= 10 * mtu);
+    let t = now.duration_since(cubic.epoch_start.unwrap());
+    assert!(cubic.w_cubic(t.as_secs_f64()) < cubic.get_cwnd() as f64);
+
+    // And of one MTU per RTT beyond W_max
+    for _ in 0..5 {
+        test_cubic_round(&mut cubic, &mut now, Duration::from_millis(1));
+    }
+    let cwnd = cubic.get_cwnd();
+    test_cubic_round(&mut cubic, &mut now, Duration::from_millis(1));
+    let increment = cubic.get_cwnd() - cwnd;
+    assert!(increment >= mtu * 9 / 10 && increment <= mtu);
+}
+
+#[test]
+fn test_cubic_timeout() {
+    let now = Instant::now();
+    let mtu = 1000;
+    let mut cubic = SctpCubic::new(mtu);
+    cubic.cwnd = 20 * mtu;
+    cubic.on_rto(now);
+    assert_eq!(cubic.get_cwnd(), mtu);
+    assert_eq!(cubic.get_ssthresh(), 14 * mtu);
+    assert!(cubic.hystart.is_none());
+
+    // No growth during fast recovery
+    cubic.on_ack(mtu, 0, true, now);
+    assert_eq!(cubic.get_cwnd(), mtu);
+    cubic.on_ack(mtu, 0, false, now);
+    assert_eq!(cubic.get_cwnd(), 2 * mtu);
+}
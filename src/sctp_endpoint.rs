@@ -0,0 +1,568 @@
+use std::collections::hash_map::{Values, ValuesMut};
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::time::Duration;
+
+use crc::crc32;
+
+use crate::Result;
+use crate::SctpError;
+use crate::{SctpAssociation, SctpAssociationState, SctpRecvInfo, SctpSendInfo};
+
+pub use crate::sctp_pkt::*;
+
+const SECRET_KEY_LEN: usize = 32;
+
+pub struct SctpEndpoint {
+    port: u16,
+    laddr_list: Vec<IpAddr>,
+    secret_key: Vec<u8>,
+
+    assoc_map: HashMap<u32, SctpAssociation>,
+    accept_queue: VecDeque<u32>,
+    send_queue: VecDeque<u32>,
+}
+
+impl SctpEndpoint {
+    pub fn new(port: u16, laddr_list: &[IpAddr]) -> Result<SctpEndpoint> {
+        let endpoint = SctpEndpoint {
+            port,
+            laddr_list: laddr_list.to_vec(),
+            secret_key: (0..SECRET_KEY_LEN).map(|_| rand::random::<u8>()).collect(),
+            assoc_map: HashMap::new(),
+            accept_queue: VecDeque::new(),
+            send_queue: VecDeque::new(),
+        };
+        Ok(endpoint)
+    }
+
+    pub fn get_port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn get_laddrs(&self) -> &Vec<IpAddr> {
+        &self.laddr_list
+    }
+
+    pub fn connect(&mut self, dst_port: u16, dst_ip: &IpAddr) -> Result<u32> {
+        if let Some(assoc) = self.assoc_map.values().find(|assoc| {
+            !assoc.is_closed() && assoc.dst_port == dst_port && assoc.get_pathid(dst_ip).is_some()
+        }) {
+            return Err(SctpError::InvalidState(assoc.state));
+        }
+
+        let mut assoc = SctpAssociation::connect(self.port, dst_port, &self.laddr_list, dst_ip)?;
+        while self.assoc_map.contains_key(&assoc.my_vtag) {
+            assoc = SctpAssociation::connect(self.port, dst_port, &self.laddr_list, dst_ip)?;
+        }
+        let id = assoc.my_vtag;
+        self.assoc_map.insert(id, assoc);
+        self.send_queue.push_back(id);
+        Ok(id)
+    }
+
+    // Returns an association established by a peer, if any.
+    pub fn accept(&mut self) -> Option<u32> {
+        while let Some(id) = self.accept_queue.pop_front() {
+            if self.assoc_map.contains_key(&id) {
+                return Some(id);
+            }
+        }
+        None
+    }
+
+    pub fn get(&self, id: u32) -> Option<&SctpAssociation> {
+        self.assoc_map.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: u32) -> Option<&mut SctpAssociation> {
+        self.assoc_map.get_mut(&id)
+    }
+
+    pub fn iter(&self) -> Values<'_, u32, SctpAssociation> {
+        self.assoc_map.values()
+    }
+
+    pub fn iter_mut(&mut self) -> ValuesMut<'_, u32, SctpAssociation> {
+        self.assoc_map.values_mut()
+    }
+
+    // Removes closed associations and hands them over to the caller.
+    pub fn take_closed(&mut self) -> Vec<SctpAssociation> {
+        let closed: Vec<u32> = self
+            .assoc_map
+            .iter()
+            .filter(|(_, assoc)| assoc.is_closed())
+            .map(|(id, _)| *id)
+            .collect();
+        self.send_queue.retain(|id| !closed.contains(id));
+        self.accept_queue.retain(|id| !closed.contains(id));
+        closed
+            .iter()
+            .filter_map(|id| self.assoc_map.remove(id))
+            .collect()
+    }
+
+    // Any response written into sbuf must be sent back to the sender even if an error is returned.
+    pub fn recv(&mut self, from: &IpAddr, rbuf: &[u8], sbuf: &mut Vec<u8>) -> Result<usize> {
+        let info = SctpRecvInfo {
+            from: *from,
+            ce: false,
+        };
+        self.recv_with_info(rbuf, sbuf, &info)
+    }
+
+    pub fn recv_with_info(
+        &mut self,
+        rbuf: &[u8],
+        sbuf: &mut Vec<u8>,
+        info: &SctpRecvInfo,
+    ) -> Result<usize> {
+        let from = &info.from;
+        let (header, mut off) = SctpCommonHeader::from_bytes(rbuf)?;
+        if header.dst_port != self.port {
+            trace!("discard packet to port={}", header.dst_port);
+            return Err(SctpError::OOTB);
+        }
+        if !SctpEndpoint::verify_checksum(rbuf) {
+            trace!("discard packet with invalid checksum from={}", from);
+            return Err(SctpError::InvalidChecksum);
+        }
+        let (chunk, _) = SctpChunk::from_bytes(&rbuf[off..]).map_err(|e| e.add_offset(off))?;
+
+        if let Some(id) = self.find_association(from, &header, &chunk, &rbuf[off..]) {
+            let assoc = self.assoc_map.get_mut(&id).unwrap();
+            while off < rbuf.len() && !assoc.is_closed() {
+                off += assoc
+                    .recv_with_info(&rbuf[off..], sbuf, info)
+                    .map_err(|e| e.add_offset(off))?;
+            }
+            return Ok(off);
+        }
+
+        let (assoc, consumed) = SctpAssociation::accept_with_laddrs(
+            from,
+            &header,
+            &rbuf[off..],
+            sbuf,
+            &self.secret_key,
+            &self.laddr_list,
+        )
+        .map_err(|e| e.add_offset(off))?;
+        off += consumed;
+
+        let assoc = match assoc {
+            Some(v) => v,
+            None => {
+                return Ok(rbuf.len());
+            }
+        };
+        let id = assoc.my_vtag;
+        if self.assoc_map.contains_key(&id) {
+            trace!("discard COOKIE-ECHO for closed association vtag={}", id);
+            return Ok(rbuf.len());
+        }
+        self.assoc_map.insert(id, assoc);
+        self.accept_queue.push_back(id);
+        self.send_queue.push_back(id);
+
+        let assoc = self.assoc_map.get_mut(&id).unwrap();
+        while off < rbuf.len() && !assoc.is_closed() {
+            off += assoc
+                .recv_with_info(&rbuf[off..], sbuf, info)
+                .map_err(|e| e.add_offset(off))?;
+        }
+        Ok(off)
+    }
+
+    fn find_association(
+        &self,
+        from: &IpAddr,
+        header: &SctpCommonHeader,
+        chunk: &SctpChunk,
+        chunks: &[u8],
+    ) -> Option<u32> {
+        let is_peer = |assoc: &SctpAssociation| {
+            !assoc.is_closed()
+                && assoc.dst_port == header.src_port
+                && assoc.get_pathid(from).is_some()
+        };
+
+        match chunk {
+            // An INIT carries no tag. Only an association waiting for SHUTDOWN-COMPLETE handles it,
+            // otherwise it is answered statelessly.
+            SctpChunk::Init(_) => self
+                .assoc_map
+                .values()
+                .find(|assoc| {
+                    is_peer(assoc) && assoc.state == SctpAssociationState::ShutdownAckSent
+                })
+                .map(|assoc| assoc.my_vtag),
+            // The T bit means that the tag is reflected from the peer.
+            SctpChunk::Abort(SctpAbortChunk { t_bit: true, .. })
+            | SctpChunk::ShutdownComplete(true) => self
+                .assoc_map
+                .values()
+                .find(|assoc| is_peer(assoc) && assoc.peer_vtag == header.vtag)
+                .map(|assoc| assoc.my_vtag),
+            // An authenticated ASCONF may come from an address which the peer is adding.
+            SctpChunk::Auth(_) => match self.assoc_map.get(&header.vtag) {
+                Some(assoc)
+                    if is_peer(assoc)
+                        || (!assoc.is_closed()
+                            && assoc.dst_port == header.src_port
+                            && assoc.find_asconf_path(chunks).is_some()) =>
+                {
+                    Some(header.vtag)
+                }
+                _ => None,
+            },
+            _ => match self.assoc_map.get(&header.vtag) {
+                Some(assoc) if is_peer(assoc) => Some(header.vtag),
+                _ => None,
+            },
+        }
+    }
+
+    fn verify_checksum(rbuf: &[u8]) -> bool {
+        let mut bytes = rbuf.to_vec();
+        let checksum = (bytes[0x08] as u32)
+            | (bytes[0x09] as u32) << 8
+            | (bytes[0x0a] as u32) << 16
+            | (bytes[0x0b] as u32) << 24;
+        for byte in bytes[0x08..0x0c].iter_mut() {
+            *byte = 0;
+        }
+        crc32::checksum_castagnoli(&bytes) == checksum
+    }
+
+    // Associations take turns so that one of them cannot starve the others.
+    pub fn send(&mut self, sbuf: &mut Vec<u8>) -> Result<(usize, IpAddr)> {
+        self.send_with_info(sbuf)
+            .map(|(written, info)| (written, info.to))
+    }
+
+    pub fn send_with_info(&mut self, sbuf: &mut Vec<u8>) -> Result<(usize, SctpSendInfo)> {
+        for _ in 0..self.send_queue.len() {
+            let id = *self.send_queue.front().unwrap();
+            let assoc = self.assoc_map.get_mut(&id).unwrap();
+            if !assoc.is_closed() {
+                let res = assoc.send_with_info(sbuf);
+                self.send_queue.rotate_left(1);
+                match res {
+                    Ok(v) => {
+                        return Ok(v);
+                    }
+                    Err(SctpError::Done) => {}
+                    Err(e) => {
+                        return Err(e);
+                    }
+                }
+            } else {
+                self.send_queue.rotate_left(1);
+            }
+        }
+        Err(SctpError::Done)
+    }
+
+    // ICMP messages go to the association that sent the quoted packet.
+    fn find_icmp_association(&self, dst: &IpAddr, quoted_header: &[u8]) -> Result<u32> {
+        self.assoc_map
+            .values()
+            .find(|assoc| !assoc.is_closed() && assoc.validate_icmp(dst, quoted_header).is_ok())
+            .map(|assoc| assoc.my_vtag)
+            .ok_or(SctpError::OOTB)
+    }
+
+    pub fn on_icmp_packet_too_big(
+        &mut self,
+        dst: &IpAddr,
+        mtu: usize,
+        quoted_header: &[u8],
+    ) -> Result<()> {
+        let id = self.find_icmp_association(dst, quoted_header)?;
+        self.assoc_map
+            .get_mut(&id)
+            .unwrap()
+            .on_icmp_packet_too_big(dst, mtu, quoted_header)
+    }
+
+    pub fn on_icmp_unreachable(&mut self, dst: &IpAddr, quoted_header: &[u8]) -> Result<()> {
+        let id = self.find_icmp_association(dst, quoted_header)?;
+        self.assoc_map
+            .get_mut(&id)
+            .unwrap()
+            .on_icmp_unreachable(dst, quoted_header)
+    }
+
+    pub fn get_timeout(&self) -> Option<Duration> {
+        self.assoc_map
+            .values()
+            .filter(|assoc| !assoc.is_closed())
+            .filter_map(|assoc| assoc.get_timeout())
+            .min()
+    }
+
+    pub fn on_timeout(&mut self) {
+        for assoc in self.assoc_map.values_mut() {
+            if !assoc.is_closed() {
+                assoc.on_timeout();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn test_exchange(
+    sender: &mut SctpEndpoint,
+    sender_ip: &IpAddr,
+    receiver: &mut SctpEndpoint,
+    receiver_ip: &IpAddr,
+) -> usize {
+    let mut num_pkts = 0;
+    loop {
+        let mut sbuf = Vec::new();
+        match sender.send(&mut sbuf) {
+            Ok(_) => {
+                let mut rbuf = Vec::new();
+                let _ = receiver.recv(sender_ip, &sbuf, &mut rbuf);
+                if !rbuf.is_empty() {
+                    let mut ignored = Vec::new();
+                    let _ = sender.recv(receiver_ip, &rbuf, &mut ignored);
+                }
+                num_pkts += 1;
+            }
+            Err(_) => {
+                break;
+            }
+        }
+    }
+    num_pkts
+}
+
+#[cfg(test)]
+fn test_endpoints() -> (SctpEndpoint, IpAddr, SctpEndpoint, IpAddr) {
+    let client_ip: IpAddr = "192.0.2.1".parse().unwrap();
+    let server_ip: IpAddr = "192.0.2.2".parse().unwrap();
+    let client = SctpEndpoint::new(5001, &vec![client_ip]).unwrap();
+    let server = SctpEndpoint::new(5002, &vec![server_ip]).unwrap();
+    (client, client_ip, server, server_ip)
+}
+
+#[test]
+fn test_endpoint_accept() {
+    let (mut client, client_ip, mut server, server_ip) = test_endpoints();
+
+    let id = client.connect(5002, &server_ip).unwrap();
+    assert_eq!(
+        client.connect(5002, &server_ip),
+        Err(SctpError::InvalidState(SctpAssociationState::CookieWait))
+    );
+
+    // INIT, INIT-ACK, COOKIE-ECHO and COOKIE-ACK
+    for _ in 0..2 {
+        test_exchange(&mut client, &client_ip, &mut server, &server_ip);
+        test_exchange(&mut server, &server_ip, &mut client, &client_ip);
+    }
+    assert!(client.get(id).unwrap().is_established());
+    assert_eq!(client.accept(), None);
+
+    let server_id = server.accept().unwrap();
+    assert_eq!(server.accept(), None);
+    let assoc = server.get(server_id).unwrap();
+    assert!(assoc.is_established());
+    assert_eq!(assoc.peer_vtag, id);
+    assert_eq!(assoc.get_pathid(&client_ip), Some(0));
+
+    client
+        .get_mut(id)
+        .unwrap()
+        .write_into_stream(0, &[1u8; 100], false, true)
+        .unwrap();
+    test_exchange(&mut client, &client_ip, &mut server, &server_ip);
+    let mut rbuf = Vec::new();
+    assert_eq!(
+        server
+            .get_mut(server_id)
+            .unwrap()
+            .read_from_stream(0, &mut rbuf),
+        Ok(100)
+    );
+
+    client.get_mut(id).unwrap().close().unwrap();
+    for _ in 0..2 {
+        test_exchange(&mut client, &client_ip, &mut server, &server_ip);
+        test_exchange(&mut server, &server_ip, &mut client, &client_ip);
+    }
+    assert_eq!(client.take_closed().len(), 1);
+    assert_eq!(server.take_closed().len(), 1);
+    assert!(server.get(server_id).is_none());
+    assert_eq!(server.get_timeout(), None);
+}
+
+#[test]
+fn test_endpoint_multiple_associations() {
+    let (mut client, client_ip, mut server, server_ip) = test_endpoints();
+    let other_ip: IpAddr = "192.0.2.3".parse().unwrap();
+    let mut other = SctpEndpoint::new(5001, &vec![other_ip]).unwrap();
+
+    // Both peers use the same port, so packets are told apart by the tag and address.
+    let id1 = client.connect(5002, &server_ip).unwrap();
+    let id2 = other.connect(5002, &server_ip).unwrap();
+    for _ in 0..2 {
+        test_exchange(&mut client, &client_ip, &mut server, &server_ip);
+        test_exchange(&mut other, &other_ip, &mut server, &server_ip);
+        loop {
+            let mut sbuf = Vec::new();
+            match server.send(&mut sbuf) {
+                Ok((_, ip)) if ip == client_ip => {
+                    client.recv(&server_ip, &sbuf, &mut Vec::new()).unwrap();
+                }
+                Ok(_) => {
+                    other.recv(&server_ip, &sbuf, &mut Vec::new()).unwrap();
+                }
+                Err(_) => {
+                    break;
+                }
+            }
+        }
+    }
+    assert!(client.get(id1).unwrap().is_established());
+    assert!(other.get(id2).unwrap().is_established());
+    let server_id1 = server.accept().unwrap();
+    let server_id2 = server.accept().unwrap();
+    assert_eq!(server.iter().filter(|x| x.is_established()).count(), 2);
+
+    // Both have data pending, and they take turns.
+    for id in &[server_id1, server_id2] {
+        server
+            .get_mut(*id)
+            .unwrap()
+            .write_into_stream(0, &[1u8; 5000], false, true)
+            .unwrap();
+    }
+    let mut dsts = Vec::new();
+    for _ in 0..4 {
+        let mut sbuf = Vec::new();
+        let (_, ip) = server.send(&mut sbuf).unwrap();
+        dsts.push(ip);
+    }
+    assert_ne!(dsts[0], dsts[1]);
+    assert_eq!(dsts[0], dsts[2]);
+    assert_eq!(dsts[1], dsts[3]);
+}
+
+#[test]
+fn test_endpoint_ootb() {
+    let (mut client, client_ip, mut server, server_ip) = test_endpoints();
+
+    let ootb = |chunk: &[u8]| {
+        let mut pkt = Vec::new();
+        SctpCommonHeader {
+            src_port: 5001,
+            dst_port: 5002,
+            vtag: 1234,
+            checksum: 0,
+        }
+        .to_bytes(&mut pkt)
+        .unwrap();
+        pkt.extend_from_slice(chunk);
+        SctpAssociation::set_checksum(&mut pkt);
+        pkt
+    };
+
+    // An OOTB COOKIE-ACK or ERROR is discarded silently.
+    for chunk_type in [SctpChunkType::CookieAck, SctpChunkType::Error].iter() {
+        let mut sbuf = Vec::new();
+        let pkt = ootb(&[chunk_type.0, 0, 0, 4]);
+        assert!(server.recv(&client_ip, &pkt, &mut sbuf).is_ok());
+        assert!(sbuf.is_empty());
+    }
+
+    // Other packets for an unknown association are answered with an ABORT with the T bit.
+    let mut heartbeat = Vec::new();
+    SctpChunk::Heartbeat(vec![0; 8])
+        .to_bytes(&mut heartbeat)
+        .unwrap();
+    let mut pkt = ootb(&heartbeat);
+    let mut sbuf = Vec::new();
+    assert!(server.recv(&client_ip, &pkt, &mut sbuf).is_ok());
+    let (header, consumed) = SctpCommonHeader::from_bytes(&sbuf).unwrap();
+    assert_eq!(header.vtag, 1234);
+    match SctpChunk::from_bytes(&sbuf[consumed..]).unwrap().0 {
+        SctpChunk::Abort(v) => assert!(v.t_bit),
+        _ => panic!(),
+    }
+    assert_eq!(server.accept(), None);
+
+    // A corrupted packet is dropped silently.
+    pkt[12] ^= 0xff;
+    let mut sbuf = Vec::new();
+    assert_eq!(
+        server.recv(&client_ip, &pkt, &mut sbuf),
+        Err(SctpError::InvalidChecksum)
+    );
+    assert!(sbuf.is_empty());
+
+    // An ABORT with the T bit must carry the peer's tag, which is not known yet.
+    let id = client.connect(5002, &server_ip).unwrap();
+    let abort = |vtag: u32, t_bit: bool| {
+        let mut pkt = Vec::new();
+        SctpCommonHeader {
+            src_port: 5002,
+            dst_port: 5001,
+            vtag: vtag,
+            checksum: 0,
+        }
+        .to_bytes(&mut pkt)
+        .unwrap();
+        SctpChunk::Abort(SctpAbortChunk {
+            t_bit: t_bit,
+            error_cause: None,
+        })
+        .to_bytes(&mut pkt)
+        .unwrap();
+        SctpAssociation::set_checksum(&mut pkt);
+        pkt
+    };
+    assert!(client
+        .recv(&server_ip, &abort(id, true), &mut Vec::new())
+        .is_ok());
+    assert_eq!(client.take_closed().len(), 0);
+
+    assert!(client
+        .recv(&server_ip, &abort(id, false), &mut Vec::new())
+        .is_ok());
+    assert_eq!(client.take_closed().len(), 1);
+}
+
+#[test]
+fn test_endpoint_asconf_from_new_address() {
+    let (mut client, client_ip, mut server, server_ip) = test_endpoints();
+    client.connect(5002, &server_ip).unwrap();
+    for _ in 0..2 {
+        test_exchange(&mut client, &client_ip, &mut server, &server_ip);
+        test_exchange(&mut server, &server_ip, &mut client, &client_ip);
+    }
+    let server_id = server.accept().unwrap();
+
+    // An authenticated ASCONF from the address being added reaches the association
+    // instead of being answered as an OOTB packet.
+    let client_ip2: IpAddr = "192.0.2.3".parse().unwrap();
+    let assoc = server.get(server_id).unwrap();
+    let mut pkt = crate::test_auth_packet(
+        assoc,
+        vec![SctpChunk::Asconf(SctpAsconfChunk {
+            serial: assoc.peer_asconf_serial.0.wrapping_add(1),
+            addr: client_ip,
+            params: vec![SctpParameter::AddIp(0, client_ip2)],
+        })],
+    );
+    SctpAssociation::set_checksum(&mut pkt);
+    let mut sbuf = Vec::new();
+    assert!(server.recv(&client_ip2, &pkt, &mut sbuf).is_ok());
+    assert!(sbuf.is_empty());
+    let assoc = server.get(server_id).unwrap();
+    assert!(assoc.is_established());
+    assert!(assoc.get_pathid(&client_ip2).is_some());
+}
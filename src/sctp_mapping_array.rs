@@ -4,12 +4,18 @@ pub use crate::sctp_pkt::*;
 use crate::Result;
 use crate::SctpError;
 
+const MAX_MAPPING_ARRAY_SIZE: usize = 4096;
+// Duplicate TSNs reported in a single SACK
+const MAX_DUP_TSNS: usize = 16;
+
 #[derive(Debug)]
 pub struct SctpMappingArray {
     storage: Vec<u8>,
     base_tsn: SerialNumber<u32>,
     pub largest_tsn: SerialNumber<u32>,
     pub cummulative_tsn: SerialNumber<u32>,
+    // Duplicates received since the last SACK
+    dup_tsns: Vec<u32>,
     trace_id: String,
 }
 
@@ -20,6 +26,7 @@ impl SctpMappingArray {
             base_tsn: SerialNumber(0),
             largest_tsn: SerialNumber(0),
             cummulative_tsn: SerialNumber(0),
+            dup_tsns: Vec::new(),
             trace_id: trace_id,
         }
     }
@@ -38,21 +45,45 @@ impl SctpMappingArray {
         Ok(init_tsn)
     }
 
-    pub fn update(&mut self, tsn: u32) -> Result<Option<u32>> {
-        if SerialNumber(tsn) < self.base_tsn {
-            return Err(SctpError::Done);
-        };
-        if SerialNumber(tsn) < self.cummulative_tsn {
-            return Err(SctpError::Done);
+    pub fn is_duplicate(&self, tsn: u32) -> bool {
+        if SerialNumber(tsn) <= self.cummulative_tsn {
+            return true;
+        }
+        let gap = self.get_gap(tsn);
+        match self.storage.get((gap >> 3) as usize) {
+            Some(byte) => (*byte >> (gap & 0x07)) & 0x01 == 0x01,
+            None => false,
         }
-        let gap = if tsn >= self.base_tsn.0 {
+    }
+
+    fn get_gap(&self, tsn: u32) -> u32 {
+        if tsn >= self.base_tsn.0 {
             tsn - self.base_tsn.0
         } else {
             0xffffffff - self.base_tsn.0 + 1 + tsn
-        };
-        if (gap >> 3) as usize > self.storage.len() {
-            self.storage
-                .reserve((gap >> 3) as usize - self.storage.len());
+        }
+    }
+
+    pub fn update(&mut self, tsn: u32) -> Result<Option<u32>> {
+        if self.is_duplicate(tsn) {
+            trace!("{} duplicate tsn={}", self.trace_id, tsn);
+            if self.dup_tsns.len() < MAX_DUP_TSNS {
+                self.dup_tsns.push(tsn);
+            }
+            return Err(SctpError::Done);
+        }
+        let gap = self.get_gap(tsn);
+        if (gap >> 3) as usize >= self.storage.len() {
+            if (gap >> 3) as usize >= MAX_MAPPING_ARRAY_SIZE {
+                trace!(
+                    "{} tsn is out of mapping array tsn={}, base_tsn={}",
+                    self.trace_id,
+                    tsn,
+                    self.base_tsn
+                );
+                return Err(SctpError::Done);
+            }
+            self.storage.resize((gap >> 3) as usize + 1, 0x00);
         }
         self.storage[(gap >> 3) as usize] |= 0x01 << (gap & 0x07);
 
@@ -108,7 +139,7 @@ impl SctpMappingArray {
         }
     }
 
-    pub fn genarate_sack(&self, a_rwnd: u32) -> Result<SctpChunk> {
+    pub fn genarate_sack(&mut self, a_rwnd: u32) -> Result<SctpChunk> {
         let mut gap_ack_blocks: Vec<SctpGapAckBlock> = Vec::new();
 
         if self.largest_tsn > self.cummulative_tsn {
@@ -166,13 +197,15 @@ impl SctpMappingArray {
             }
         }
 
+        let dup_tsns = std::mem::take(&mut self.dup_tsns);
         let sack = SctpChunk::Sack(SctpSackChunk {
             cum_ack: self.cummulative_tsn.0,
             a_rwnd: a_rwnd,
             num_gap_ack: gap_ack_blocks.len() as u16,
-            num_dup_ack: 0,
+            num_dup_ack: dup_tsns.len() as u16,
             gap_acks: gap_ack_blocks,
-            dup_acks: Vec::new(),
+            dup_acks: dup_tsns,
+            dac_pkts: 0,
         });
         Ok(sack)
     }
@@ -524,6 +557,7 @@ fn test_sctp_ack_track() {
         num_dup_ack: 0,
         gap_acks: Vec::new(),
         dup_acks: Vec::new(),
+        dac_pkts: 0,
     });
     let sack = record.genarate_sack(0).unwrap();
     assert_eq!(sack, expected);
@@ -536,6 +570,7 @@ fn test_sctp_ack_track() {
         num_dup_ack: 0,
         gap_acks: vec![SctpGapAckBlock { start: 2, end: 2 }],
         dup_acks: Vec::new(),
+        dac_pkts: 0,
     });
     let sack = record.genarate_sack(0).unwrap();
     assert_eq!(sack, expected);
@@ -548,6 +583,7 @@ fn test_sctp_ack_track() {
         num_dup_ack: 0,
         gap_acks: vec![SctpGapAckBlock { start: 2, end: 3 }],
         dup_acks: Vec::new(),
+        dac_pkts: 0,
     });
     let sack = record.genarate_sack(0).unwrap();
     assert_eq!(sack, expected);
@@ -563,7 +599,61 @@ fn test_sctp_ack_track() {
             SctpGapAckBlock { start: 5, end: 5 },
         ],
         dup_acks: Vec::new(),
+        dac_pkts: 0,
     });
     let sack = record.genarate_sack(0).unwrap();
     assert_eq!(sack, expected);
 }
+
+#[test]
+fn test_sctp_dup_tsns() {
+    let mut record = SctpMappingArray::new(String::from("test"));
+    record.initialize(0).unwrap();
+    record.update(0).unwrap();
+    record.update(2).unwrap();
+
+    assert_eq!(record.update(0), Err(SctpError::Done));
+    assert_eq!(record.update(2), Err(SctpError::Done));
+    assert_eq!(record.update(0), Err(SctpError::Done));
+    let expected = SctpChunk::Sack(SctpSackChunk {
+        cum_ack: 0,
+        a_rwnd: 0,
+        num_gap_ack: 1,
+        num_dup_ack: 3,
+        gap_acks: vec![SctpGapAckBlock { start: 2, end: 2 }],
+        dup_acks: vec![0, 2, 0],
+        dac_pkts: 0,
+    });
+    assert_eq!(record.genarate_sack(0).unwrap(), expected);
+
+    // Cleared once reported, and bounded
+    for _ in 0..2 * MAX_DUP_TSNS {
+        assert_eq!(record.update(2), Err(SctpError::Done));
+    }
+    match record.genarate_sack(0).unwrap() {
+        SctpChunk::Sack(sack) => {
+            assert_eq!(sack.num_dup_ack as usize, MAX_DUP_TSNS);
+            assert_eq!(sack.dup_acks.len(), MAX_DUP_TSNS);
+        }
+        _ => panic!(),
+    }
+    match record.genarate_sack(0).unwrap() {
+        SctpChunk::Sack(sack) => assert!(sack.dup_acks.is_empty()),
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn test_sctp_tsn_record_out_of_window() {
+    let mut record = SctpMappingArray::new(String::from("test"));
+    record.initialize(510840415).unwrap();
+
+    let ret = record.update(510840415 + 256 * 8).unwrap();
+    assert_eq!(ret, None);
+    assert_eq!(record.largest_tsn, 510840415 + 256 * 8);
+
+    let ret = record.update(510840415 + 0x7fffffff);
+    assert_eq!(ret, Err(SctpError::Done));
+    assert_eq!(record.largest_tsn, 510840415 + 256 * 8);
+    assert_eq!(record.cummulative_tsn, 510840415 - 1);
+}
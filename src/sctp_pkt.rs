@@ -7,10 +7,12 @@ use nom::error::ErrorKind;
 use nom::number::streaming::{be_u16, be_u32, be_u64, be_u8};
 use nom::{Err, IResult};
 
+use std::cmp;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
 use crate::Result;
 use crate::SctpError;
+use crate::SctpParseError;
 
 #[derive(Debug, PartialEq)]
 pub struct SctpCommonHeader {
@@ -26,7 +28,11 @@ impl SctpCommonHeader {
             Ok(v) => v,
             Err(e) => {
                 trace!("{:?}", e);
-                return Err(SctpError::InvalidChunk);
+                return Err(SctpError::InvalidChunk(SctpParseError {
+                    chunk_type: None,
+                    param_type: None,
+                    offset: 0,
+                }));
             }
         };
         Ok((header, bytes.len() - remain.len()))
@@ -84,10 +90,13 @@ impl debug SctpChunkType {
     Error               = 9,
     CookieEcho          = 10,
     CookieAck           = 11,
+    Ecne                = 12,
+    Cwr                 = 13,
     ShutdownComplete    = 14,
     Auth                = 15,
     AsconfAck           = 128,
     ReConfig            = 130,
+    Pad                 = 132,
     ForwardTsn          = 192,
     Asconf              = 193,
 }
@@ -115,6 +124,13 @@ pub enum SctpChunk {
     Shutdown(u32),
     ShutdownAck,
     ShutdownComplete(bool),
+    // Lowest TSN Number (RFC 9260 Appendix A)
+    Ecne(u32),
+    Cwr(u32),
+    Pad(usize),
+    Asconf(SctpAsconfChunk),
+    AsconfAck(SctpAsconfAckChunk),
+    Auth(SctpAuthChunk),
     Unknown(SctpChunkType, u8, Vec<u8>),
 }
 
@@ -123,12 +139,52 @@ impl SctpChunk {
         let (remain, chunk) = match SctpChunk::parse_sctp_chunk(bytes) {
             Ok(v) => v,
             Err(_) => {
-                return Err(SctpError::InvalidChunk);
+                return Err(SctpError::InvalidChunk(SctpChunk::locate_parse_error(
+                    bytes,
+                )));
             }
         };
         Ok((chunk, bytes.len() - remain.len()))
     }
 
+    fn locate_parse_error(bytes: &[u8]) -> SctpParseError {
+        let mut error = SctpParseError {
+            chunk_type: None,
+            param_type: None,
+            offset: 0,
+        };
+        if bytes.len() < 4 {
+            return error;
+        }
+        let chunk_type = SctpChunkType(bytes[0]);
+        error.chunk_type = Some(chunk_type);
+        if chunk_type != SctpChunkType::Init && chunk_type != SctpChunkType::InitAck {
+            return error;
+        }
+
+        // Find the malformed parameter of INIT or INIT-ACK.
+        let length = cmp::min((bytes[2] as usize) << 8 | bytes[3] as usize, bytes.len());
+        let mut off = 20;
+        while off < length {
+            if off + 4 > length {
+                error.offset = off;
+                return error;
+            }
+            let param_type = SctpParameterType((bytes[off] as u16) << 8 | bytes[off + 1] as u16);
+            match SctpParameter::parse_sctp_parameter(&bytes[off..length]) {
+                Ok((remain, _)) => {
+                    off = length - remain.len();
+                }
+                Err(_) => {
+                    error.param_type = Some(param_type);
+                    error.offset = off;
+                    return error;
+                }
+            }
+        }
+        error
+    }
+
     pub fn bytes_len(&self) -> usize {
         let mut len = match self {
             SctpChunk::Data(v) => {
@@ -202,12 +258,56 @@ impl SctpChunk {
                 len += 4; // Cumulative TSN Ack
                 len
             }
+            SctpChunk::Ecne(_) | SctpChunk::Cwr(_) => {
+                let mut len = 1; // Chunk Type
+                len += 1; // Chunk flags
+                len += 2; // Chunk Length
+                len += 4; // Lowest TSN Number
+                len
+            }
             SctpChunk::ShutdownAck | SctpChunk::CookieAck | SctpChunk::ShutdownComplete(..) => {
                 let mut len = 1; // Chunk Type
                 len += 1; // Chunk flags
                 len += 2; // Chunk Length
                 len
             }
+            SctpChunk::Pad(v) => {
+                let mut len = 1; // Chunk Type
+                len += 1; // Chunk flags
+                len += 2; // Chunk Length
+                len += v; // Padding Data
+                len
+            }
+            SctpChunk::Asconf(v) => {
+                let mut len = 1; // Chunk Type
+                len += 1; // Chunk flags
+                len += 2; // Chunk Length
+                len += 4; // Sequence Number
+                len += SctpParameter::from_addr(&v.addr).bytes_len(); // Address Parameter
+                for param in &v.params {
+                    len += param.bytes_len();
+                }
+                len
+            }
+            SctpChunk::AsconfAck(v) => {
+                let mut len = 1; // Chunk Type
+                len += 1; // Chunk flags
+                len += 2; // Chunk Length
+                len += 4; // Sequence Number
+                for param in &v.params {
+                    len += param.bytes_len();
+                }
+                len
+            }
+            SctpChunk::Auth(v) => {
+                let mut len = 1; // Chunk Type
+                len += 1; // Chunk flags
+                len += 2; // Chunk Length
+                len += 2; // Shared Key Identifier
+                len += 2; // HMAC Identifier
+                len += v.hmac.len();
+                len
+            }
             SctpChunk::CookieEcho(v) => {
                 let mut len = 1; // Chunk Type
                 len += 1; // Chunk flags
@@ -238,7 +338,8 @@ impl SctpChunk {
                     .write_u8(
                         if v.e_bit { 0b0000_0001 } else { 0x00 }
                             | if v.b_bit { 0b0000_0010 } else { 0x00 }
-                            | if v.u_bit { 0b0000_0100 } else { 0x00 },
+                            | if v.u_bit { 0b0000_0100 } else { 0x00 }
+                            | if v.i_bit { 0b0000_1000 } else { 0x00 },
                     )
                     .unwrap();
                 bytes
@@ -284,7 +385,7 @@ impl SctpChunk {
             }
             SctpChunk::Sack(v) => {
                 bytes.write_u8(u8::from(SctpChunkType::Sack)).unwrap();
-                bytes.write_u8(0).unwrap();
+                bytes.write_u8((v.dac_pkts & 0b11) << 6).unwrap();
                 bytes
                     .write_u16::<BigEndian>(
                         16 + 4 * v.gap_acks.len() as u16 + 4 * v.dup_acks.len() as u16,
@@ -365,6 +466,18 @@ impl SctpChunk {
                 bytes.write_u8(0).unwrap();
                 bytes.write_u16::<BigEndian>(4).unwrap();
             }
+            SctpChunk::Ecne(tsn) => {
+                bytes.write_u8(u8::from(SctpChunkType::Ecne)).unwrap();
+                bytes.write_u8(0).unwrap();
+                bytes.write_u16::<BigEndian>(8).unwrap();
+                bytes.write_u32::<BigEndian>(*tsn).unwrap();
+            }
+            SctpChunk::Cwr(tsn) => {
+                bytes.write_u8(u8::from(SctpChunkType::Cwr)).unwrap();
+                bytes.write_u8(0).unwrap();
+                bytes.write_u16::<BigEndian>(8).unwrap();
+                bytes.write_u32::<BigEndian>(*tsn).unwrap();
+            }
             SctpChunk::CookieEcho(v) => {
                 bytes.write_u8(u8::from(SctpChunkType::CookieEcho)).unwrap();
                 bytes.write_u8(0).unwrap();
@@ -383,6 +496,51 @@ impl SctpChunk {
                 bytes.write_u8(if *v { 0b0000_0001 } else { 0x00 }).unwrap();
                 bytes.write_u16::<BigEndian>(4).unwrap();
             }
+            SctpChunk::Pad(v) => {
+                bytes.write_u8(u8::from(SctpChunkType::Pad)).unwrap();
+                bytes.write_u8(0).unwrap();
+                bytes.write_u16::<BigEndian>(4 + *v as u16).unwrap();
+                bytes.resize(bytes.len() + v, 0);
+            }
+            SctpChunk::Asconf(v) => {
+                let mut param_bytes = Vec::new();
+                SctpParameter::from_addr(&v.addr)
+                    .to_bytes(&mut param_bytes)
+                    .unwrap();
+                for param in &v.params {
+                    param.to_bytes(&mut param_bytes).unwrap();
+                }
+                bytes.write_u8(u8::from(SctpChunkType::Asconf)).unwrap();
+                bytes.write_u8(0).unwrap();
+                bytes
+                    .write_u16::<BigEndian>(8 + param_bytes.len() as u16)
+                    .unwrap();
+                bytes.write_u32::<BigEndian>(v.serial).unwrap();
+                bytes.extend(&param_bytes);
+            }
+            SctpChunk::AsconfAck(v) => {
+                let mut param_bytes = Vec::new();
+                for param in &v.params {
+                    param.to_bytes(&mut param_bytes).unwrap();
+                }
+                bytes.write_u8(u8::from(SctpChunkType::AsconfAck)).unwrap();
+                bytes.write_u8(0).unwrap();
+                bytes
+                    .write_u16::<BigEndian>(8 + param_bytes.len() as u16)
+                    .unwrap();
+                bytes.write_u32::<BigEndian>(v.serial).unwrap();
+                bytes.extend(&param_bytes);
+            }
+            SctpChunk::Auth(v) => {
+                bytes.write_u8(u8::from(SctpChunkType::Auth)).unwrap();
+                bytes.write_u8(0).unwrap();
+                bytes
+                    .write_u16::<BigEndian>(8 + v.hmac.len() as u16)
+                    .unwrap();
+                bytes.write_u16::<BigEndian>(v.shared_key_id).unwrap();
+                bytes.write_u16::<BigEndian>(u16::from(v.hmac_id)).unwrap();
+                bytes.extend(&v.hmac);
+            }
             _ => {}
         };
         if (bytes.len() - prev_len) % 4 > 0 {
@@ -409,6 +567,12 @@ impl SctpChunk {
             SctpChunk::Shutdown(..) => SctpChunkType::Shutdown,
             SctpChunk::ShutdownAck => SctpChunkType::ShutdownAck,
             SctpChunk::ShutdownComplete(..) => SctpChunkType::ShutdownComplete,
+            SctpChunk::Ecne(..) => SctpChunkType::Ecne,
+            SctpChunk::Cwr(..) => SctpChunkType::Cwr,
+            SctpChunk::Pad(..) => SctpChunkType::Pad,
+            SctpChunk::Asconf(..) => SctpChunkType::Asconf,
+            SctpChunk::AsconfAck(..) => SctpChunkType::AsconfAck,
+            SctpChunk::Auth(..) => SctpChunkType::Auth,
             SctpChunk::Unknown(chunk_type, _, _) => *chunk_type,
         }
     }
@@ -423,7 +587,7 @@ impl SctpChunk {
         do_parse!(
             ctype: be_u8 >>
             flags: be_u8 >>
-            length: be_u16 >>
+            length: verify!(be_u16, |v: &u16| *v >= 4) >>
             chunk: flat_map!(take!(length - 4),
                 call!(SctpChunk::parse_sctp_chunk_with_type, SctpChunkType(ctype), length as usize - 4, flags)
                 ) >>
@@ -442,7 +606,7 @@ impl SctpChunk {
             SctpChunkType::Data => SctpChunk::parse_sctp_chunk_data(i, length, flags),
             SctpChunkType::Init => SctpChunk::parse_sctp_chunk_init(i, SctpChunkType::Init),
             SctpChunkType::InitAck => SctpChunk::parse_sctp_chunk_init(i, SctpChunkType::InitAck),
-            SctpChunkType::Sack => SctpChunk::parse_sctp_chunk_sack(i),
+            SctpChunkType::Sack => SctpChunk::parse_sctp_chunk_sack(i, flags),
             SctpChunkType::Abort => SctpChunk::parse_sctp_chunk_abort(i, length, flags),
             SctpChunkType::Heartbeat => SctpChunk::parse_sctp_chunk_heartbeat(i, length),
             SctpChunkType::HeartbeatAck => SctpChunk::parse_sctp_chunk_heartbeat_ack(i, length),
@@ -453,6 +617,12 @@ impl SctpChunk {
             SctpChunkType::ShutdownComplete => {
                 SctpChunk::parse_sctp_chunk_shutdown_complete(i, flags)
             }
+            SctpChunkType::Ecne => SctpChunk::parse_sctp_chunk_ecne(i),
+            SctpChunkType::Cwr => SctpChunk::parse_sctp_chunk_cwr(i),
+            SctpChunkType::Pad => Ok((&i[length..], SctpChunk::Pad(length))),
+            SctpChunkType::Asconf => SctpChunk::parse_sctp_chunk_asconf(i),
+            SctpChunkType::AsconfAck => SctpChunk::parse_sctp_chunk_asconf_ack(i),
+            SctpChunkType::Auth => SctpChunk::parse_sctp_chunk_auth(i, length),
             _ => map!(i, take!(length), |chunk| {
                 SctpChunk::Unknown(chunk_type, flags, Vec::from(chunk))
             }),
@@ -460,6 +630,9 @@ impl SctpChunk {
     }
 
     fn parse_sctp_chunk_data(i: &[u8], length: usize, flags: u8) -> IResult<&[u8], SctpChunk> {
+        if length < 12 {
+            return Err(Err::Error(error_position!(i, ErrorKind::LengthValue)));
+        }
         do_parse!(
             i,
             tsn: be_u32
@@ -468,6 +641,7 @@ impl SctpChunk {
                 >> pid: be_u32
                 >> v: take!(length - 12)
                 >> (SctpChunk::Data(SctpDataChunk {
+                    i_bit: flags & 0b0000_1000 != 0,
                     u_bit: if flags & 0b0000_0100 != 0 {
                         true
                     } else {
@@ -501,6 +675,7 @@ impl SctpChunk {
                 >> is: be_u16
                 >> itsn: be_u32
                 >> params: many0!(complete!(SctpParameter::parse_sctp_parameter))
+                >> eof!()
                 >> ({
                     let contents = SctpInitChunk {
                         init_tag: itag,
@@ -519,8 +694,9 @@ impl SctpChunk {
         )
     }
 
-    named! {parse_sctp_chunk_sack<SctpChunk>,
+    fn parse_sctp_chunk_sack(i: &[u8], flags: u8) -> IResult<&[u8], SctpChunk> {
         do_parse!(
+            i,
             cack: be_u32 >>
             arwnd: be_u32 >>
             ngap: be_u16 >>
@@ -547,6 +723,7 @@ impl SctpChunk {
                     num_dup_ack: ndup,
                     gap_acks: gaps,
                     dup_acks: dups,
+                    dac_pkts: (flags >> 6) & 0b11,
                 }
             ) )
         )
@@ -598,6 +775,20 @@ impl SctpChunk {
         )
     }
 
+    named! {parse_sctp_chunk_ecne<SctpChunk>,
+        do_parse!(
+            tsn: be_u32 >>
+            ( SctpChunk::Ecne(tsn) )
+        )
+    }
+
+    named! {parse_sctp_chunk_cwr<SctpChunk>,
+        do_parse!(
+            tsn: be_u32 >>
+            ( SctpChunk::Cwr(tsn) )
+        )
+    }
+
     fn parse_sctp_chunk_cookie_echo(i: &[u8], length: usize) -> IResult<&[u8], SctpChunk> {
         do_parse!(i, v: take!(length) >> (SctpChunk::CookieEcho(Vec::from(v))))
     }
@@ -613,10 +804,59 @@ impl SctpChunk {
         };
         return Ok((&i[0..], SctpChunk::ShutdownComplete(t_bit)));
     }
+
+    fn parse_sctp_chunk_asconf(i: &[u8]) -> IResult<&[u8], SctpChunk> {
+        do_parse!(
+            i,
+            serial: be_u32
+                >> addr: map_opt!(call!(SctpParameter::parse_sctp_parameter), |param| {
+                    SctpParameter::to_addr(&param)
+                })
+                >> params: many0!(complete!(SctpParameter::parse_sctp_parameter))
+                >> eof!()
+                >> (SctpChunk::Asconf(SctpAsconfChunk {
+                    serial,
+                    addr,
+                    params,
+                }))
+        )
+    }
+
+    fn parse_sctp_chunk_asconf_ack(i: &[u8]) -> IResult<&[u8], SctpChunk> {
+        do_parse!(
+            i,
+            serial: be_u32
+                >> params: many0!(complete!(SctpParameter::parse_sctp_parameter))
+                >> eof!()
+                >> (SctpChunk::AsconfAck(SctpAsconfAckChunk {
+                    serial,
+                    params,
+                }))
+        )
+    }
+
+    fn parse_sctp_chunk_auth(i: &[u8], length: usize) -> IResult<&[u8], SctpChunk> {
+        if length < 4 {
+            return Err(Err::Error(error_position!(i, ErrorKind::LengthValue)));
+        }
+        do_parse!(
+            i,
+            shared_key_id: be_u16
+                >> hmac_id: be_u16
+                >> hmac: take!(length - 4)
+                >> (SctpChunk::Auth(SctpAuthChunk {
+                    shared_key_id,
+                    hmac_id: SctpHmacAlgoId(hmac_id),
+                    hmac: Vec::from(hmac),
+                }))
+        )
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct SctpDataChunk {
+    // SACK-IMMEDIATELY (RFC 7053)
+    pub i_bit: bool,
     pub u_bit: bool,
     pub b_bit: bool,
     pub e_bit: bool,
@@ -645,6 +885,9 @@ pub struct SctpSackChunk {
     pub num_dup_ack: u16,
     pub gap_acks: Vec<SctpGapAckBlock>,
     pub dup_acks: Vec<u32>,
+    // Packets acknowledged by this SACK, carried in the chunk flags for
+    // Delayed Ack for CMT (DAC)
+    pub dac_pkts: u8,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -660,6 +903,30 @@ pub struct SctpAbortChunk {
     pub error_cause: Option<SctpErrorCause>,
 }
 
+// ASCONF (RFC 5061 Section 4.1.1). The address parameter is one of the
+// sender's addresses, used to find the association.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SctpAsconfChunk {
+    pub serial: u32,
+    pub addr: IpAddr,
+    pub params: Vec<SctpParameter>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SctpAsconfAckChunk {
+    pub serial: u32,
+    pub params: Vec<SctpParameter>,
+}
+
+// AUTH (RFC 4895 Section 4.2). The HMAC covers this chunk, with the HMAC
+// field zeroed, and all the chunks after it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SctpAuthChunk {
+    pub shared_key_id: u16,
+    pub hmac_id: SctpHmacAlgoId,
+    pub hmac: Vec<u8>,
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct SctpGapAckBlock {
     pub start: u16,
@@ -683,6 +950,11 @@ impl debug SctpParameterType {
     HmacAlgo        = 32772,
     SupportedExts   = 32776,
     ForwardTsn      = 49152,
+    AddIp           = 49153,
+    DeleteIp        = 49154,
+    ErrorCauseIndication = 49155,
+    SetPrimary      = 49156,
+    SuccessIndication = 49157,
 }
 }
 
@@ -706,10 +978,31 @@ pub enum SctpParameter {
     HmacAlgo(Vec<SctpHmacAlgoId>),
     SupportedExts(Vec<SctpChunkType>),
     ForwardTsn,
+    // ASCONF parameters carry an ASCONF-Request Correlation ID (RFC 5061 Section 4.2).
+    AddIp(u32, IpAddr),
+    DeleteIp(u32, IpAddr),
+    ErrorCauseIndication(u32, Vec<SctpErrorCause>),
+    SetPrimary(u32, IpAddr),
+    SuccessIndication(u32),
     Unknown(SctpParameterType, Vec<u8>),
 }
 
 impl SctpParameter {
+    pub fn from_addr(addr: &IpAddr) -> SctpParameter {
+        match addr {
+            IpAddr::V4(addr4) => SctpParameter::Ipv4(*addr4),
+            IpAddr::V6(addr6) => SctpParameter::Ipv6(*addr6),
+        }
+    }
+
+    pub fn to_addr(&self) -> Option<IpAddr> {
+        match self {
+            SctpParameter::Ipv4(addr4) => Some(IpAddr::V4(*addr4)),
+            SctpParameter::Ipv6(addr6) => Some(IpAddr::V6(*addr6)),
+            _ => None,
+        }
+    }
+
     pub fn bytes_len(&self) -> usize {
         let mut len = match self {
             SctpParameter::Ipv4(..) => {
@@ -765,6 +1058,30 @@ impl SctpParameter {
                 len += 2 * v.len(); // // Algo ID #n
                 len
             }
+            SctpParameter::AddIp(_, addr)
+            | SctpParameter::DeleteIp(_, addr)
+            | SctpParameter::SetPrimary(_, addr) => {
+                let mut len = 2; // Type
+                len += 2; // Length
+                len += 4; // ASCONF-Request Correlation ID
+                len += SctpParameter::from_addr(addr).bytes_len(); // Address Parameter
+                len
+            }
+            SctpParameter::ErrorCauseIndication(_, v) => {
+                let mut len = 2; // Type
+                len += 2; // Length
+                len += 4; // ASCONF-Request Correlation ID
+                for cause in v {
+                    len += cause.bytes_len();
+                }
+                len
+            }
+            SctpParameter::SuccessIndication(_) => {
+                let mut len = 2; // Type
+                len += 2; // Length
+                len += 4; // ASCONF-Request Correlation ID
+                len
+            }
             SctpParameter::Unknown(_, v) => {
                 let mut len = 2; // Type
                 len += 1; // Length
@@ -867,6 +1184,46 @@ impl SctpParameter {
                     .unwrap();
                 bytes.write_u16::<BigEndian>(4).unwrap();
             }
+            SctpParameter::AddIp(id, addr)
+            | SctpParameter::DeleteIp(id, addr)
+            | SctpParameter::SetPrimary(id, addr) => {
+                let param_type = match self {
+                    SctpParameter::AddIp(..) => SctpParameterType::AddIp,
+                    SctpParameter::DeleteIp(..) => SctpParameterType::DeleteIp,
+                    _ => SctpParameterType::SetPrimary,
+                };
+                let mut addr_bytes = Vec::new();
+                SctpParameter::from_addr(addr)
+                    .to_bytes(&mut addr_bytes)
+                    .unwrap();
+                bytes.write_u16::<BigEndian>(u16::from(param_type)).unwrap();
+                bytes
+                    .write_u16::<BigEndian>(8 + addr_bytes.len() as u16)
+                    .unwrap();
+                bytes.write_u32::<BigEndian>(*id).unwrap();
+                bytes.extend(addr_bytes)
+            }
+            SctpParameter::ErrorCauseIndication(id, v) => {
+                let mut cause_bytes = Vec::new();
+                for cause in v {
+                    cause.to_bytes(&mut cause_bytes).unwrap();
+                }
+                bytes
+                    .write_u16::<BigEndian>(u16::from(SctpParameterType::ErrorCauseIndication))
+                    .unwrap();
+                bytes
+                    .write_u16::<BigEndian>(8 + cause_bytes.len() as u16)
+                    .unwrap();
+                bytes.write_u32::<BigEndian>(*id).unwrap();
+                bytes.extend(cause_bytes)
+            }
+            SctpParameter::SuccessIndication(id) => {
+                bytes
+                    .write_u16::<BigEndian>(u16::from(SctpParameterType::SuccessIndication))
+                    .unwrap();
+                bytes.write_u16::<BigEndian>(8).unwrap();
+                bytes.write_u32::<BigEndian>(*id).unwrap();
+            }
             SctpParameter::Unknown(param_type, v) => {
                 bytes
                     .write_u16::<BigEndian>(u16::from(*param_type))
@@ -887,11 +1244,12 @@ impl SctpParameter {
     named! {parse_sctp_parameter<SctpParameter>,
         do_parse!(
             param_type: be_u16 >>
-            param_length: be_u16 >>
+            param_length: verify!(be_u16, |v: &u16| *v >= 4) >>
             param: flat_map!(take!(param_length - 4),
                 call!(SctpParameter::parse_sctp_parameter_with_type, SctpParameterType(param_type), param_length as usize - 4)
                 ) >>
-            cond!(param_length % 4 > 0, take!(4 - (param_length % 4))) >> // skip padding bytes
+            // skip padding bytes, which the last parameter in a chunk may omit
+            cond!(param_length % 4 > 0, opt!(complete!(take!(4 - (param_length % 4))))) >>
             ( param )
         )
     }
@@ -918,6 +1276,17 @@ impl SctpParameter {
             SctpParameterType::ForwardTsn => {
                 SctpParameter::parse_sctp_parameter_forward_tsn(i, length)
             }
+            SctpParameterType::AddIp
+            | SctpParameterType::DeleteIp
+            | SctpParameterType::SetPrimary => {
+                SctpParameter::parse_sctp_parameter_address_request(i, param_type)
+            }
+            SctpParameterType::ErrorCauseIndication => {
+                SctpParameter::parse_sctp_parameter_error_cause_indication(i)
+            }
+            SctpParameterType::SuccessIndication => {
+                SctpParameter::parse_sctp_parameter_success_indication(i)
+            }
             _ => map!(i, take!(length), |param| {
                 SctpParameter::Unknown(param_type, Vec::from(param))
             }),
@@ -1019,6 +1388,42 @@ impl SctpParameter {
             Err(Err::Error(error_position!(i, ErrorKind::Verify)))
         }
     }
+
+    fn parse_sctp_parameter_address_request(
+        i: &[u8],
+        param_type: SctpParameterType,
+    ) -> IResult<&[u8], SctpParameter> {
+        do_parse!(
+            i,
+            id: be_u32
+                >> addr: map_opt!(call!(SctpParameter::parse_sctp_parameter), |param| {
+                    SctpParameter::to_addr(&param)
+                })
+                >> eof!()
+                >> (match param_type {
+                    SctpParameterType::AddIp => SctpParameter::AddIp(id, addr),
+                    SctpParameterType::DeleteIp => SctpParameter::DeleteIp(id, addr),
+                    _ => SctpParameter::SetPrimary(id, addr),
+                })
+        )
+    }
+
+    named! {parse_sctp_parameter_error_cause_indication<SctpParameter>,
+        do_parse!(
+            id: be_u32 >>
+            causes: many0!(complete!(SctpErrorCause::parse_sctp_error_cause)) >>
+            eof!() >>
+            ( SctpParameter::ErrorCauseIndication(id, causes) )
+        )
+    }
+
+    named! {parse_sctp_parameter_success_indication<SctpParameter>,
+        do_parse!(
+            id: be_u32 >>
+            eof!() >>
+            ( SctpParameter::SuccessIndication(id) )
+        )
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -1040,15 +1445,20 @@ impl SctpStateCookie {
         }
         let mut mac = Hmac::new(Sha256::new(), key);
         mac.input(&bytes[0..(bytes.len() - 32)]);
+        let error = SctpError::InvalidChunk(SctpParseError {
+            chunk_type: Some(SctpChunkType::CookieEcho),
+            param_type: None,
+            offset: 0,
+        });
         if mac.result().code() != &bytes[(bytes.len() - 32)..] {
-            return Err(SctpError::InvalidChunk);
+            return Err(error);
         }
 
         let (remain, cookie) =
             match SctpStateCookie::parse_sctp_state_cookie(&bytes[0..(bytes.len() - 32)]) {
                 Ok(v) => v,
                 Err(_) => {
-                    return Err(SctpError::InvalidChunk);
+                    return Err(error);
                 }
             };
         Ok((cookie, bytes.len() - remain.len()))
@@ -1109,7 +1519,7 @@ pub struct SctpHmacAlgoId(pub u16);
 newtype_enum! {
 impl debug SctpHmacAlgoId {
     Sha1    = 1,
-    Sha256  = 256,
+    Sha256  = 3,
 }
 }
 
@@ -1137,6 +1547,11 @@ impl debug SctpErrorCauseCode {
     RestartAssocWithNewAddr = 11,
     UserInitiatedAbort      = 12,
     ProtocolViolation       = 13,
+    DeleteLastAddr          = 160,
+    ResourceShortage        = 161,
+    DeleteSourceAddr        = 162,
+    IllegalAsconfAck        = 163,
+    NoAuthorization         = 164,
 }
 }
 
@@ -1161,6 +1576,13 @@ pub enum SctpErrorCause {
     RestartAssocWithNewAddr(SctpParameterType, u16, Vec<u8>),
     UserInitiatedAbort(Vec<u8>),
     ProtocolViolation(Vec<u8>),
+    // The ASCONF error causes (RFC 5061 Section 4.3) carry the TLV of the
+    // refused ASCONF parameter.
+    DeleteLastAddr(Vec<u8>),
+    ResourceShortage(Vec<u8>),
+    DeleteSourceAddr(Vec<u8>),
+    IllegalAsconfAck,
+    NoAuthorization(Vec<u8>),
     Unknown(SctpErrorCauseCode, Vec<u8>),
 }
 
@@ -1174,18 +1596,41 @@ impl SctpErrorCause {
                 len += 2; //  (Reserved)
                 len
             }
+            SctpErrorCause::NoUserData(_) => {
+                let mut len = 2; //  Cause Code
+                len += 2; // Cause Length
+                len += 4; // TSN value
+                len
+            }
             SctpErrorCause::UserInitiatedAbort(v) => {
                 let mut len = 2; //  Cause Code
                 len += 2; // Cause Length
                 len += v.len();
                 len
             }
-            SctpErrorCause::ProtocolViolation(v) => {
+            SctpErrorCause::ProtocolViolation(v)
+            | SctpErrorCause::DeleteLastAddr(v)
+            | SctpErrorCause::ResourceShortage(v)
+            | SctpErrorCause::DeleteSourceAddr(v)
+            | SctpErrorCause::NoAuthorization(v) => {
+                let mut len = 2; //  Cause Code
+                len += 2; // Cause Length
+                len += v.len();
+                len
+            }
+            SctpErrorCause::UnresolvableAddr(_, _, v) => {
                 let mut len = 2; //  Cause Code
                 len += 2; // Cause Length
+                len += 2; // Parameter Type
+                len += 2; // Parameter Length
                 len += v.len();
                 len
             }
+            SctpErrorCause::IllegalAsconfAck => {
+                let mut len = 2; //  Cause Code
+                len += 2; // Cause Length
+                len
+            }
             _ => 0,
         };
         if len % 4 > 0 {
@@ -1205,6 +1650,13 @@ impl SctpErrorCause {
                 bytes.write_u16::<BigEndian>(*v).unwrap();
                 bytes.write_u16::<BigEndian>(0).unwrap();
             }
+            SctpErrorCause::NoUserData(v) => {
+                bytes
+                    .write_u16::<BigEndian>(u16::from(SctpErrorCauseCode::NoUserData))
+                    .unwrap();
+                bytes.write_u16::<BigEndian>(8).unwrap();
+                bytes.write_u32::<BigEndian>(*v).unwrap();
+            }
             SctpErrorCause::UserInitiatedAbort(v) => {
                 bytes
                     .write_u16::<BigEndian>(u16::from(SctpErrorCauseCode::UserInitiatedAbort))
@@ -1212,6 +1664,34 @@ impl SctpErrorCause {
                 bytes.write_u16::<BigEndian>(4 + v.len() as u16).unwrap();
                 bytes.extend(v);
             }
+            SctpErrorCause::ProtocolViolation(v)
+            | SctpErrorCause::DeleteLastAddr(v)
+            | SctpErrorCause::ResourceShortage(v)
+            | SctpErrorCause::DeleteSourceAddr(v)
+            | SctpErrorCause::NoAuthorization(v) => {
+                bytes
+                    .write_u16::<BigEndian>(u16::from(self.get_code()))
+                    .unwrap();
+                bytes.write_u16::<BigEndian>(4 + v.len() as u16).unwrap();
+                bytes.extend(v);
+            }
+            SctpErrorCause::UnresolvableAddr(param_type, length, v) => {
+                bytes
+                    .write_u16::<BigEndian>(u16::from(SctpErrorCauseCode::UnresolvableAddr))
+                    .unwrap();
+                bytes.write_u16::<BigEndian>(8 + v.len() as u16).unwrap();
+                bytes
+                    .write_u16::<BigEndian>(u16::from(*param_type))
+                    .unwrap();
+                bytes.write_u16::<BigEndian>(*length).unwrap();
+                bytes.extend(v);
+            }
+            SctpErrorCause::IllegalAsconfAck => {
+                bytes
+                    .write_u16::<BigEndian>(u16::from(SctpErrorCauseCode::IllegalAsconfAck))
+                    .unwrap();
+                bytes.write_u16::<BigEndian>(4).unwrap();
+            }
             _ => {}
         }
         if (bytes.len() - prev_len) % 4 > 0 {
@@ -1225,7 +1705,7 @@ impl SctpErrorCause {
     named! {parse_sctp_error_cause<SctpErrorCause>,
         do_parse!(
             code: be_u16 >>
-            length: be_u16 >>
+            length: verify!(be_u16, |v: &u16| *v >= 4) >>
             cause: flat_map!(take!(length - 4),
                 call!(SctpErrorCause::parse_sctp_error_cause_with_code, SctpErrorCauseCode(code), length as usize - 4)
                 ) >>
@@ -1242,12 +1722,33 @@ impl SctpErrorCause {
             SctpErrorCauseCode::InvalidStreamId => {
                 SctpErrorCause::parse_sctp_error_cause_invalid_stream_id(i)
             }
+            SctpErrorCauseCode::NoUserData => {
+                SctpErrorCause::parse_sctp_error_cause_no_user_data(i)
+            }
             SctpErrorCauseCode::UserInitiatedAbort => {
                 SctpErrorCause::parse_sctp_error_cause_user_initiated_abort(i, length)
             }
             SctpErrorCauseCode::ProtocolViolation => {
                 SctpErrorCause::parse_sctp_error_cause_protocol_violation(i, length)
             }
+            SctpErrorCauseCode::UnresolvableAddr => {
+                SctpErrorCause::parse_sctp_error_cause_unresolvable_addr(i, length)
+            }
+            SctpErrorCauseCode::DeleteLastAddr
+            | SctpErrorCauseCode::ResourceShortage
+            | SctpErrorCauseCode::DeleteSourceAddr
+            | SctpErrorCauseCode::NoAuthorization => {
+                map!(i, take!(length), |v| {
+                    let v = Vec::from(v);
+                    match cause_code {
+                        SctpErrorCauseCode::DeleteLastAddr => SctpErrorCause::DeleteLastAddr(v),
+                        SctpErrorCauseCode::ResourceShortage => SctpErrorCause::ResourceShortage(v),
+                        SctpErrorCauseCode::DeleteSourceAddr => SctpErrorCause::DeleteSourceAddr(v),
+                        _ => SctpErrorCause::NoAuthorization(v),
+                    }
+                })
+            }
+            SctpErrorCauseCode::IllegalAsconfAck => Ok((i, SctpErrorCause::IllegalAsconfAck)),
             _ => map!(i, take!(length), |cause| {
                 SctpErrorCause::Unknown(cause_code, Vec::from(cause))
             }),
@@ -1261,6 +1762,13 @@ impl SctpErrorCause {
         )
     }
 
+    named! {parse_sctp_error_cause_no_user_data<SctpErrorCause>,
+        do_parse!(
+            tsn: be_u32 >>
+            ( SctpErrorCause::NoUserData(tsn) )
+        )
+    }
+
     fn parse_sctp_error_cause_user_initiated_abort(
         i: &[u8],
         length: usize,
@@ -1279,6 +1787,52 @@ impl SctpErrorCause {
             v: take!(length) >> (SctpErrorCause::ProtocolViolation(Vec::from(v)))
         )
     }
+
+    fn parse_sctp_error_cause_unresolvable_addr(
+        i: &[u8],
+        length: usize,
+    ) -> IResult<&[u8], SctpErrorCause> {
+        if length < 4 {
+            return Err(Err::Error(error_position!(i, ErrorKind::LengthValue)));
+        }
+        do_parse!(
+            i,
+            param_type: be_u16
+                >> param_length: be_u16
+                >> v: take!(length - 4)
+                >> (SctpErrorCause::UnresolvableAddr(
+                    SctpParameterType(param_type),
+                    param_length,
+                    Vec::from(v)
+                ))
+        )
+    }
+
+    pub fn get_code(&self) -> SctpErrorCauseCode {
+        match self {
+            SctpErrorCause::InvalidStreamId(..) => SctpErrorCauseCode::InvalidStreamId,
+            SctpErrorCause::MissingParam(..) => SctpErrorCauseCode::MissingParam,
+            SctpErrorCause::CookieError(..) => SctpErrorCauseCode::CookieError,
+            SctpErrorCause::OutOfResource => SctpErrorCauseCode::OutOfResource,
+            SctpErrorCause::UnresolvableAddr(..) => SctpErrorCauseCode::UnresolvableAddr,
+            SctpErrorCause::UnrecognizedChunk(..) => SctpErrorCauseCode::UnrecognizedChunk,
+            SctpErrorCause::InvalidParam => SctpErrorCauseCode::InvalidParam,
+            SctpErrorCause::UnrecognizedParam(..) => SctpErrorCauseCode::UnrecognizedParam,
+            SctpErrorCause::NoUserData(..) => SctpErrorCauseCode::NoUserData,
+            SctpErrorCause::CookieInShutdown => SctpErrorCauseCode::CookieInShutdown,
+            SctpErrorCause::RestartAssocWithNewAddr(..) => {
+                SctpErrorCauseCode::RestartAssocWithNewAddr
+            }
+            SctpErrorCause::UserInitiatedAbort(..) => SctpErrorCauseCode::UserInitiatedAbort,
+            SctpErrorCause::ProtocolViolation(..) => SctpErrorCauseCode::ProtocolViolation,
+            SctpErrorCause::DeleteLastAddr(..) => SctpErrorCauseCode::DeleteLastAddr,
+            SctpErrorCause::ResourceShortage(..) => SctpErrorCauseCode::ResourceShortage,
+            SctpErrorCause::DeleteSourceAddr(..) => SctpErrorCauseCode::DeleteSourceAddr,
+            SctpErrorCause::IllegalAsconfAck => SctpErrorCauseCode::IllegalAsconfAck,
+            SctpErrorCause::NoAuthorization(..) => SctpErrorCauseCode::NoAuthorization,
+            SctpErrorCause::Unknown(code, _) => *code,
+        }
+    }
 }
 
 #[test]
@@ -1298,6 +1852,7 @@ fn test_parse_sctp_common_header() {
 fn test_parse_sctp_data() {
     let data: &[u8] = include_bytes!("../assets/sctp_data.bin");
     let expected = SctpChunk::Data(SctpDataChunk {
+        i_bit: false,
         u_bit: false,
         b_bit: true,
         e_bit: true,
@@ -1317,6 +1872,7 @@ fn test_pack_sctp_data() {
     let data: &[u8] = include_bytes!("../assets/sctp_data.bin");
     let mut packed = Vec::new();
     SctpChunk::Data(SctpDataChunk {
+        i_bit: false,
         u_bit: false,
         b_bit: true,
         e_bit: true,
@@ -1331,6 +1887,25 @@ fn test_pack_sctp_data() {
     assert_eq!(Vec::from(&data[12..]), packed);
 }
 
+#[test]
+fn test_pack_sctp_data_sack_immediately() {
+    let chunk = SctpChunk::Data(SctpDataChunk {
+        i_bit: true,
+        u_bit: false,
+        b_bit: true,
+        e_bit: true,
+        tsn: 1,
+        stream_id: 0,
+        stream_seq: 0,
+        proto_id: 0,
+        data: vec![1u8; 4],
+    });
+    let mut packed = Vec::new();
+    chunk.to_bytes(&mut packed).unwrap();
+    assert_eq!(packed[1], 0b0000_1011);
+    assert_eq!(SctpChunk::from_bytes(&packed), Ok((chunk, 20)));
+}
+
 #[test]
 fn test_parse_sctp_init() {
     let data: &[u8] = include_bytes!("../assets/sctp_init.bin");
@@ -1492,6 +2067,7 @@ fn test_parse_sctp_sack() {
         num_dup_ack: 0,
         gap_acks: Vec::new(),
         dup_acks: Vec::new(),
+        dac_pkts: 0,
     });
 
     let res = SctpChunk::from_bytes(&data[12..]);
@@ -1505,6 +2081,7 @@ fn test_parse_sctp_sack() {
         num_dup_ack: 0,
         gap_acks: vec![SctpGapAckBlock { start: 1, end: 2 }],
         dup_acks: Vec::new(),
+        dac_pkts: 0,
     });
 
     let res = SctpChunk::from_bytes(&data[12..]);
@@ -1518,6 +2095,7 @@ fn test_parse_sctp_sack() {
         num_dup_ack: 1,
         gap_acks: Vec::new(),
         dup_acks: vec![591162750],
+        dac_pkts: 0,
     });
 
     let res = SctpChunk::from_bytes(&data[12..]);
@@ -1531,6 +2109,7 @@ fn test_parse_sctp_sack() {
         num_dup_ack: 1,
         gap_acks: vec![SctpGapAckBlock { start: 1, end: 2 }],
         dup_acks: vec![591162750],
+        dac_pkts: 0,
     });
 
     let res = SctpChunk::from_bytes(&data[12..]);
@@ -1623,3 +2202,164 @@ fn test_parse_sctp_shutdown_complete() {
     let res = SctpChunk::from_bytes(&data[12..]);
     assert_eq!(res, Ok((expected, 4)));
 }
+
+#[test]
+fn test_parse_sctp_pad() {
+    let chunk = SctpChunk::Pad(6);
+    let mut bytes = Vec::new();
+    assert_eq!(chunk.to_bytes(&mut bytes), Ok(12));
+    assert_eq!(&bytes[..4], &[0x84, 0x00, 0x00, 0x0a]);
+
+    let res = SctpChunk::from_bytes(&bytes);
+    assert_eq!(res, Ok((chunk, 12)));
+}
+
+#[test]
+fn test_pack_sctp_asconf() {
+    let addr4 = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+    let addr6 = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+    let chunk = SctpChunk::Asconf(SctpAsconfChunk {
+        serial: 0x12345678,
+        addr: addr4,
+        params: vec![
+            SctpParameter::AddIp(1, addr6),
+            SctpParameter::DeleteIp(2, addr4),
+            SctpParameter::SetPrimary(3, addr6),
+        ],
+    });
+    let mut packed = Vec::new();
+    chunk.to_bytes(&mut packed).unwrap();
+    assert_eq!(packed.len(), chunk.bytes_len());
+    assert_eq!(
+        &packed[..8],
+        &[0xc1, 0x00, 0x00, 0x58, 0x12, 0x34, 0x56, 0x78]
+    );
+    assert_eq!(&packed[16..20], &[0xc0, 0x01, 0x00, 0x1c]);
+
+    let res = SctpChunk::from_bytes(&packed);
+    assert_eq!(res, Ok((chunk, packed.len())));
+
+    let mut tlv = Vec::new();
+    SctpParameter::DeleteIp(2, addr4)
+        .to_bytes(&mut tlv)
+        .unwrap();
+    let chunk = SctpChunk::AsconfAck(SctpAsconfAckChunk {
+        serial: 0x12345678,
+        params: vec![
+            SctpParameter::SuccessIndication(1),
+            SctpParameter::ErrorCauseIndication(2, vec![SctpErrorCause::DeleteLastAddr(tlv)]),
+            SctpParameter::ErrorCauseIndication(
+                3,
+                vec![SctpErrorCause::UnresolvableAddr(
+                    SctpParameterType::Ipv6,
+                    20,
+                    Vec::from(&[0; 16][..]),
+                )],
+            ),
+        ],
+    });
+    let mut packed = Vec::new();
+    chunk.to_bytes(&mut packed).unwrap();
+    assert_eq!(packed.len(), chunk.bytes_len());
+    assert_eq!(&packed[..4], &[0x80, 0x00, 0x00, 0x4c]);
+
+    let res = SctpChunk::from_bytes(&packed);
+    assert_eq!(res, Ok((chunk, packed.len())));
+}
+
+#[test]
+fn test_pack_sctp_auth() {
+    let chunk = SctpChunk::Auth(SctpAuthChunk {
+        shared_key_id: 0,
+        hmac_id: SctpHmacAlgoId::Sha1,
+        hmac: vec![0xab; 20],
+    });
+    let mut packed = Vec::new();
+    chunk.to_bytes(&mut packed).unwrap();
+    assert_eq!(packed.len(), chunk.bytes_len());
+    assert_eq!(
+        &packed[..8],
+        &[0x0f, 0x00, 0x00, 0x1c, 0x00, 0x00, 0x00, 0x01]
+    );
+    assert_eq!(&packed[8..], &[0xab; 20][..]);
+
+    let res = SctpChunk::from_bytes(&packed);
+    assert_eq!(res, Ok((chunk, packed.len())));
+}
+
+#[test]
+fn test_pack_sctp_ecne_cwr() {
+    let chunk = SctpChunk::Ecne(0x12345678);
+    let mut packed = Vec::new();
+    chunk.to_bytes(&mut packed).unwrap();
+    assert_eq!(packed.len(), chunk.bytes_len());
+    assert_eq!(packed, &[0x0c, 0x00, 0x00, 0x08, 0x12, 0x34, 0x56, 0x78]);
+    let res = SctpChunk::from_bytes(&packed);
+    assert_eq!(res, Ok((chunk, 8)));
+
+    let chunk = SctpChunk::Cwr(0x12345678);
+    let mut packed = Vec::new();
+    chunk.to_bytes(&mut packed).unwrap();
+    assert_eq!(packed, &[0x0d, 0x00, 0x00, 0x08, 0x12, 0x34, 0x56, 0x78]);
+    let res = SctpChunk::from_bytes(&packed);
+    assert_eq!(res, Ok((chunk, 8)));
+}
+
+#[test]
+fn test_pack_sctp_abort_with_error_cause() {
+    let causes = vec![
+        SctpErrorCause::NoUserData(4094720724),
+        SctpErrorCause::ProtocolViolation(Vec::from("DATA chunk".as_bytes())),
+        SctpErrorCause::ProtocolViolation(Vec::from("SACK".as_bytes())),
+    ];
+    for cause in causes {
+        let chunk = SctpChunk::Abort(SctpAbortChunk {
+            t_bit: false,
+            error_cause: Some(cause),
+        });
+        let mut packed = Vec::new();
+        chunk.to_bytes(&mut packed).unwrap();
+        assert_eq!(packed.len(), chunk.bytes_len());
+
+        let res = SctpChunk::from_bytes(&packed);
+        assert_eq!(res, Ok((chunk, packed.len())));
+    }
+}
+
+#[test]
+fn test_parse_sctp_invalid_chunk() {
+    // Chunk lengths shorter than the chunk header or DATA header.
+    let res = SctpChunk::from_bytes(&[3, 0, 0, 2]);
+    assert_eq!(
+        res,
+        Err(SctpError::InvalidChunk(SctpParseError {
+            chunk_type: Some(SctpChunkType::Sack),
+            param_type: None,
+            offset: 0,
+        }))
+    );
+    let res = SctpChunk::from_bytes(&[0, 3, 0, 8, 0, 0, 0, 1]);
+    assert_eq!(
+        res,
+        Err(SctpError::InvalidChunk(SctpParseError {
+            chunk_type: Some(SctpChunkType::Data),
+            param_type: None,
+            offset: 0,
+        }))
+    );
+
+    // Supported Extensions parameter with a broken length.
+    let data: &[u8] = include_bytes!("../assets/sctp_init.bin");
+    let mut chunk = Vec::from(&data[12..]);
+    chunk[30] = 0;
+    chunk[31] = 2;
+    let res = SctpChunk::from_bytes(&chunk);
+    assert_eq!(
+        res,
+        Err(SctpError::InvalidChunk(SctpParseError {
+            chunk_type: Some(SctpChunkType::Init),
+            param_type: Some(SctpParameterType::SupportedExts),
+            offset: 28,
+        }))
+    );
+}
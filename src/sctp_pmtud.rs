@@ -0,0 +1,265 @@
+use std::time::{Duration, Instant};
+
+// RFC 8899 Section 5.1.2
+pub const BASE_PLPMTU: usize = 1200;
+const MAX_PROBES: u32 = 3;
+const PMTU_RAISE_TIMER: Duration = Duration::from_secs(600);
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SctpPmtudState {
+    // Confirming that BASE_PLPMTU works.
+    Base,
+    // Probing for a larger PLPMTU.
+    Search,
+    // The PLPMTU is found, a new search starts after PMTU_RAISE_TIMER.
+    SearchComplete,
+    // Even BASE_PLPMTU was not confirmed.
+    Error,
+}
+
+// Datagram Packetization Layer PMTU Discovery (RFC 8899) for a single path.
+// All sizes include the IP, UDP and SCTP common headers.
+#[derive(Debug)]
+pub struct SctpPmtud {
+    state: SctpPmtudState,
+    base_pmtu: usize,
+    max_pmtu: usize,
+    pmtu: usize,
+    search_high: usize,
+
+    probe_size: Option<usize>,
+    probe_count: u32,
+    black_hole_count: u32,
+    raise_timeout: Option<Instant>,
+}
+
+impl SctpPmtud {
+    pub fn new(base_pmtu: usize, max_pmtu: usize) -> Self {
+        SctpPmtud {
+            state: SctpPmtudState::Base,
+            base_pmtu,
+            max_pmtu,
+            pmtu: base_pmtu,
+            search_high: max_pmtu,
+            probe_size: None,
+            probe_count: 0,
+            black_hole_count: 0,
+            raise_timeout: None,
+        }
+    }
+
+    pub fn get_pmtu(&self) -> usize {
+        self.pmtu
+    }
+
+    pub fn get_max_pmtu(&self) -> usize {
+        self.max_pmtu
+    }
+
+    pub fn get_state(&self) -> SctpPmtudState {
+        self.state
+    }
+
+    pub fn get_timeout(&self) -> Option<Instant> {
+        self.raise_timeout
+    }
+
+    pub fn on_timeout(&mut self, now: Instant) {
+        if let Some(timeout) = self.raise_timeout {
+            if timeout <= now {
+                self.raise_timeout = None;
+                self.search_high = self.max_pmtu;
+                self.state = match self.state {
+                    SctpPmtudState::Error => SctpPmtudState::Base,
+                    _ => SctpPmtudState::Search,
+                };
+            }
+        }
+    }
+
+    // The size of the next probe, if one should be sent now
+    pub fn get_probe_size(&self) -> Option<usize> {
+        if self.probe_size.is_some() {
+            return None;
+        }
+        match self.state {
+            SctpPmtudState::Base => Some(self.base_pmtu),
+            SctpPmtudState::Search => {
+                // Binary search between the confirmed PLPMTU and the smallest
+                // size that has not failed yet, rounded up to 4 bytes.
+                let sum = self.pmtu + self.search_high;
+                let size = (sum - sum / 2 + 3) & !3;
+                Some(std::cmp::min(size, self.search_high))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn on_probe_sent(&mut self, size: usize) {
+        self.probe_size = Some(size);
+    }
+
+    pub fn on_probe_acked(&mut self, now: Instant) {
+        let size = match self.probe_size.take() {
+            Some(v) => v,
+            None => return,
+        };
+        self.probe_count = 0;
+        if size > self.pmtu {
+            self.pmtu = size;
+        }
+        if self.state == SctpPmtudState::Base || self.state == SctpPmtudState::Error {
+            self.state = SctpPmtudState::Search;
+        }
+        self.check_search_complete(now);
+    }
+
+    pub fn on_probe_lost(&mut self, now: Instant) {
+        let size = match self.probe_size.take() {
+            Some(v) => v,
+            None => return,
+        };
+        self.probe_count += 1;
+        if self.probe_count < MAX_PROBES {
+            return;
+        }
+        self.probe_count = 0;
+        match self.state {
+            SctpPmtudState::Base => {
+                self.state = SctpPmtudState::Error;
+                self.raise_timeout = Some(now + PMTU_RAISE_TIMER);
+            }
+            SctpPmtudState::Search => {
+                self.search_high = size - 4;
+                self.check_search_complete(now);
+            }
+            _ => {}
+        }
+    }
+
+    fn check_search_complete(&mut self, now: Instant) {
+        if self.state == SctpPmtudState::Search && self.search_high <= self.pmtu {
+            self.state = SctpPmtudState::SearchComplete;
+            self.raise_timeout = Some(now + PMTU_RAISE_TIMER);
+        }
+    }
+
+    // Packets larger than BASE_PLPMTU that are lost repeatedly mean the
+    // PLPMTU no longer works (RFC 8899 Section 4.3). Returns true if the
+    // PLPMTU was reduced.
+    pub fn on_packet_lost(&mut self, size: usize) -> bool {
+        if size <= self.base_pmtu || self.pmtu <= self.base_pmtu {
+            return false;
+        }
+        self.black_hole_count += 1;
+        if self.black_hole_count < MAX_PROBES {
+            return false;
+        }
+        self.black_hole_count = 0;
+        self.search_high = self.pmtu - 4;
+        self.pmtu = self.base_pmtu;
+        self.probe_size = None;
+        self.probe_count = 0;
+        self.raise_timeout = None;
+        self.state = SctpPmtudState::Base;
+        true
+    }
+
+    // A Packet Too Big message lowers the PLPMTU at once, it is never
+    // raised by one (RFC 8899 Section 4.6.2). Returns true if the PLPMTU
+    // was reduced.
+    pub fn on_packet_too_big(&mut self, mtu: usize, now: Instant) -> bool {
+        let mtu = std::cmp::max(mtu & !3, self.base_pmtu);
+        if mtu >= self.pmtu {
+            if self.state == SctpPmtudState::Search && mtu < self.search_high {
+                self.search_high = mtu;
+                self.check_search_complete(now);
+            }
+            return false;
+        }
+        self.pmtu = mtu;
+        self.search_high = mtu;
+        self.probe_size = None;
+        self.probe_count = 0;
+        self.black_hole_count = 0;
+        self.state = SctpPmtudState::SearchComplete;
+        self.raise_timeout = Some(now + PMTU_RAISE_TIMER);
+        true
+    }
+
+    pub fn on_packet_acked(&mut self, size: usize) {
+        if size > self.base_pmtu {
+            self.black_hole_count = 0;
+        }
+    }
+}
+
+#[test]
+fn test_pmtud_search() {
+    let now = Instant::now();
+    let mut pmtud = SctpPmtud::new(1200, 1500);
+    assert_eq!(pmtud.get_pmtu(), 1200);
+
+    // The path supports up to 1400 bytes.
+    while let Some(size) = pmtud.get_probe_size() {
+        pmtud.on_probe_sent(size);
+        if size <= 1400 {
+            pmtud.on_probe_acked(now);
+        } else {
+            pmtud.on_probe_lost(now);
+        }
+    }
+    assert_eq!(pmtud.get_state(), SctpPmtudState::SearchComplete);
+    assert_eq!(pmtud.get_pmtu(), 1400);
+
+    // Searching again after the raise timer.
+    pmtud.on_timeout(now + PMTU_RAISE_TIMER);
+    assert_eq!(pmtud.get_state(), SctpPmtudState::Search);
+    assert_eq!(pmtud.get_probe_size(), Some(1452));
+}
+
+#[test]
+fn test_pmtud_black_hole() {
+    let now = Instant::now();
+    let mut pmtud = SctpPmtud::new(1200, 1500);
+    while let Some(size) = pmtud.get_probe_size() {
+        pmtud.on_probe_sent(size);
+        pmtud.on_probe_acked(now);
+    }
+    assert_eq!(pmtud.get_pmtu(), 1500);
+
+    // Small packets do not count.
+    assert!(!pmtud.on_packet_lost(1000));
+    assert!(!pmtud.on_packet_lost(1500));
+    pmtud.on_packet_acked(1500);
+    assert!(!pmtud.on_packet_lost(1500));
+    assert!(!pmtud.on_packet_lost(1500));
+    assert!(pmtud.on_packet_lost(1500));
+    assert_eq!(pmtud.get_pmtu(), 1200);
+    assert_eq!(pmtud.get_state(), SctpPmtudState::Base);
+
+    // BASE_PLPMTU is confirmed again, and the failed size is not probed.
+    pmtud.on_probe_sent(pmtud.get_probe_size().unwrap());
+    pmtud.on_probe_acked(now);
+    assert_eq!(pmtud.get_state(), SctpPmtudState::Search);
+    assert!(pmtud.get_probe_size().unwrap() < 1500);
+}
+
+#[test]
+fn test_pmtud_packet_too_big() {
+    let now = Instant::now();
+    let mut pmtud = SctpPmtud::new(1200, 1500);
+    while let Some(size) = pmtud.get_probe_size() {
+        pmtud.on_probe_sent(size);
+        pmtud.on_probe_acked(now);
+    }
+
+    assert!(!pmtud.on_packet_too_big(1500, now));
+    assert!(pmtud.on_packet_too_big(1281, now));
+    assert_eq!(pmtud.get_pmtu(), 1280);
+    assert_eq!(pmtud.get_state(), SctpPmtudState::SearchComplete);
+
+    // Never below BASE_PLPMTU.
+    assert!(pmtud.on_packet_too_big(576, now));
+    assert_eq!(pmtud.get_pmtu(), 1200);
+}
@@ -1,13 +1,16 @@
-use std::collections::{BTreeMap, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 use std::time::{Duration, Instant};
 
 use sna::SerialNumber;
 
 use crate::Result;
 use crate::SctpError;
+use crate::{SctpEvent, SctpPathParams, SctpPathStatus, SctpSwitchBackPolicy};
 
 use crate::sctp_collections::{SctpBTreeMap, SctpTsnQueue};
+use crate::sctp_congestion::{CongestionController, SctpCongestionControl};
 pub use crate::sctp_pkt::*;
+use crate::sctp_pmtud::{SctpPmtud, BASE_PLPMTU};
 
 const RTO_INITIAL: Duration = Duration::from_secs(3);
 const RTO_MIN: Duration = Duration::from_secs(1);
@@ -18,10 +21,19 @@ const RTO_BETA: f64 = 1.0 / 4.0;
 const DUP_THRESH: usize = 3;
 
 const MAX_PATH_RETRANS: u32 = 5;
-const _MAX_INIT_RETRANS: u32 = 8;
+const MAX_PATH_RETRANS_LIMIT: u32 = 31; // The RTO backoff factor fits in a u32.
+const PF_MAX_RETRANS: u32 = 0; // RFC 7829 Section 8
+const MAX_INIT_RETRANS: u32 = 8;
+const MAX_ASSOC_RETRANS: u32 = 10;
+
+const SHUTDOWN_GUARD_TIMEOUT: Duration = Duration::from_secs(5 * 60); // 5 * RTO.Max
 
 const HB_INTERVAL: Duration = Duration::from_secs(30);
-const _HB_MAX_BURST: u32 = 1;
+// HEARTBEATs to unconfirmed addresses per RTO (RFC 9260 Section 5.4)
+const HB_MAX_BURST: u32 = 1;
+
+// The smallest fixed path MTU, the IPv4 minimum reassembly buffer size
+const MIN_FIXED_PMTU: usize = 576;
 
 #[derive(Debug)]
 pub struct SctpRecovery {
@@ -29,13 +41,35 @@ pub struct SctpRecovery {
     shutdown_pending: bool,
     shutdown_received: bool,
     closing: bool,
+    shutdown_guard_expired: bool,
 
     rwnd: usize,
     fast_recovery: bool,
     recovery_point: Option<u32>,
+    // The largest TSN sent when the cwnd was last reduced for an ECNE
+    ecn_recovery_point: Option<SerialNumber<u32>>,
 
     path_list: Vec<Option<SctpPath>>,
     primary_path: Option<usize>,
+    requested_primary_path: Option<usize>,
+    active_path: Option<usize>,
+    switch_back_policy: SctpSwitchBackPolicy,
+    primary_switchover: bool,
+    pf_max_retrans: u32,
+    congestion_control: SctpCongestionControl,
+
+    // Concurrent Multipath Transfer: new data goes to every usable path.
+    cmt: bool,
+    next_cmt_path: usize,
+    // New data is released at the pacing rate of each path.
+    pacing: bool,
+    // The lowest and highest TSN newly acknowledged on each path by the SACK
+    // being processed, for Split Fast Retransmit
+    newly_acked_by_path: BTreeMap<usize, (SerialNumber<u32>, SerialNumber<u32>)>,
+
+    // Path verification is rate limited to HB_MAX_BURST HEARTBEATs per RTO.
+    verification_start: Option<Instant>,
+    verification_count: u32,
 
     largest_tsn: SerialNumber<u32>,
 
@@ -48,10 +82,17 @@ pub struct SctpRecovery {
     total_flight: usize,
     total_flight_count: usize,
     t2_shutdown_timeout: Option<Instant>,
+    t5_shutdown_guard_timeout: Option<Instant>,
+    t4_rto_timeout: Option<Instant>,
+    asconf_sent: Option<SctpTransmitControlChunk>,
+
+    error_count: u32,
+    init_retrans_count: u32,
 
     data_sent: SctpTsnQueue<SctpTransmitData>,
     pub control_waiting_trans: SctpBTreeMap<u64, (SctpChunk, usize)>,
     next_control_sequence: SerialNumber<u64>,
+    pub pmtu_probe_waiting_trans: VecDeque<(SctpChunk, usize, usize)>,
     pub tsn_waiting_t3_retrans: SctpBTreeMap<u32, (usize, usize, bool)>,
     pub tsn_waiting_fast_retrans: SctpBTreeMap<u32, (usize, usize, bool)>,
 
@@ -74,16 +115,29 @@ struct SctpPath {
     rttvar: Duration,
 
     mtu: usize,
+    overhead: usize,
+    pmtud: SctpPmtud,
+    probe_sequence: Option<u64>,
     flight: usize,
     flight_count: usize,
     ack: usize,
-    cwnd: usize,
-    ssthresh: usize,
-    partial_bytes_acked: usize,
+    cc: Box<dyn CongestionController>,
     recovery_point: Option<SerialNumber<u64>>,
+    // When the next packet of new data may be sent, if paced
+    pacing_time: Option<Instant>,
+    // RFC 7661: the largest flight in the current window of data, which
+    // ends with the given sequence, and whether the sender ran out of data
+    // in it. The last window was application limited if both happened with
+    // less than half of the cwnd used.
+    max_flight: usize,
+    window_end: Option<SerialNumber<u64>>,
+    out_of_data: bool,
+    app_limited: bool,
 
     next_hb_sequence: u64,
     next_sequence: SerialNumber<u64>,
+    // The first DATA chunk of the packet being built
+    packet_start: SerialNumber<u64>,
     lowest_sequence: Option<SerialNumber<u64>>,
 
     t1_timeout: Option<Instant>,
@@ -92,6 +146,14 @@ struct SctpPath {
 
     retrans_count: u32,
     retrans_threshold: u32,
+    pf_threshold: u32,
+
+    hb_interval: Duration,
+    hb_enabled: bool,
+    pmtud_enabled: bool,
+    rto_initial: Duration,
+    rto_min: Duration,
+    rto_max: Duration,
 
     control_sent: VecDeque<SctpTransmitControlChunk>,
     heartbeat_sent: SctpBTreeMap<u64, SctpTransmitHeartbeatChunk>,
@@ -109,6 +171,7 @@ struct SctpPath {
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum SctpPathState {
     Active,
+    PotentiallyFailed,
     InActive,
 }
 
@@ -154,6 +217,9 @@ struct SctpTransmitDataInfo {
     sequence: SerialNumber<u64>,
     tsn: SerialNumber<u32>,
     bytes_len: usize,
+    // The size of the packet the chunk was sent in, including the IP and
+    // UDP headers
+    pkt_len: usize,
     state: SctpTransmitDataState,
     time: Instant,
     do_rtt: bool,
@@ -171,6 +237,7 @@ impl SctpRecovery {
             shutdown_pending: false,
             shutdown_received: false,
             closing: false,
+            shutdown_guard_expired: false,
             peer_cumulative_tsn_ack: None,
             rwnd: 0,
             largest_tsn: initial_tsn_minus1,
@@ -181,14 +248,33 @@ impl SctpRecovery {
             total_flight_count: 0,
             path_list: Vec::new(),
             primary_path: None,
+            requested_primary_path: None,
+            active_path: None,
+            switch_back_policy: SctpSwitchBackPolicy::Immediate,
+            congestion_control: SctpCongestionControl::Reno,
+            primary_switchover: false,
+            pf_max_retrans: PF_MAX_RETRANS,
+            cmt: false,
+            next_cmt_path: 0,
+            pacing: false,
+            newly_acked_by_path: BTreeMap::new(),
+            verification_start: None,
+            verification_count: 0,
             data_sent: SctpTsnQueue::new(SerialNumber(init_tsn)),
             control_waiting_trans: SctpBTreeMap::new(),
             next_control_sequence: SerialNumber(0),
+            pmtu_probe_waiting_trans: VecDeque::new(),
             tsn_waiting_t3_retrans: SctpBTreeMap::new(),
             tsn_waiting_fast_retrans: SctpBTreeMap::new(),
             fast_recovery: false,
             recovery_point: None,
+            ecn_recovery_point: None,
             t2_shutdown_timeout: None,
+            t5_shutdown_guard_timeout: None,
+            t4_rto_timeout: None,
+            asconf_sent: None,
+            error_count: 0,
+            init_retrans_count: 0,
             trace_id: trace_id,
         };
         Ok(recovery)
@@ -202,9 +288,28 @@ impl SctpRecovery {
         self.established = true;
     }
 
-    pub fn add_path(&mut self, mtu: usize) -> usize {
+    // The path MTU starts at BASE_PLPMTU and is raised by probing up to
+    // max_pmtu. The overhead covers the IP, UDP and SCTP common headers.
+    pub fn add_path(&mut self, max_pmtu: usize, overhead: usize) -> usize {
         let pathid = self.path_list.len();
-        self.path_list.push(Some(SctpPath {
+        let path = self.new_path(pathid, max_pmtu, overhead);
+        self.path_list.push(Some(path));
+        pathid
+    }
+
+    // A removed path keeps its pathid, and gets it back when the address is added again.
+    pub fn restore_path(&mut self, pathid: usize, max_pmtu: usize, overhead: usize) -> Result<()> {
+        if pathid >= self.path_list.len() || self.path_list[pathid].is_some() {
+            return Err(SctpError::InvalidPathId);
+        }
+        self.path_list[pathid] = Some(self.new_path(pathid, max_pmtu, overhead));
+        Ok(())
+    }
+
+    fn new_path(&self, pathid: usize, max_pmtu: usize, overhead: usize) -> SctpPath {
+        let pmtud = SctpPmtud::new(std::cmp::min(BASE_PLPMTU, max_pmtu), max_pmtu);
+        let mtu = pmtud.get_pmtu() - overhead;
+        SctpPath {
             id: pathid,
             confirmed: false,
             random_value: rand::random::<u64>(),
@@ -214,19 +319,33 @@ impl SctpRecovery {
             srtt: None,
             rttvar: Duration::new(0, 0),
             mtu: mtu,
-            cwnd: mtu * 4,
-            ssthresh: std::usize::MAX,
+            overhead,
+            pmtud,
+            probe_sequence: None,
+            cc: self.congestion_control.new_controller(mtu),
             recovery_point: None,
+            pacing_time: None,
+            max_flight: 0,
+            window_end: None,
+            out_of_data: false,
+            app_limited: false,
             last_time: None,
             next_hb_sequence: 0,
             next_sequence: SerialNumber(0),
+            packet_start: SerialNumber(0),
             lowest_sequence: None,
             retrans_count: 0,
             retrans_threshold: MAX_PATH_RETRANS,
+            pf_threshold: self.pf_max_retrans,
+            hb_interval: HB_INTERVAL,
+            hb_enabled: true,
+            pmtud_enabled: true,
+            rto_initial: RTO_INITIAL,
+            rto_min: RTO_MIN,
+            rto_max: RTO_MAX,
             flight: 0,
             flight_count: 0,
             ack: 0,
-            partial_bytes_acked: 0,
             t1_timeout: None,
             t3_retrans_timeout: None,
             heartbeat_timeout: None,
@@ -238,8 +357,102 @@ impl SctpRecovery {
             wait_fast_retrans: false,
             fast_recovery: false,
             trace_id: self.trace_id.clone(),
-        }));
-        pathid
+        }
+    }
+
+    // Outstanding DATA and chunks waiting for transmission on the removed path
+    // move to another path. The last path cannot be removed.
+    pub fn remove_path(&mut self, pathid: usize) -> Result<()> {
+        if self.get_path(pathid).is_none() {
+            return Err(SctpError::InvalidPathId);
+        }
+        let alternate = match self.find_alternate_path(pathid).or_else(|| {
+            self.path_list
+                .iter()
+                .flatten()
+                .filter(|path| path.id != pathid)
+                .min_by_key(|path| !path.confirmed)
+                .map(|path| path.id)
+        }) {
+            Some(v) => v,
+            None => return Err(SctpError::InvalidPathId),
+        };
+        trace!(
+            "{} remove path pathid={}, alternate={}",
+            self.trace_id,
+            pathid,
+            alternate
+        );
+        let removed = self.path_list[pathid].take().unwrap();
+        if !removed.control_sent.is_empty() {
+            // INIT or COOKIE-ECHO is retransmitted on the alternate path.
+            let path = self.get_path_mut(alternate).unwrap();
+            for mut tmit_ctrl in removed.control_sent {
+                tmit_ctrl.pathid = alternate;
+                path.control_sent.push_back(tmit_ctrl);
+            }
+            if path.t1_timeout.is_none() {
+                path.t1_timeout = removed.t1_timeout;
+            }
+        }
+
+        let mut first = true;
+        for (tsn, tmit_data) in &mut self.data_sent {
+            if tmit_data.pathid != pathid {
+                continue;
+            }
+            tmit_data.pathid = alternate;
+            if tmit_data.state == SctpTransmitDataState::Sent {
+                self.total_flight -= tmit_data.bytes_len;
+                self.total_flight_count -= 1;
+                tmit_data.state = SctpTransmitDataState::Lost;
+                self.tsn_waiting_t3_retrans
+                    .insert(tsn.0, (alternate, tmit_data.bytes_len, first));
+                first = false;
+            }
+        }
+        for waiting in [
+            &mut self.tsn_waiting_t3_retrans,
+            &mut self.tsn_waiting_fast_retrans,
+        ] {
+            for tsn in waiting.keys().copied().collect::<Vec<u32>>() {
+                if let Some((pathid1, _, _)) = waiting.get_mut(&tsn) {
+                    if *pathid1 == pathid {
+                        *pathid1 = alternate;
+                    }
+                }
+            }
+        }
+        for sequence in self
+            .control_waiting_trans
+            .keys()
+            .copied()
+            .collect::<Vec<u64>>()
+        {
+            if let Some((_, pathid1)) = self.control_waiting_trans.get_mut(&sequence) {
+                if *pathid1 == pathid {
+                    *pathid1 = alternate;
+                }
+            }
+        }
+        self.pmtu_probe_waiting_trans
+            .retain(|(_, pathid1, _)| *pathid1 != pathid);
+        if let Some(tmit_ctrl) = &mut self.asconf_sent {
+            if tmit_ctrl.pathid == pathid {
+                tmit_ctrl.pathid = alternate;
+            }
+        }
+
+        if self.primary_path == Some(pathid) {
+            self.primary_path = Some(alternate);
+        }
+        if self.requested_primary_path == Some(pathid) {
+            self.requested_primary_path = None;
+        }
+        if self.active_path == Some(pathid) {
+            self.active_path = None;
+        }
+        Ok(())
     }
 
     pub fn get_next_tsn(&mut self) -> u32 {
@@ -247,6 +460,36 @@ impl SctpRecovery {
         self.largest_tsn.0
     }
 
+    pub fn get_largest_tsn(&self) -> u32 {
+        self.largest_tsn.0
+    }
+
+    pub fn get_cum_ack(&self) -> u32 {
+        self.cum_ack.0
+    }
+
+    pub fn get_peer_rwnd(&self) -> usize {
+        self.rwnd
+    }
+
+    // DATA chunks are dropped from data_sent once cumulatively acknowledged.
+    pub fn get_unacked_count(&self) -> usize {
+        self.data_sent.iter().count()
+    }
+
+    // Whether a confirmed path other than the one given remains.
+    pub fn has_other_confirmed_path(&self, pathid: usize) -> bool {
+        self.path_list
+            .iter()
+            .flatten()
+            .any(|path| path.id != pathid && path.confirmed)
+    }
+
+    // Including removed paths, whose pathids are not reused.
+    pub fn get_path_count(&self) -> usize {
+        self.path_list.len()
+    }
+
     fn get_path(&self, pathid: usize) -> Option<&SctpPath> {
         if let Some(opt) = self.path_list.get(pathid) {
             if let Some(path) = opt {
@@ -294,6 +537,7 @@ impl SctpRecovery {
             if let Some((confirmed, state)) = path.get_state() {
                 if confirmed && state == SctpPathState::Active {
                     self.primary_path = Some(pathid);
+                    self.active_path = None;
                     return Ok(());
                 }
             }
@@ -301,6 +545,285 @@ impl SctpRecovery {
         Err(SctpError::InvalidPathId)
     }
 
+    // An unconfirmed path becomes the primary path once it is confirmed
+    // (RFC 5061 Section 4.2.5).
+    pub fn request_primary_path(&mut self, pathid: usize) -> Result<()> {
+        if self.set_primary_path(pathid).is_ok() {
+            self.requested_primary_path = None;
+            return Ok(());
+        }
+        match self.get_path(pathid) {
+            Some(path) if !path.confirmed => {
+                self.requested_primary_path = Some(pathid);
+                Ok(())
+            }
+            _ => Err(SctpError::InvalidPathId),
+        }
+    }
+
+    // The path used for new data and SACKs, the primary path unless it has failed
+    pub fn get_active_path(&self) -> Option<usize> {
+        self.active_path.or(self.primary_path)
+    }
+
+    pub fn set_switch_back_policy(&mut self, policy: SctpSwitchBackPolicy) {
+        self.switch_back_policy = policy;
+    }
+
+    fn is_path_usable(&self, pathid: usize) -> bool {
+        self.get_path_state(pathid) == Ok((true, SctpPathState::Active))
+    }
+
+    // The confirmed and active path with the smallest RTO
+    fn find_alternate_path(&self, exclude: usize) -> Option<usize> {
+        self.path_list
+            .iter()
+            .filter_map(|x| x.as_ref())
+            .filter(|path| {
+                path.id != exclude && path.confirmed && path.state == SctpPathState::Active
+            })
+            .min_by_key(|path| path.get_rto())
+            .map(|path| path.id)
+    }
+
+    // Retransmissions go to an alternate active destination if the path
+    // timed out (RFC 9260 Section 6.4). The pending retransmission moves
+    // along with them, and the flight is accounted on the new path when sent.
+    fn get_retrans_path(&mut self, pathid: usize, fast_retrans: bool) -> usize {
+        let timed_out = match self.get_path(pathid) {
+            Some(path) => !fast_retrans || path.retrans_count > 0,
+            None => false,
+        };
+        let retrans_pathid = match self.find_alternate_path(pathid) {
+            Some(v) if timed_out => v,
+            _ => return pathid,
+        };
+
+        let path = self.get_path_mut(pathid).unwrap();
+        let wait_retrans = if fast_retrans {
+            std::mem::replace(&mut path.wait_fast_retrans, false)
+        } else {
+            std::mem::replace(&mut path.wait_t3_retrans, false)
+        };
+        let retrans_path = self.get_path_mut(retrans_pathid).unwrap();
+        if fast_retrans {
+            retrans_path.wait_fast_retrans |= wait_retrans;
+        } else {
+            retrans_path.wait_t3_retrans |= wait_retrans;
+        }
+        trace!(
+            "{} retransmit to alternate path pathid={}, retrans_pathid={}",
+            self.trace_id,
+            pathid,
+            retrans_pathid
+        );
+        retrans_pathid
+    }
+
+    // The confirmed path that failed least recently, used when every path is
+    // potentially failed (RFC 7829 Section 4)
+    fn find_potentially_failed_path(&self) -> Option<usize> {
+        self.path_list
+            .iter()
+            .filter_map(|x| x.as_ref())
+            .filter(|path| path.confirmed && path.state == SctpPathState::PotentiallyFailed)
+            .min_by_key(|path| path.retrans_count)
+            .map(|path| path.id)
+    }
+
+    // Moves new data and SACKs away from a failed primary path and back
+    // again once it is reachable (RFC 9260 Section 6.4). With the primary
+    // path switchover (RFC 7829 Section 5), the new path becomes the primary.
+    pub fn update_active_path(&mut self) -> Option<SctpEvent> {
+        let primary = self.primary_path?;
+        let current = self.get_active_path()?;
+        let next = if self.is_path_usable(primary) {
+            if current == primary
+                || self.switch_back_policy == SctpSwitchBackPolicy::Immediate
+                || !self.is_path_usable(current)
+            {
+                primary
+            } else {
+                current
+            }
+        } else if current != primary && self.is_path_usable(current) {
+            current
+        } else if let Some(pathid) = self.find_alternate_path(current) {
+            pathid
+        } else {
+            self.find_potentially_failed_path().unwrap_or(current)
+        };
+
+        if next == current {
+            return None;
+        }
+
+        trace!(
+            "{} active path changed from pathid={} to pathid={}",
+            self.trace_id,
+            current,
+            next
+        );
+        if next == primary {
+            self.active_path = None;
+            Some(SctpEvent::PathSwitchBack {
+                from: current,
+                to: next,
+            })
+        } else if self.primary_switchover && self.is_path_usable(next) {
+            self.primary_path = Some(next);
+            self.active_path = None;
+            Some(SctpEvent::PrimaryPathSwitchover {
+                from: current,
+                to: next,
+            })
+        } else {
+            self.active_path = Some(next);
+            Some(SctpEvent::PathFailover {
+                from: current,
+                to: next,
+            })
+        }
+    }
+
+    pub fn set_primary_switchover(&mut self, enabled: bool) {
+        self.primary_switchover = enabled;
+    }
+
+    pub fn set_pf_max_retrans(&mut self, pf_max_retrans: u32) {
+        self.pf_max_retrans = pf_max_retrans;
+        for path in self.path_list.iter_mut().filter_map(|x| x.as_mut()) {
+            path.pf_threshold = pf_max_retrans;
+        }
+    }
+
+    pub fn get_path_params(&self, pathid: usize) -> Result<SctpPathParams> {
+        match self.get_path(pathid) {
+            Some(path) => Ok(path.get_params()),
+            None => Err(SctpError::InvalidPathId),
+        }
+    }
+
+    pub fn get_path_status(&self, pathid: usize) -> Result<SctpPathStatus> {
+        match self.get_path(pathid) {
+            Some(path) => Ok(path.get_status()),
+            None => Err(SctpError::InvalidPathId),
+        }
+    }
+
+    pub fn set_path_params(
+        &mut self,
+        pathid: usize,
+        params: &SctpPathParams,
+        now: Instant,
+    ) -> Result<()> {
+        let path = match self.get_path_mut(pathid) {
+            Some(v) => v,
+            None => return Err(SctpError::InvalidPathId),
+        };
+        if params.rto_min.as_millis() == 0
+            || params.rto_min > params.rto_initial
+            || params.rto_initial > params.rto_max
+            || params.max_retrans == 0
+            || params.max_retrans > MAX_PATH_RETRANS_LIMIT
+        {
+            return Err(SctpError::InvalidValue);
+        }
+        if !params.pmtud_enabled
+            && (params.pmtu < MIN_FIXED_PMTU || params.pmtu > path.pmtud.get_max_pmtu())
+        {
+            return Err(SctpError::InvalidValue);
+        }
+        path.set_params(params, now);
+        Ok(())
+    }
+
+    pub fn set_congestion_control(&mut self, congestion_control: SctpCongestionControl) {
+        self.congestion_control = congestion_control;
+        for path in self.path_list.iter_mut().flatten() {
+            path.cc = congestion_control.new_controller(path.mtu);
+        }
+    }
+
+    pub fn set_cmt(&mut self, enabled: bool) {
+        self.cmt = enabled;
+    }
+
+    pub fn is_cmt_enabled(&self) -> bool {
+        self.cmt
+    }
+
+    // Nothing is left to send while the path may have room in its cwnd.
+    pub fn on_out_of_data(&mut self, pathid: usize) {
+        if let Some(path) = self.get_path_mut(pathid) {
+            path.out_of_data = true;
+        }
+    }
+
+    pub fn set_pacing(&mut self, enabled: bool) {
+        self.pacing = enabled;
+        if !enabled {
+            for path in self.path_list.iter_mut().flatten() {
+                path.pacing_time = None;
+            }
+        }
+    }
+
+    // Whether new data on the path has to wait for its pacing time
+    pub fn is_paced(&self, pathid: usize, now: Instant) -> bool {
+        match self.get_path(pathid) {
+            Some(path) if self.pacing => matches!(path.pacing_time, Some(time) if time > now),
+            _ => false,
+        }
+    }
+
+    pub fn get_pacing_timeout(&self, now: Instant) -> Option<Instant> {
+        if !self.pacing {
+            return None;
+        }
+        self.path_list
+            .iter()
+            .flatten()
+            .filter_map(|path| path.pacing_time)
+            .filter(|time| *time > now)
+            .min()
+    }
+
+    // The path for the next packet of new data. With CMT, the usable paths
+    // take turns as long as their congestion window has room.
+    pub fn get_data_path(&mut self) -> Option<usize> {
+        if !self.cmt {
+            return self.get_active_path();
+        }
+        let usable: Vec<usize> = self
+            .path_list
+            .iter()
+            .flatten()
+            .map(|path| path.id)
+            .filter(|id| self.is_path_usable(*id))
+            .collect();
+        let next = usable
+            .iter()
+            .filter(|id| **id >= self.next_cmt_path)
+            .chain(usable.iter().filter(|id| **id < self.next_cmt_path))
+            .find(|id| self.get_available_window(**id).unwrap_or(0) > 0)
+            .copied();
+        match next {
+            Some(pathid) => {
+                self.next_cmt_path = pathid + 1;
+                Some(pathid)
+            }
+            None => self.get_active_path(),
+        }
+    }
+
+    pub fn is_data_path(&self, pathid: usize) -> bool {
+        if self.cmt && self.is_path_usable(pathid) {
+            return true;
+        }
+        self.get_active_path() == Some(pathid)
+    }
+
     pub fn get_path_mtu(&self, pathid: usize) -> Result<usize> {
         if let Some(path) = self.get_path(pathid) {
             Ok(path.mtu)
@@ -309,19 +832,49 @@ impl SctpRecovery {
         }
     }
 
+    pub fn get_pmtu(&self, pathid: usize) -> Result<usize> {
+        if let Some(path) = self.get_path(pathid) {
+            Ok(path.mtu + path.overhead)
+        } else {
+            Err(SctpError::InvalidPathId)
+        }
+    }
+
+    pub fn on_packet_too_big(&mut self, pathid: usize, mtu: usize, now: Instant) -> Result<()> {
+        if let Some(path) = self.get_path_mut(pathid) {
+            if path.pmtud_enabled && path.pmtud.on_packet_too_big(mtu, now) {
+                path.update_mtu();
+            }
+            Ok(())
+        } else {
+            Err(SctpError::InvalidPathId)
+        }
+    }
+
+    pub fn on_path_unreachable(&mut self, pathid: usize) -> Result<()> {
+        if let Some(path) = self.get_path_mut(pathid) {
+            path.on_unreachable();
+            Ok(())
+        } else {
+            Err(SctpError::InvalidPathId)
+        }
+    }
+
     pub fn get_available_cwnd(&self, pathid: usize) -> Result<usize> {
         if let Some(path) = self.get_path(pathid) {
-            Ok(path.cwnd.checked_sub(path.flight).unwrap_or(0))
+            Ok(path.cc.get_cwnd().saturating_sub(path.flight))
         } else {
             Err(SctpError::InvalidPathId)
         }
     }
 
+    // The peer's rwnd is shared by all paths, so DATA in flight to any of
+    // them counts against it, with or without CMT.
     pub fn get_available_window(&self, pathid: usize) -> Result<usize> {
         if let Some(path) = self.get_path(pathid) {
             let window = std::cmp::min(
-                self.rwnd.checked_sub(path.flight).unwrap_or(0),
-                path.cwnd.checked_sub(path.flight).unwrap_or(0),
+                self.rwnd.saturating_sub(self.total_flight),
+                path.cc.get_cwnd().saturating_sub(path.flight),
             );
             Ok(window)
         } else {
@@ -365,6 +918,9 @@ impl SctpRecovery {
             self.get_heartbeats_timeout(now),
             self.get_t3_retrans_timeout(now),
             self.get_t2_shutdown_timeout(now),
+            self.get_t5_shutdown_guard_timeout(now),
+            self.get_pmtu_probe_timeout(now),
+            self.get_t4_rto_timeout(now),
         ]
         .into_iter()
         .filter_map(|x| x)
@@ -394,15 +950,55 @@ impl SctpRecovery {
         self.path_list
             .iter()
             .filter_map(|opt| {
-                if let Some(path) = opt {
-                    return path.get_idle_timeout(now);
+                let path = opt.as_ref()?;
+                let timeout = path.get_idle_timeout(now)?;
+                if path.confirmed {
+                    Some(timeout)
                 } else {
-                    return None;
+                    Some(std::cmp::max(
+                        timeout,
+                        self.get_verification_time(path, now),
+                    ))
                 }
             })
             .min()
     }
 
+    // When the next HEARTBEAT to the unconfirmed path may be sent
+    fn get_verification_time(&self, path: &SctpPath, now: Instant) -> Instant {
+        match self.verification_start {
+            Some(start) if self.verification_count >= HB_MAX_BURST => {
+                std::cmp::max(start + path.get_rto(), now)
+            }
+            _ => now,
+        }
+    }
+
+    fn on_verification_sent(&mut self, rto: Duration, now: Instant) {
+        match self.verification_start {
+            Some(start) if now < start + rto => {
+                self.verification_count += 1;
+            }
+            _ => {
+                self.verification_start = Some(now);
+                self.verification_count = 1;
+            }
+        }
+    }
+
+    fn get_pmtu_probe_timeout(&self, now: Instant) -> Option<Instant> {
+        if !self.established {
+            return None;
+        }
+        self.path_list
+            .iter()
+            .filter_map(|opt| {
+                opt.as_ref()
+                    .and_then(|path| path.get_pmtu_probe_timeout(now))
+            })
+            .min()
+    }
+
     fn get_heartbeats_timeout(&self, now: Instant) -> Option<Instant> {
         if !self.established {
             return None;
@@ -449,6 +1045,28 @@ impl SctpRecovery {
         return None;
     }
 
+    fn get_t4_rto_timeout(&self, now: Instant) -> Option<Instant> {
+        if let Some(t4_timeout) = self.t4_rto_timeout {
+            if t4_timeout <= now {
+                return Some(now);
+            } else {
+                return Some(t4_timeout);
+            }
+        }
+        None
+    }
+
+    fn get_t5_shutdown_guard_timeout(&self, now: Instant) -> Option<Instant> {
+        if let Some(t5_timeout) = self.t5_shutdown_guard_timeout {
+            if t5_timeout <= now {
+                return Some(now);
+            } else {
+                return Some(t5_timeout);
+            }
+        }
+        None
+    }
+
     pub fn on_timeout(&mut self, now: Instant) {
         if let Some(timeout) = self.get_t1_timeout(now) {
             if timeout == now {
@@ -474,11 +1092,29 @@ impl SctpRecovery {
             }
         }
 
+        if let Some(timeout) = self.get_t5_shutdown_guard_timeout(now) {
+            if timeout == now {
+                self.on_t5_shutdown_guard_timeout();
+            }
+        }
+
+        if let Some(timeout) = self.get_t4_rto_timeout(now) {
+            if timeout == now {
+                self.on_t4_rto_timeout();
+            }
+        }
+
         if let Some(timeout) = self.get_idle_timeout(now) {
             if timeout == now {
                 self.on_idle_timeout(now);
             }
         }
+
+        if let Some(timeout) = self.get_pmtu_probe_timeout(now) {
+            if timeout == now {
+                self.on_pmtu_probe_timeout(now);
+            }
+        }
     }
 
     fn on_t1_timeout(&mut self, now: Instant) {
@@ -487,6 +1123,16 @@ impl SctpRecovery {
         for opt in self.path_list.iter_mut() {
             if let Some(path) = opt {
                 if let Some(v) = path.on_t1_timeout(now) {
+                    self.init_retrans_count += 1;
+                    if self.init_retrans_count > MAX_INIT_RETRANS {
+                        trace!(
+                            "{} init retransmission limit exceeded count={}",
+                            self.trace_id,
+                            self.init_retrans_count
+                        );
+                        self.closing = true;
+                        return;
+                    }
                     self.control_waiting_trans
                         .insert(self.next_control_sequence.0, v);
                     self.next_control_sequence += 1;
@@ -498,13 +1144,54 @@ impl SctpRecovery {
     fn on_idle_timeout(&mut self, now: Instant) {
         trace!("{} idle timeout fired", self.trace_id);
 
-        for opt in self.path_list.iter_mut() {
-            if let Some(path) = opt {
-                if let Some(v) = path.on_idle_timeout(now) {
-                    self.control_waiting_trans
-                        .insert(self.next_control_sequence.0, v);
-                    self.next_control_sequence += 1;
+        for pathid in 0..self.path_list.len() {
+            let (confirmed, rto) = match self.get_path(pathid) {
+                Some(path) if path.confirmed => (true, path.get_rto()),
+                Some(path) if self.get_verification_time(path, now) <= now => {
+                    (false, path.get_rto())
+                }
+                _ => continue,
+            };
+            if let Some(v) = self.get_path_mut(pathid).unwrap().on_idle_timeout(now) {
+                if !confirmed {
+                    self.on_verification_sent(rto, now);
                 }
+                self.control_waiting_trans
+                    .insert(self.next_control_sequence.0, v);
+                self.next_control_sequence += 1;
+            }
+        }
+    }
+
+    // An on-demand HEARTBEAT, which also counts against the rate limit of
+    // path verification
+    pub fn request_heartbeat(&mut self, pathid: usize, now: Instant) -> Result<()> {
+        let path = match self.get_path(pathid) {
+            Some(v) => v,
+            None => return Err(SctpError::InvalidPathId),
+        };
+        let rto = path.get_rto();
+        if !path.confirmed && self.get_verification_time(path, now) > now {
+            return Err(SctpError::Done);
+        }
+        let confirmed = path.confirmed;
+        let v = match self.get_path_mut(pathid).unwrap().send_heartbeat() {
+            Some(v) => v,
+            None => return Err(SctpError::Done),
+        };
+        if !confirmed {
+            self.on_verification_sent(rto, now);
+        }
+        self.control_waiting_trans
+            .insert(self.next_control_sequence.0, v);
+        self.next_control_sequence += 1;
+        Ok(())
+    }
+
+    fn on_pmtu_probe_timeout(&mut self, now: Instant) {
+        for path in self.path_list.iter_mut().flatten() {
+            if let Some(v) = path.on_pmtu_probe_timeout(now) {
+                self.pmtu_probe_waiting_trans.push_back(v);
             }
         }
     }
@@ -512,9 +1199,20 @@ impl SctpRecovery {
     fn on_heartbeats_timeout(&mut self, now: Instant) {
         trace!("{} heartbeat timeout fired", self.trace_id);
 
+        // Unanswered HEARTBEATs to unconfirmed addresses do not count
+        // toward Association.Max.Retrans.
+        let mut lost = 0;
         for opt in self.path_list.iter_mut() {
             if let Some(path) = opt {
-                path.on_heartbeats_timeout(now);
+                let lost1 = path.on_heartbeats_timeout(now);
+                if path.confirmed {
+                    lost += lost1;
+                }
+            }
+        }
+        if self.established {
+            for _ in 0..lost {
+                self.increment_error_count();
             }
         }
     }
@@ -526,25 +1224,33 @@ impl SctpRecovery {
         for opt in self.path_list.iter_mut() {
             if let Some(path) = opt {
                 if path.on_t3_retrans_timeout(now) {
-                    timeout_pathid.insert(path.id, false);
+                    timeout_pathid.insert(path.id, path.id);
                 }
             }
         }
+        for (pathid, retrans_pathid) in timeout_pathid.iter_mut() {
+            *retrans_pathid = self.get_retrans_path(*pathid, false);
+        }
 
+        let mut first_pathid = BTreeSet::new();
         for (tsn, tmit_data) in &mut self.data_sent {
-            if let Some(second) = timeout_pathid.get_mut(&tmit_data.pathid) {
+            if let Some(retrans_pathid) = timeout_pathid.get(&tmit_data.pathid) {
                 trace!(
-                    "{} try to retransmit tsn={}, pathid={}",
+                    "{} try to retransmit tsn={}, pathid={}, retrans_pathid={}",
                     self.trace_id,
                     tsn,
-                    tmit_data.pathid
+                    tmit_data.pathid,
+                    retrans_pathid
                 );
 
                 self.tsn_waiting_t3_retrans.insert(
                     tsn.0,
-                    (tmit_data.pathid, tmit_data.bytes_len, *second == false),
+                    (
+                        *retrans_pathid,
+                        tmit_data.bytes_len,
+                        first_pathid.insert(*retrans_pathid),
+                    ),
                 );
-                *second = true;
 
                 if tmit_data.state == SctpTransmitDataState::Sent {
                     self.total_flight -= tmit_data.bytes_len;
@@ -553,11 +1259,26 @@ impl SctpRecovery {
                 tmit_data.state = SctpTransmitDataState::Lost;
             }
         }
+
+        for _ in 0..timeout_pathid.len() {
+            self.increment_error_count();
+        }
     }
 
     fn on_t2_shutdown_timeout(&mut self) {
         trace!("{} T2 shutdown timeout fired", self.trace_id);
 
+        self.t2_shutdown_timeout = None;
+        self.increment_error_count();
+        if self.closing {
+            return;
+        }
+
+        let pathid = self.get_active_path().unwrap_or(0);
+        if let Some(path) = self.get_path_mut(pathid) {
+            path.increment_error_count();
+        }
+
         if self.shutdown_pending {
             // Send Shutdown
             assert!(!self.shutdown_received);
@@ -566,12 +1287,10 @@ impl SctpRecovery {
                 "{} send SHUTDOWN cumulative_tsn_ack={}, pathid={})",
                 self.trace_id,
                 self.peer_cumulative_tsn_ack.unwrap(),
-                self.primary_path.unwrap_or(0)
-            );
-            self.control_waiting_trans.insert(
-                self.next_control_sequence.0,
-                (shutdown, self.primary_path.unwrap_or(0)),
+                pathid
             );
+            self.control_waiting_trans
+                .insert(self.next_control_sequence.0, (shutdown, pathid));
             self.next_control_sequence += 1;
         }
 
@@ -579,24 +1298,85 @@ impl SctpRecovery {
             // Send Shutdown-ACK
             assert!(!self.shutdown_pending);
             let shutdown_ack = SctpChunk::ShutdownAck;
+            trace!("{} send SHUTDOWN-ACK pathid={})", self.trace_id, pathid);
+            self.control_waiting_trans
+                .insert(self.next_control_sequence.0, (shutdown_ack, pathid));
+            self.next_control_sequence += 1;
+        }
+    }
+
+    // Association.Max.Retrans (RFC 9260 Section 8.1)
+    fn increment_error_count(&mut self) {
+        self.error_count += 1;
+        if self.error_count > MAX_ASSOC_RETRANS {
             trace!(
-                "{} send SHUTDOWN-ACK pathid={})",
+                "{} association retransmission limit exceeded count={}",
                 self.trace_id,
-                self.primary_path.unwrap_or(0)
+                self.error_count
             );
-            self.control_waiting_trans.insert(
-                self.next_control_sequence.0,
-                (shutdown_ack, self.primary_path.unwrap_or(0)),
-            );
-            self.next_control_sequence += 1;
+            self.closing = true;
         }
     }
 
+    // An unacknowledged ASCONF is retransmitted to an alternate destination
+    // if possible (RFC 5061 Section 5.1, Rule F).
+    fn on_t4_rto_timeout(&mut self) {
+        trace!("{} T4 RTO timeout fired", self.trace_id);
+
+        self.t4_rto_timeout = None;
+        let tmit_ctrl = match self.asconf_sent.take() {
+            Some(v) => v,
+            None => return,
+        };
+        self.increment_error_count();
+        if self.closing {
+            return;
+        }
+        if let Some(path) = self.get_path_mut(tmit_ctrl.pathid) {
+            path.increment_error_count();
+        }
+
+        let pathid = self
+            .find_alternate_path(tmit_ctrl.pathid)
+            .or_else(|| self.get_active_path())
+            .unwrap_or(tmit_ctrl.pathid);
+        trace!(
+            "{} try to retransmit ASCONF pathid={}",
+            self.trace_id,
+            pathid
+        );
+        self.control_waiting_trans
+            .insert(self.next_control_sequence.0, (tmit_ctrl.chunk, pathid));
+        self.next_control_sequence += 1;
+    }
+
+    fn on_t5_shutdown_guard_timeout(&mut self) {
+        trace!("{} T5 shutdown guard timeout fired", self.trace_id);
+
+        self.t5_shutdown_guard_timeout = None;
+        self.t2_shutdown_timeout = None;
+        self.shutdown_guard_expired = true;
+    }
+
     pub fn on_control_sent(&mut self, chunk: SctpChunk, pathid: usize, now: Instant) {
         match &chunk {
             SctpChunk::Shutdown(..) | SctpChunk::ShutdownAck => {
                 let path = self.get_path_mut(pathid).unwrap();
                 self.t2_shutdown_timeout = Some(now + path.get_rto());
+                if chunk.get_type() == SctpChunkType::Shutdown
+                    && self.t5_shutdown_guard_timeout.is_none()
+                {
+                    self.t5_shutdown_guard_timeout = Some(now + SHUTDOWN_GUARD_TIMEOUT);
+                }
+            }
+            SctpChunk::Asconf(..) => {
+                let path = self.get_path_mut(pathid).unwrap();
+                self.t4_rto_timeout = Some(now + path.get_rto());
+                self.asconf_sent = Some(SctpTransmitControlChunk {
+                    chunk,
+                    pathid,
+                    time: now,
+                });
             }
             _ => {
                 let path = self.get_path_mut(pathid).unwrap();
@@ -614,13 +1394,65 @@ impl SctpRecovery {
                 }
             }
         }
-        return None;
+        return None;
+    }
+
+    // Returns an event if the HEARTBEAT-ACK confirmed the path.
+    pub fn on_heartbeatack_received(
+        &mut self,
+        chunk: SctpChunk,
+        now: Instant,
+    ) -> Option<SctpEvent> {
+        let mut event = None;
+        if let SctpChunk::HeartbeatAckWithInfo(hbinfo) = &chunk {
+            let pathid = hbinfo.pathid;
+            if let Some(path) = self.get_path_mut(pathid) {
+                let confirmed = path.confirmed;
+                if path.on_heartbeatack_received(chunk, now) {
+                    if !confirmed {
+                        trace!("{} path confirmed pathid={}", path.trace_id, pathid);
+                        event = Some(SctpEvent::PathConfirmed { pathid });
+                    }
+                    self.error_count = 0;
+                    if self.requested_primary_path == Some(pathid) {
+                        self.requested_primary_path = None;
+                        let _ = self.set_primary_path(pathid);
+                    }
+                }
+            }
+        }
+        event
     }
 
-    pub fn on_heartbeatack_received(&mut self, chunk: SctpChunk, now: Instant) {
-        if let SctpChunk::HeartbeatAckWithInfo(hbinfo) = &chunk {
-            let path = self.get_path_mut(hbinfo.pathid).unwrap();
-            path.on_heartbeatack_received(chunk, now);
+    // Returns the acknowledged ASCONF, which may still be waiting for retransmission.
+    pub fn on_asconf_ack_received(&mut self, serial: u32) -> Option<SctpAsconfChunk> {
+        let is_acked = |chunk: &SctpChunk| match chunk {
+            SctpChunk::Asconf(v) => v.serial == serial,
+            _ => false,
+        };
+        let chunk = if matches!(&self.asconf_sent, Some(tmit_ctrl) if is_acked(&tmit_ctrl.chunk)) {
+            self.t4_rto_timeout = None;
+            self.asconf_sent.take().unwrap().chunk
+        } else {
+            let sequence = self
+                .control_waiting_trans
+                .range(None, None)
+                .find(|(_, (chunk, _))| is_acked(chunk))
+                .map(|(sequence, _)| *sequence)?;
+            self.control_waiting_trans.remove(&sequence).unwrap().0
+        };
+        self.error_count = 0;
+        match chunk {
+            SctpChunk::Asconf(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    // A packet of `len` bytes, including the common header, was sent to the
+    // path with the DATA chunks passed to on_data_sent() since the last one.
+    pub fn on_packet_sent(&mut self, pathid: usize, len: usize) {
+        if let Some(path) = self.get_path_mut(pathid) {
+            path.on_packet_sent(len);
         }
     }
 
@@ -636,8 +1468,12 @@ impl SctpRecovery {
 
                 assert!(tsn > self.cum_ack);
 
+                let pacing = self.pacing;
                 let path = self.get_path_mut(pathid).unwrap();
                 path.on_data_sent(SerialNumber(tsn), bytes_len, retrans, earliest_retrans, now);
+                if pacing {
+                    path.update_pacing_time(bytes_len, now);
+                }
 
                 let tmit_data = match self.data_sent.get_mut(tsn) {
                     Some(tmit_data1) => {
@@ -654,6 +1490,7 @@ impl SctpRecovery {
                     }
                 };
 
+                tmit_data.pathid = pathid;
                 tmit_data.retrans = retrans;
                 tmit_data.in_flight = true;
 
@@ -671,15 +1508,25 @@ impl SctpRecovery {
         };
     }
 
-    fn check_datas_lost(&mut self) {
+    fn check_datas_lost(&mut self, dac_pkts: u8, now: Instant) {
         let mut lost_tsn = Vec::new();
 
+        // With CMT, only TSNs newly acknowledged on the same path count as
+        // miss indications (Split Fast Retransmit). A SACK for two packets
+        // sent to a single path counts twice (Delayed Ack for CMT).
+        let dac = dac_pkts >= 2 && self.newly_acked_by_path.len() == 1;
         for (tsn, tmit_data) in &mut self.data_sent {
             if tmit_data.state != SctpTransmitDataState::Sent {
                 continue;
             }
 
-            if self.highest_newly_ack.is_some() && tsn < self.highest_newly_ack.unwrap() {
+            if self.cmt {
+                if let Some((lowest, highest)) = self.newly_acked_by_path.get(&tmit_data.pathid) {
+                    if tsn < *highest {
+                        tmit_data.miss_indications += if dac && tsn < *lowest { 2 } else { 1 };
+                    }
+                }
+            } else if self.highest_newly_ack.is_some() && tsn < self.highest_newly_ack.unwrap() {
                 tmit_data.miss_indications += 1;
             }
             if tmit_data.miss_indications >= DUP_THRESH {
@@ -703,29 +1550,38 @@ impl SctpRecovery {
         for opt in self.path_list.iter_mut() {
             if let Some(path) = opt {
                 if path.check_datas_lost(&data_sent_state) {
-                    lost_pathid.insert(path.id, false);
+                    lost_pathid.insert(path.id, path.id);
                 }
             }
         }
 
+        for (pathid, retrans_pathid) in lost_pathid.iter_mut() {
+            *retrans_pathid = self.get_retrans_path(*pathid, true);
+        }
+
+        let mut first_pathid = BTreeSet::new();
         for tsn in lost_tsn {
             let tmit_data = self.data_sent.get_mut(tsn).unwrap();
             let pathid = tmit_data.pathid;
 
             trace!("{} lost tsn={}, pathid={}", self.trace_id, tsn, pathid,);
-            if let Some(second) = lost_pathid.get_mut(&pathid) {
+            if let Some(retrans_pathid) = lost_pathid.get(&pathid) {
                 if !tmit_data.fast_retrans {
                     self.tsn_waiting_fast_retrans.insert(
                         tsn,
-                        (tmit_data.pathid, tmit_data.bytes_len, *second == false),
+                        (
+                            *retrans_pathid,
+                            tmit_data.bytes_len,
+                            first_pathid.insert(*retrans_pathid),
+                        ),
                     );
-                    *second = true;
                     tmit_data.fast_retrans = true;
                     trace!(
-                        "{} try to fast retransmit tsn={}, pathid={}",
+                        "{} try to fast retransmit tsn={}, pathid={}, retrans_pathid={}",
                         self.trace_id,
                         tsn,
-                        tmit_data.pathid
+                        pathid,
+                        retrans_pathid
                     );
                 }
             }
@@ -739,17 +1595,27 @@ impl SctpRecovery {
                 self.total_flight_count
             );
 
-            self.on_enter_recovery(pathid);
+            self.on_enter_recovery(pathid, now);
         }
     }
 
-    pub fn on_sack_received(&mut self, chunk: SctpChunk, now: Instant) {
+    pub fn on_sack_received(&mut self, chunk: SctpChunk, now: Instant) -> Result<()> {
         if let SctpChunk::Sack(sack_chunk) = chunk {
             if SerialNumber(sack_chunk.cum_ack) < self.cum_ack {
-                return;
+                return Ok(());
+            }
+            if SerialNumber(sack_chunk.cum_ack) > self.largest_tsn {
+                trace!(
+                    "{} SACK acks unsent tsn cum_ack={}, largest_tsn={}",
+                    self.trace_id,
+                    sack_chunk.cum_ack,
+                    self.largest_tsn
+                );
+                return Err(SctpError::ProtocolViolation);
             }
 
             self.rwnd = sack_chunk.a_rwnd as usize;
+            self.newly_acked_by_path.clear();
 
             assert_eq!(self.data_sent.smallest_tsn, self.cum_ack + 1);
             let smallest_tsn = self.data_sent.smallest_tsn;
@@ -757,6 +1623,7 @@ impl SctpRecovery {
             let old_cum_ack = self.cum_ack;
 
             if self.cum_ack < SerialNumber(sack_chunk.cum_ack) {
+                self.error_count = 0;
                 let start = self.cum_ack + 1;
                 let end = SerialNumber(sack_chunk.cum_ack) + 1;
                 if start.0 < end.0 {
@@ -776,6 +1643,15 @@ impl SctpRecovery {
             }
 
             for ack_block in sack_chunk.gap_acks {
+                if ack_block.start == 0 || ack_block.start > ack_block.end {
+                    trace!(
+                        "{} ignore invalid gap ack block start={}, end={}",
+                        self.trace_id,
+                        ack_block.start,
+                        ack_block.end
+                    );
+                    continue;
+                }
                 let start = SerialNumber(sack_chunk.cum_ack) + ack_block.start as u32;
                 let end = SerialNumber(sack_chunk.cum_ack) + ack_block.end as u32 + 1;
                 last_ack = SerialNumber(sack_chunk.cum_ack) + ack_block.end as u32;
@@ -821,15 +1697,16 @@ impl SctpRecovery {
             );
             for opt in self.path_list.iter_mut() {
                 if let Some(path) = opt {
-                    path.on_sack_received(&data_sent_state, now);
+                    path.on_sack_received(&data_sent_state, now, self.cmt);
                 }
             }
 
             if smallest_tsn <= self.cum_ack {
                 self.data_sent.drain(smallest_tsn.0, (self.cum_ack + 1).0);
             }
-            self.check_datas_lost();
+            self.check_datas_lost(sack_chunk.dac_pkts, now);
         }
+        Ok(())
     }
 
     fn on_data_acked(&mut self, tsn: SerialNumber<u32>, state: SctpTransmitDataState) {
@@ -863,6 +1740,10 @@ impl SctpRecovery {
                 if tmit_data.state == SctpTransmitDataState::Sent {
                     self.total_flight -= tmit_data.bytes_len;
                     self.total_flight_count -= 1;
+                    self.newly_acked_by_path
+                        .entry(tmit_data.pathid)
+                        .and_modify(|(_, highest)| *highest = tsn)
+                        .or_insert((tsn, tsn));
                 }
                 tmit_data.state = state;
             }
@@ -879,7 +1760,16 @@ impl SctpRecovery {
         );
     }
 
-    fn on_enter_recovery(&mut self, pathid: usize) {
+    fn on_enter_recovery(&mut self, pathid: usize, now: Instant) {
+        if self.cmt {
+            // Each path recovers on its own (CUC).
+            if let Some(path) = self.get_path_mut(pathid) {
+                if !path.fast_recovery {
+                    path.on_enter_recovery(now);
+                }
+            }
+            return;
+        }
         if self.fast_recovery {
             return;
         }
@@ -893,7 +1783,7 @@ impl SctpRecovery {
         self.fast_recovery = true;
         self.recovery_point = Some(self.largest_tsn.0);
         if let Some(path) = self.get_path_mut(pathid) {
-            path.on_enter_recovery();
+            path.on_enter_recovery(now);
         }
     }
 
@@ -909,72 +1799,152 @@ impl SctpRecovery {
         }
     }
 
-    pub fn on_enter_shutdown(&mut self, cumulative_tsn_ack: u32) -> bool {
+    // The sender side of ECN (RFC 9260 Appendix A). The cwnd is reduced at
+    // most once per window of data, but every ECNE is answered with a CWR so
+    // that the peer stops repeating it.
+    pub fn on_ecne_received(&mut self, lowest_tsn: u32, now: Instant) {
+        let tsn = SerialNumber(lowest_tsn);
+        if tsn > self.largest_tsn {
+            trace!(
+                "{} discard ECNE for unsent tsn={}, largest_tsn={}",
+                self.trace_id,
+                lowest_tsn,
+                self.largest_tsn
+            );
+            return;
+        }
+
+        let beyond_recovery_point = match self.ecn_recovery_point {
+            Some(point) => tsn > point,
+            None => true,
+        };
+        if beyond_recovery_point {
+            let pathid = self
+                .data_sent
+                .get(lowest_tsn)
+                .map(|tmit_data| tmit_data.pathid)
+                .or_else(|| self.get_active_path());
+            if let Some(path) = pathid.and_then(|pathid| self.get_path_mut(pathid)) {
+                path.on_ecn_echo(now);
+            }
+            self.ecn_recovery_point = Some(self.largest_tsn);
+        }
+
+        let pathid = self.get_active_path().unwrap_or(0);
         trace!(
-            "{} enter shutdown cumulative_tsn_ack={}",
+            "{} send CWR lowest_tsn={}, pathid={}",
             self.trace_id,
-            cumulative_tsn_ack
+            lowest_tsn,
+            pathid
         );
-        self.peer_cumulative_tsn_ack = Some(cumulative_tsn_ack);
-        if self.shutdown_received {
-            return self.check_shutdown_ready();
-        }
-        if !self.shutdown_pending {
-            self.established = false;
+        self.control_waiting_trans.insert(
+            self.next_control_sequence.0,
+            (SctpChunk::Cwr(lowest_tsn), pathid),
+        );
+        self.next_control_sequence += 1;
+    }
+
+    pub fn on_enter_shutdown(&mut self) {
+        trace!("{} enter shutdown", self.trace_id);
+        if !self.shutdown_received {
             self.shutdown_pending = true;
         }
-        return self.check_shutdown_ready();
     }
 
-    pub fn on_shutdown_received(&mut self) -> bool {
+    pub fn on_shutdown_received(&mut self, cumulative_tsn_ack: u32, now: Instant) -> Result<()> {
+        if SerialNumber(cumulative_tsn_ack) > self.largest_tsn {
+            trace!(
+                "{} SHUTDOWN acks unsent tsn cumulative_tsn_ack={}, largest_tsn={}",
+                self.trace_id,
+                cumulative_tsn_ack,
+                self.largest_tsn
+            );
+            return Err(SctpError::ProtocolViolation);
+        }
+
         if self.shutdown_pending {
             self.shutdown_pending = false;
         }
+        self.shutdown_received = true;
 
-        if !self.shutdown_received {
-            self.established = false;
-            self.shutdown_received = true;
+        // The Cumulative TSN Ack of SHUTDOWN is processed as a SACK without any Gap Ack Block.
+        let sack = SctpChunk::Sack(SctpSackChunk {
+            cum_ack: cumulative_tsn_ack,
+            a_rwnd: self.rwnd as u32,
+            num_gap_ack: 0,
+            num_dup_ack: 0,
+            gap_acks: Vec::new(),
+            dup_acks: Vec::new(),
+            dac_pkts: 0,
+        });
+        self.on_sack_received(sack, now)
+    }
+
+    pub fn is_all_data_acked(&self) -> bool {
+        self.data_sent.is_empty()
+    }
+
+    // The Cumulative TSN Ack of a SHUTDOWN retransmitted on T2 expiry, once
+    // one was sent
+    pub fn update_peer_cumulative_tsn_ack(&mut self, cumulative_tsn_ack: u32) {
+        if self.peer_cumulative_tsn_ack.is_some() {
+            self.peer_cumulative_tsn_ack = Some(cumulative_tsn_ack);
         }
-        return self.check_shutdown_ready();
     }
 
-    fn check_shutdown_ready(&mut self) -> bool {
-        if self.data_sent.is_empty() {
-            if self.shutdown_pending {
-                // Send Shutdown
-                assert!(!self.shutdown_received);
-                let shutdown = SctpChunk::Shutdown(self.peer_cumulative_tsn_ack.unwrap());
-                trace!(
-                    "{} send SHUTDONW peer_cumulative_tsn_ack={}",
-                    self.trace_id,
-                    self.peer_cumulative_tsn_ack.unwrap()
-                );
-                self.control_waiting_trans.insert(
-                    self.next_control_sequence.0,
-                    (shutdown, self.primary_path.unwrap_or(0)),
-                );
-                self.next_control_sequence += 1;
-            }
+    pub fn send_shutdown(&mut self, cumulative_tsn_ack: u32) {
+        assert!(self.shutdown_pending);
+        self.peer_cumulative_tsn_ack = Some(cumulative_tsn_ack);
 
-            if self.shutdown_received {
-                // Send Shutdown-ACK
-                assert!(!self.shutdown_pending);
-                let shutdown_ack = SctpChunk::ShutdownAck;
-                trace!("{} send SHUTDONW-ACK", self.trace_id);
-                self.control_waiting_trans.insert(
-                    self.next_control_sequence.0,
-                    (shutdown_ack, self.primary_path.unwrap_or(0)),
-                );
-                self.next_control_sequence += 1;
-            }
-            true
-        } else {
-            false
+        // A SHUTDOWN waiting for transmission carries an outdated Cumulative TSN Ack.
+        let queued = self
+            .control_waiting_trans
+            .range(None, None)
+            .filter_map(|(sequence, (chunk, _))| match chunk {
+                SctpChunk::Shutdown(..) => Some(*sequence),
+                _ => None,
+            })
+            .collect::<Vec<u64>>();
+        for sequence in queued {
+            self.control_waiting_trans.remove(&sequence);
         }
+
+        let pathid = self.get_active_path().unwrap_or(0);
+        trace!(
+            "{} send SHUTDOWN cumulative_tsn_ack={}, pathid={}",
+            self.trace_id,
+            cumulative_tsn_ack,
+            pathid
+        );
+        self.control_waiting_trans.insert(
+            self.next_control_sequence.0,
+            (SctpChunk::Shutdown(cumulative_tsn_ack), pathid),
+        );
+        self.next_control_sequence += 1;
     }
 
-    pub fn on_shutdown_ack_received(&mut self) {
+    pub fn send_shutdown_ack(&mut self) {
+        assert!(self.shutdown_received);
+        let pathid = self.get_active_path().unwrap_or(0);
+        trace!("{} send SHUTDOWN-ACK pathid={}", self.trace_id, pathid);
+        self.control_waiting_trans.insert(
+            self.next_control_sequence.0,
+            (SctpChunk::ShutdownAck, pathid),
+        );
+        self.next_control_sequence += 1;
+    }
+
+    pub fn on_shutdown_completed(&mut self) {
         self.t2_shutdown_timeout = None;
+        self.t5_shutdown_guard_timeout = None;
+    }
+
+    pub fn is_closing(&self) -> bool {
+        self.closing
+    }
+
+    pub fn is_shutdown_guard_expired(&self) -> bool {
+        self.shutdown_guard_expired
     }
 }
 
@@ -1002,23 +1972,132 @@ impl SctpPath {
             self.latest_rtt,
             self.srtt.unwrap()
         );
+        self.cc
+            .on_rtt_sample(self.latest_rtt, self.srtt.unwrap(), recv_time);
+    }
+
+    fn get_params(&self) -> SctpPathParams {
+        SctpPathParams {
+            hb_interval: self.hb_interval,
+            hb_enabled: self.hb_enabled,
+            max_retrans: self.retrans_threshold,
+            pmtud_enabled: self.pmtud_enabled,
+            pmtu: self.mtu + self.overhead,
+            rto_initial: self.rto_initial,
+            rto_min: self.rto_min,
+            rto_max: self.rto_max,
+        }
+    }
+
+    fn get_status(&self) -> SctpPathStatus {
+        SctpPathStatus {
+            confirmed: self.confirmed,
+            state: self.state,
+            srtt: self.srtt,
+            rttvar: self.rttvar,
+            rto: self.get_rto(),
+            cwnd: self.cc.get_cwnd(),
+            ssthresh: self.cc.get_ssthresh(),
+            flight: self.flight,
+            partial_bytes_acked: self.cc.get_partial_bytes_acked(),
+            pacing_rate: self.cc.get_pacing_rate(),
+            pmtu: self.mtu + self.overhead,
+            error_count: self.retrans_count,
+        }
+    }
+
+    fn set_params(&mut self, params: &SctpPathParams, now: Instant) {
+        self.hb_interval = params.hb_interval;
+        self.hb_enabled = params.hb_enabled;
+        self.retrans_threshold = params.max_retrans;
+        self.rto_initial = params.rto_initial;
+        self.rto_min = params.rto_min;
+        self.rto_max = params.rto_max;
+
+        if !params.pmtud_enabled {
+            self.pmtud_enabled = false;
+            self.probe_sequence = None;
+            self.mtu = params.pmtu - self.overhead;
+            self.cc.on_mtu_changed(self.mtu);
+        } else if !self.pmtud_enabled {
+            // Discovery starts over from BASE_PLPMTU.
+            let max_pmtu = self.pmtud.get_max_pmtu();
+            self.pmtud = SctpPmtud::new(std::cmp::min(BASE_PLPMTU, max_pmtu), max_pmtu);
+            self.pmtud_enabled = true;
+            self.update_mtu();
+        }
+        trace!(
+            "{} path parameters changed pathid={}, params={:?}",
+            self.trace_id,
+            self.id,
+            params
+        );
+
+        let timeout = now + self.get_rto();
+        let shorten = |timer: Option<Instant>| timer.map(|v| std::cmp::min(v, timeout));
+        self.t1_timeout = shorten(self.t1_timeout);
+        self.heartbeat_timeout = shorten(self.heartbeat_timeout);
+        self.t3_retrans_timeout = shorten(self.t3_retrans_timeout);
     }
 
     fn get_state(&self) -> Option<(bool, SctpPathState)> {
         Some((self.confirmed, self.state))
     }
 
+    fn increment_error_count(&mut self) {
+        if self.state != SctpPathState::InActive {
+            self.retrans_count += 1;
+            if self.retrans_count >= self.retrans_threshold {
+                trace!("{} path becomes inactive pathid={}", self.trace_id, self.id);
+                self.state = SctpPathState::InActive;
+            } else if self.retrans_count > self.pf_threshold && self.state == SctpPathState::Active
+            {
+                trace!(
+                    "{} path becomes potentially failed pathid={}",
+                    self.trace_id,
+                    self.id
+                );
+                self.state = SctpPathState::PotentiallyFailed;
+            }
+        }
+    }
+
+    fn on_unreachable(&mut self) {
+        trace!(
+            "{} path becomes unreachable pathid={}",
+            self.trace_id,
+            self.id
+        );
+        self.retrans_count = std::cmp::max(self.retrans_count, self.retrans_threshold);
+        self.state = SctpPathState::InActive;
+    }
+
+    // The destination is reachable again (RFC 9260 Section 8.3)
+    fn clear_error_count(&mut self) {
+        self.retrans_count = 0;
+        if self.state != SctpPathState::Active {
+            trace!("{} path becomes active pathid={}", self.trace_id, self.id);
+            self.state = SctpPathState::Active;
+        }
+    }
+
     fn get_rto(&self) -> Duration {
         if let Some(srtt) = self.srtt {
-            std::cmp::max(
-                std::cmp::min(
-                    (srtt + self.rttvar * 4) * 2_u32.pow(self.retrans_count),
-                    RTO_MAX,
-                ),
-                RTO_MIN,
-            )
+            std::cmp::max(self.backoff(srtt + self.rttvar * 4), self.rto_min)
         } else {
-            std::cmp::min(RTO_INITIAL * 2_u32.pow(self.retrans_count), RTO_MAX)
+            self.backoff(self.rto_initial)
+        }
+    }
+
+    // The RTO doubles per retransmission up to RTO.Max, which also caps an
+    // overflowing backoff.
+    fn backoff(&self, rto: Duration) -> Duration {
+        match 2_u32
+            .checked_pow(self.retrans_count)
+            .and_then(|factor| rto.checked_mul(factor))
+        {
+            Some(v) => std::cmp::min(v, self.rto_max),
+            None => self.rto_max,
         }
     }
 
@@ -1034,13 +2113,32 @@ impl SctpPath {
         }
     }
 
+    // A potentially failed path is probed every RTO (RFC 7829 Section 4), as
+    // is an unconfirmed path (RFC 9260 Section 5.4).
+    fn get_hb_interval(&self) -> Duration {
+        if self.state == SctpPathState::PotentiallyFailed || !self.confirmed {
+            self.get_rto()
+        } else {
+            self.get_rto() + self.hb_interval
+        }
+    }
+
+    // Without HEARTBEATs, an idle path is still confirmed and a potentially
+    // failed path is still probed.
+    fn is_hb_needed(&self) -> bool {
+        self.hb_enabled || !self.confirmed || self.state == SctpPathState::PotentiallyFailed
+    }
+
     fn get_idle_timeout(&self, now: Instant) -> Option<Instant> {
-        let rto = self.get_rto();
+        if !self.is_hb_needed() {
+            return None;
+        }
+        let interval = self.get_hb_interval();
         if let Some(last_time) = self.last_time {
-            if now.duration_since(last_time) > rto + HB_INTERVAL {
+            if now.duration_since(last_time) > interval {
                 return Some(now);
             } else {
-                return Some(now + rto + HB_INTERVAL - now.duration_since(last_time));
+                return Some(now + interval - now.duration_since(last_time));
             }
         } else {
             if self.next_hb_sequence == 0 {
@@ -1051,6 +2149,22 @@ impl SctpPath {
         }
     }
 
+    fn get_pmtu_probe_timeout(&self, now: Instant) -> Option<Instant> {
+        if !self.pmtud_enabled {
+            return None;
+        }
+        if self.confirmed
+            && self.state == SctpPathState::Active
+            && self.pmtud.get_probe_size().is_some()
+        {
+            return Some(now);
+        }
+        match self.pmtud.get_timeout() {
+            Some(timeout) if timeout <= now => Some(now),
+            v => v,
+        }
+    }
+
     fn get_heartbeats_timeout(&self, now: Instant) -> Option<Instant> {
         if let Some(heartbeat_timeout) = self.heartbeat_timeout {
             if heartbeat_timeout <= now {
@@ -1096,12 +2210,7 @@ impl SctpPath {
 
         if let Some(i) = iter.next() {
             let tmit_ctrl = self.control_sent.remove(i).unwrap();
-            if self.state != SctpPathState::InActive {
-                self.retrans_count += 1;
-                if self.retrans_count >= self.retrans_threshold {
-                    self.state = SctpPathState::InActive;
-                }
-            }
+            self.increment_error_count();
             trace!(
                 "{} try to retransmit INIT pathid={}",
                 self.trace_id,
@@ -1115,42 +2224,97 @@ impl SctpPath {
     fn on_idle_timeout(&mut self, now: Instant) -> Option<(SctpChunk, usize)> {
         trace!("{} IDLE timeout fired pathid={}", self.trace_id, self.id);
 
-        let rto = self.get_rto();
-        if !self.wait_hb_trans
+        if self.is_hb_needed()
             && (self.last_time.is_none()
-                || now.duration_since(self.last_time.unwrap()) > rto + HB_INTERVAL)
+                || now.duration_since(self.last_time.unwrap()) > self.get_hb_interval())
         {
-            let heartbeat = SctpChunk::HeartbeatWithInfo(SctpHeartbeatInfo {
-                sequence: self.next_hb_sequence,
-                pathid: self.id,
-                random_value: self.random_value,
-            });
-            self.next_hb_sequence += 1;
-            self.wait_hb_trans = true;
+            return self.send_heartbeat();
+        }
+        return None;
+    }
+
+    // The HEARTBEAT carries the nonce of the path, which the HEARTBEAT-ACK
+    // must echo to confirm the path.
+    fn send_heartbeat(&mut self) -> Option<(SctpChunk, usize)> {
+        if self.wait_hb_trans {
+            return None;
+        }
+        let heartbeat = SctpChunk::HeartbeatWithInfo(SctpHeartbeatInfo {
+            sequence: self.next_hb_sequence,
+            pathid: self.id,
+            random_value: self.random_value,
+        });
+        self.next_hb_sequence += 1;
+        self.wait_hb_trans = true;
+
+        trace!(
+            "{} try to transmit HEARTBEAT pathid={}",
+            self.trace_id,
+            self.id
+        );
+        Some((heartbeat, self.id))
+    }
+
+    // A PMTU probe is a HEARTBEAT padded to the probe size (RFC 8899 Section 6.2.1)
+    fn on_pmtu_probe_timeout(&mut self, now: Instant) -> Option<(SctpChunk, usize, usize)> {
+        if !self.pmtud_enabled {
+            return None;
+        }
+        self.pmtud.on_timeout(now);
+        if !self.confirmed || self.state != SctpPathState::Active {
+            return None;
+        }
+        let size = self.pmtud.get_probe_size()?;
+        let heartbeat = SctpChunk::HeartbeatWithInfo(SctpHeartbeatInfo {
+            sequence: self.next_hb_sequence,
+            pathid: self.id,
+            random_value: self.random_value,
+        });
+        self.probe_sequence = Some(self.next_hb_sequence);
+        self.next_hb_sequence += 1;
+        self.pmtud.on_probe_sent(size);
+
+        trace!(
+            "{} try to transmit PMTU probe pathid={}, size={}",
+            self.trace_id,
+            self.id,
+            size
+        );
+        Some((heartbeat, self.id, size - self.overhead))
+    }
 
+    fn update_mtu(&mut self) {
+        if !self.pmtud_enabled {
+            return;
+        }
+        let mtu = self.pmtud.get_pmtu() - self.overhead;
+        if mtu != self.mtu {
             trace!(
-                "{} try to transmit HEARTBEAT pathid={}",
+                "{} path MTU changed pathid={}, pmtu={}, state={:?}",
                 self.trace_id,
-                self.id
+                self.id,
+                self.pmtud.get_pmtu(),
+                self.pmtud.get_state()
             );
-            return Some((heartbeat, self.id));
+            self.mtu = mtu;
+            self.cc.on_mtu_changed(mtu);
         }
-        return None;
     }
 
-    fn on_heartbeats_timeout(&mut self, now: Instant) {
+    fn on_heartbeats_timeout(&mut self, now: Instant) -> u32 {
         trace!(
             "{} HEARTBEAT timeout fired pathid={}",
             self.trace_id,
             self.id
         );
 
-        self.check_heartbeats_lost(now);
+        let lost = self.check_heartbeats_lost(now);
         if let Some(heartbeat_timeout) = self.heartbeat_timeout {
             if heartbeat_timeout <= now {
                 self.heartbeat_timeout = None;
             }
         }
+        lost
     }
 
     fn on_t3_retrans_timeout(&mut self, now: Instant) -> bool {
@@ -1169,24 +2333,26 @@ impl SctpPath {
             self.id
         );
 
-        self.ssthresh = std::cmp::max(
-            self.mtu.checked_mul(4).unwrap_or_else(|| std::usize::MAX),
-            self.cwnd / 2,
-        );
-        self.cwnd = self.mtu;
+        self.cc.on_rto(now);
 
         trace!(
             "{} congestion control pathid={}, cwnd={}, ssthresh={}",
             self.trace_id,
             self.id,
-            self.cwnd,
-            self.ssthresh
+            self.cc.get_cwnd(),
+            self.cc.get_ssthresh()
         );
 
-        if self.state != SctpPathState::InActive {
-            self.retrans_count += 1;
-            if self.retrans_count >= self.retrans_threshold {
-                self.state = SctpPathState::InActive;
+        self.increment_error_count();
+
+        let largest_lost = self
+            .data_sent
+            .range(None, None)
+            .map(|(_, tmit_data_info)| tmit_data_info.pkt_len)
+            .max();
+        if let Some(pkt_len) = largest_lost {
+            if self.pmtud.on_packet_lost(pkt_len) {
+                self.update_mtu();
             }
         }
 
@@ -1233,6 +2399,8 @@ impl SctpPath {
                     },
                 );
                 self.wait_hb_trans = false;
+                // The idle time so far counts before last_time restarts.
+                self.check_idle(now);
                 self.last_time = Some(now);
                 let rto = self.get_rto();
                 trace!("{} set HEARTBEAT timeout rto={:?}", self.trace_id, rto);
@@ -1263,21 +2431,35 @@ impl SctpPath {
         }
     }
 
-    pub fn on_heartbeatack_received(&mut self, chunk: SctpChunk, now: Instant) {
+    pub fn on_heartbeatack_received(&mut self, chunk: SctpChunk, now: Instant) -> bool {
         if let SctpChunk::HeartbeatAckWithInfo(hbinfo) = chunk {
-            if let Some(tmit_chunk) = self.heartbeat_sent.remove(&hbinfo.sequence) {
-                if tmit_chunk.hbinfo.random_value == hbinfo.random_value {
-                    if !self.confirmed {
-                        self.confirmed = true;
-                    }
-                    self.update_rtt(tmit_chunk.time, now);
+            // A HEARTBEAT-ACK with a wrong nonce leaves the HEARTBEAT pending.
+            let matched = matches!(
+                self.heartbeat_sent.get(&hbinfo.sequence),
+                Some(v) if v.hbinfo.random_value == hbinfo.random_value
+            );
+            if matched {
+                let tmit_chunk = self.heartbeat_sent.remove(&hbinfo.sequence).unwrap();
+                if !self.confirmed {
+                    self.confirmed = true;
+                }
+                self.update_rtt(tmit_chunk.time, now);
+                if self.heartbeat_sent.is_empty() {
                     self.heartbeat_timeout = None;
                 }
+                self.clear_error_count();
+                if self.probe_sequence == Some(hbinfo.sequence) {
+                    self.probe_sequence = None;
+                    self.pmtud.on_probe_acked(now);
+                    self.update_mtu();
+                }
+                return true;
             }
         }
+        false
     }
 
-    fn check_heartbeats_lost(&mut self, now: Instant) {
+    fn check_heartbeats_lost(&mut self, now: Instant) -> u32 {
         let range_iter = self.heartbeat_sent.range(None, Some(self.next_hb_sequence));
         let lost_hbs: Vec<u64> = range_iter
             .filter_map(|(sequence, tmit_hb)| {
@@ -1288,23 +2470,39 @@ impl SctpPath {
                 }
             })
             .collect();
-        if !lost_hbs.is_empty() {
-            self.on_heartbeats_lost(lost_hbs);
-        }
+        self.on_heartbeats_lost(lost_hbs, now)
     }
 
-    fn on_heartbeats_lost(&mut self, lost_hbs: Vec<u64>) {
+    fn on_heartbeats_lost(&mut self, lost_hbs: Vec<u64>, now: Instant) -> u32 {
+        let mut lost = 0;
         for sequence in lost_hbs {
             if let Some(_) = self.heartbeat_sent.remove(&sequence) {
-                trace!("{} lost Heartbeat sequence={}", self.trace_id, sequence);
-                if self.state != SctpPathState::InActive {
-                    self.retrans_count += 1;
-                    if self.retrans_count >= self.retrans_threshold {
-                        self.state = SctpPathState::InActive;
-                    }
+                if self.probe_sequence == Some(sequence) {
+                    // A lost probe says nothing about reachability
+                    trace!("{} lost PMTU probe sequence={}", self.trace_id, sequence);
+                    self.probe_sequence = None;
+                    self.pmtud.on_probe_lost(now);
+                    continue;
                 }
+                trace!("{} lost Heartbeat sequence={}", self.trace_id, sequence);
+                self.increment_error_count();
+                lost += 1;
+            }
+        }
+        lost
+    }
+
+    // Black hole detection counts the packets DATA chunks were sent in.
+    fn on_packet_sent(&mut self, len: usize) {
+        let pkt_len = len - 12 + self.overhead;
+        let mut sequence = self.packet_start;
+        while sequence < self.next_sequence {
+            if let Some(tmit_data_info) = self.data_sent.get_mut(&sequence.0) {
+                tmit_data_info.pkt_len = pkt_len;
             }
+            sequence += 1;
         }
+        self.packet_start = self.next_sequence;
     }
 
     pub fn on_data_sent(
@@ -1325,6 +2523,7 @@ impl SctpPath {
                 sequence: sequence,
                 tsn: tsn,
                 bytes_len: bytes_len,
+                pkt_len: bytes_len + self.overhead,
                 state: SctpTransmitDataState::Sent,
                 time: now,
                 do_rtt: self.needs_new_rtt && !retrans,
@@ -1335,6 +2534,9 @@ impl SctpPath {
             self.lowest_sequence = Some(sequence);
         }
 
+        if !retrans {
+            self.check_idle(now);
+        }
         self.last_time = Some(now);
         if self.t3_retrans_timeout.is_none() {
             let rto = self.get_rto();
@@ -1358,6 +2560,7 @@ impl SctpPath {
 
         self.flight += bytes_len;
         self.flight_count += 1;
+        self.max_flight = std::cmp::max(self.max_flight, self.flight);
 
         trace!(
             "{} transmission DATA tsn={}, pathid={}, flight={}, flight_count={}",
@@ -1430,10 +2633,14 @@ impl SctpPath {
         self.wait_fast_retrans = true;
     }
 
+    // With CUC (Cwnd Update for CMT), the congestion window grows whenever the
+    // oldest DATA outstanding on this path is acknowledged, even by a gap ack
+    // block, and fast recovery ends once everything sent before it was acknowledged.
     fn on_sack_received(
         &mut self,
         data_sent_state: &SctpTsnQueue<SctpTransmitDataState>,
         now: Instant,
+        cuc: bool,
     ) {
         if data_sent_state.is_empty() {
             return;
@@ -1449,6 +2656,8 @@ impl SctpPath {
         }
 
         let mut lowest_tsn = None;
+        let old_pseudo_cum_ack = self.data_sent.range(None, None).next().map(|(key, _)| *key);
+        let mut gap_acked = 0;
 
         for (sequence, tsn, state) in tsn_array {
             assert!(tsn >= data_sent_state.smallest_tsn);
@@ -1461,6 +2670,11 @@ impl SctpPath {
                         && new_state == SctpTransmitDataState::GapAcked)
                         || new_state == SctpTransmitDataState::CumAcked
                     {
+                        if cuc && new_state == SctpTransmitDataState::GapAcked {
+                            if let Some(tmit_data_info) = self.data_sent.get(&sequence) {
+                                gap_acked += tmit_data_info.bytes_len;
+                            }
+                        }
                         self.on_data_acked(sequence, new_state, now);
                     } else {
                         if let Some(tmit_data_info) = self.data_sent.get_mut(&sequence) {
@@ -1493,7 +2707,22 @@ impl SctpPath {
                 }
             }
         }
-        self.congestion_control();
+        if cuc {
+            let pseudo_cum_ack = self.data_sent.range(None, None).next().map(|(key, _)| *key);
+            if pseudo_cum_ack != old_pseudo_cum_ack {
+                self.ack += gap_acked;
+            }
+            if let Some(recovery_point) = self.recovery_point {
+                let exited = match pseudo_cum_ack {
+                    Some(key) => SerialNumber(key) > recovery_point,
+                    None => true,
+                };
+                if exited {
+                    self.on_exit_recovery();
+                }
+            }
+        }
+        self.congestion_control(now);
     }
 
     fn on_data_acked(&mut self, sequence: u64, state: SctpTransmitDataState, now: Instant) {
@@ -1521,6 +2750,8 @@ impl SctpPath {
                     self.ack += tmit_data_info.bytes_len;
                 }
             }
+            self.pmtud.on_packet_acked(tmit_data_info.pkt_len);
+            self.clear_error_count();
 
             if do_rtt {
                 self.update_rtt(tmit_time, now);
@@ -1528,72 +2759,152 @@ impl SctpPath {
         }
     }
 
-    fn congestion_control(&mut self) {
-        if !self.fast_recovery {
-            self.increase_cwnd();
+    fn update_app_limited(&mut self) {
+        let oldest = self
+            .data_sent
+            .range(None, None)
+            .next()
+            .map(|(key, _)| SerialNumber(*key));
+        if let (Some(window_end), Some(oldest)) = (self.window_end, oldest) {
+            if oldest <= window_end {
+                return;
+            }
+        }
+        self.app_limited = self.out_of_data && self.max_flight < self.cc.get_cwnd() / 2;
+        self.out_of_data = false;
+        self.max_flight = self.flight;
+        self.window_end = Some(SerialNumber(self.next_sequence.0.wrapping_sub(1)));
+    }
+
+    // RFC 9260 Section 7.2.1: the cwnd decays for every RTO in which the
+    // path sent no data.
+    fn check_idle(&mut self, now: Instant) {
+        let last_time = match self.last_time {
+            Some(v) if self.flight == 0 => v,
+            _ => return,
+        };
+        let rto = self.get_rto();
+        let old_cwnd = self.cc.get_cwnd();
+        let mut idle = now.saturating_duration_since(last_time);
+        while idle >= rto {
+            let cwnd = self.cc.get_cwnd();
+            self.cc.on_idle(now);
+            if self.cc.get_cwnd() == cwnd {
+                break;
+            }
+            idle -= rto;
+        }
+        if self.cc.get_cwnd() != old_cwnd {
+            trace!(
+                "{} decrease cwnd after idle pathid={}, old_cwnd={}, cwnd={}",
+                self.trace_id,
+                self.id,
+                old_cwnd,
+                self.cc.get_cwnd()
+            );
+        }
+    }
+
+    fn congestion_control(&mut self, now: Instant) {
+        self.update_app_limited();
+        if self.app_limited {
+            trace!(
+                "{} application limited pathid={}, cwnd={}, ack={}",
+                self.trace_id,
+                self.id,
+                self.cc.get_cwnd(),
+                self.ack
+            );
+            self.cc.on_app_limited_ack(self.ack, self.flight, now);
+            self.ack = 0;
+            return;
         }
+        let old_cwnd = self.cc.get_cwnd();
+        self.cc
+            .on_ack(self.ack, self.flight, self.fast_recovery, now);
+        trace!(
+            "{} increase cwnd pathid={}, ssthresh={}, old_cwnd={}, cwnd={}, ack={}, partial_bytes_acked={}",
+            self.trace_id,
+            self.id,
+            self.cc.get_ssthresh(),
+            old_cwnd,
+            self.cc.get_cwnd(),
+            self.ack,
+            self.cc.get_partial_bytes_acked()
+        );
+        self.ack = 0;
     }
 
-    fn on_enter_recovery(&mut self) {
-        let old_ssthresh = self.ssthresh;
-        let old_cwnd = self.cwnd;
-        self.ssthresh = std::cmp::max(self.cwnd / 2, 4 * self.mtu);
-        self.cwnd = self.ssthresh;
+    fn on_enter_recovery(&mut self, now: Instant) {
+        let old_ssthresh = self.cc.get_ssthresh();
+        let old_cwnd = self.cc.get_cwnd();
+        self.cc.on_congestion_event(now);
         self.fast_recovery = true;
+        self.recovery_point = Some(SerialNumber(self.next_sequence.0.wrapping_sub(1)));
         trace!(
             "{} enter recovery pathid={}, old_ssthresh={}, ssthresh={}, old_cwnd={}, cwnd={}",
             self.trace_id,
             self.id,
             old_ssthresh,
-            self.ssthresh,
+            self.cc.get_ssthresh(),
             old_cwnd,
-            self.cwnd
+            self.cc.get_cwnd()
         );
     }
 
     fn on_exit_recovery(&mut self) {
         self.fast_recovery = false;
+        self.recovery_point = None;
         trace!("{} exit recovery pathid={}", self.trace_id, self.id);
     }
 
-    fn increase_cwnd(&mut self) {
-        let old_cwnd = self.cwnd;
-        if self.cwnd <= self.ssthresh {
-            if self.flight + self.ack >= self.cwnd {
-                let increment = std::cmp::max(self.ack, self.mtu);
-                self.cwnd += increment;
-            }
-            trace!(
-                "{} increase cwnd pathid={}, ssthresh={}, old_cwnd={}, cwnd={}, ack={}",
-                self.trace_id,
-                self.id,
-                self.ssthresh,
-                old_cwnd,
-                self.cwnd,
-                self.ack
-            );
+    // The rate of the congestion controller, or else the cwnd spread over
+    // the smoothed RTT with some headroom for the window to grow, 2x in slow
+    // start and 1.25x after it (as in Linux).
+    fn get_pacing_rate(&self) -> Option<u64> {
+        if let Some(rate) = self.cc.get_pacing_rate() {
+            return Some(rate);
+        }
+        let srtt = self.srtt?;
+        if srtt.is_zero() {
+            return None;
+        }
+        let gain = if self.cc.get_cwnd() < self.cc.get_ssthresh() {
+            2.0
         } else {
-            let old_partial_bytes_acked = self.partial_bytes_acked;
-            self.partial_bytes_acked += self.ack;
-            if self.partial_bytes_acked >= self.cwnd {
-                self.cwnd += self.mtu;
-                self.partial_bytes_acked =
-                    self.partial_bytes_acked.checked_sub(self.cwnd).unwrap_or(0);
-            }
-            trace!(
-                "{} increase cwnd pathid={}, ssthresh={}, old_cwnd={}, cwnd={}, ack={}, old_partial_bytes_acked={}, partial_bytes_acked={}",
-                self.trace_id,
-                self.id,
-                self.ssthresh,
-                old_cwnd,
-                self.cwnd,
-                self.ack,
-                old_partial_bytes_acked,
-                self.partial_bytes_acked
+            1.25
+        };
+        Some((gain * self.cc.get_cwnd() as f64 / srtt.as_secs_f64()) as u64)
+    }
 
-            );
+    // Sending is not allowed to catch up on time the path was not paced for.
+    fn update_pacing_time(&mut self, bytes_len: usize, now: Instant) {
+        let rate = match self.get_pacing_rate() {
+            Some(v) if v > 0 => v,
+            _ => return,
+        };
+        let start = self
+            .pacing_time
+            .map_or(now, |time| std::cmp::max(time, now));
+        let interval = Duration::from_secs_f64(bytes_len as f64 / rate as f64);
+        self.pacing_time = Some(start + interval);
+    }
+
+    // Fast recovery already reduced the cwnd for this window.
+    fn on_ecn_echo(&mut self, now: Instant) {
+        if self.fast_recovery {
+            return;
         }
-        self.ack = 0;
+        let old_cwnd = self.cc.get_cwnd();
+        self.cc.on_congestion_event(now);
+        trace!(
+            "{} reduce cwnd for ECNE pathid={}, ssthresh={}, old_cwnd={}, cwnd={}",
+            self.trace_id,
+            self.id,
+            self.cc.get_ssthresh(),
+            old_cwnd,
+            self.cc.get_cwnd()
+        );
     }
 }
 
@@ -1619,6 +2930,7 @@ impl SctpTransmitData {
 fn test_recovery_all_data_acked() {
     let mut recovery = SctpRecovery::new(0).unwrap();
     let datachunk = SctpDataChunk {
+        i_bit: false,
         u_bit: false,
         b_bit: true,
         e_bit: true,
@@ -1635,6 +2947,7 @@ fn test_recovery_all_data_acked() {
         num_dup_ack: 0,
         gap_acks: Vec::new(),
         dup_acks: Vec::new(),
+        dac_pkts: 0,
     };
 
     recovery.on_data_sent(SctpChunk::Data(datachunk), 0);
@@ -1649,6 +2962,7 @@ fn test_recovery_all_data_acked() {
 fn test_recovery_some_data_cum_acked() {
     let mut recovery = SctpRecovery::new(0xffffffff).unwrap();
     let datachunk0 = SctpDataChunk {
+        i_bit: false,
         u_bit: false,
         b_bit: true,
         e_bit: true,
@@ -1668,6 +2982,7 @@ fn test_recovery_some_data_cum_acked() {
         num_dup_ack: 0,
         gap_acks: Vec::new(),
         dup_acks: Vec::new(),
+        dac_pkts: 0,
     };
 
     assert_eq!(recovery.cum_ack, 0xffffffff - 1);
@@ -1685,6 +3000,7 @@ fn test_recovery_some_data_cum_acked() {
 fn test_recovery_some_data_gap_acked() {
     let mut recovery = SctpRecovery::new(0xffffffff).unwrap();
     let datachunk0 = SctpDataChunk {
+        i_bit: false,
         u_bit: false,
         b_bit: true,
         e_bit: true,
@@ -1704,6 +3020,7 @@ fn test_recovery_some_data_gap_acked() {
         num_dup_ack: 0,
         gap_acks: vec![SctpGapAckBlock { start: 2, end: 2 }],
         dup_acks: Vec::new(),
+        dac_pkts: 0,
     };
     let sackchunk1 = SctpSackChunk {
         cum_ack: 0,
@@ -1712,6 +3029,7 @@ fn test_recovery_some_data_gap_acked() {
         num_dup_ack: 0,
         gap_acks: Vec::new(),
         dup_acks: Vec::new(),
+        dac_pkts: 0,
     };
 
     assert_eq!(recovery.cum_ack, 0xffffffff - 1);
@@ -1733,6 +3051,7 @@ fn test_recovery_some_data_gap_acked() {
 fn test_recovery_gap_acked_and_revoked() {
     let mut recovery = SctpRecovery::new(0xffffffff).unwrap();
     let datachunk0 = SctpDataChunk {
+        i_bit: false,
         u_bit: false,
         b_bit: true,
         e_bit: true,
@@ -1752,6 +3071,7 @@ fn test_recovery_gap_acked_and_revoked() {
         num_dup_ack: 0,
         gap_acks: vec![SctpGapAckBlock { start: 2, end: 2 }],
         dup_acks: Vec::new(),
+        dac_pkts: 0,
     };
     let sackchunk1 = SctpSackChunk {
         cum_ack: 0xffffffff,
@@ -1760,6 +3080,7 @@ fn test_recovery_gap_acked_and_revoked() {
         num_dup_ack: 0,
         gap_acks: Vec::new(),
         dup_acks: Vec::new(),
+        dac_pkts: 0,
     };
 
     assert_eq!(recovery.cum_ack, 0xffffffff - 1);
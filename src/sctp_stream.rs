@@ -272,13 +272,27 @@ impl SctpStreamOut {
         return !self.pending_queue.is_empty();
     }
 
-    pub fn write(&mut self, rbuf: &[u8], is_unordered: bool, is_complete: bool) -> Result<usize> {
+    // The DATA chunks needed for the pending data, without padding
+    pub fn pending_chunks_len(&self) -> usize {
+        self.pending_queue.iter().map(|p| 16 + p.data.len()).sum()
+    }
+
+    pub fn write(
+        &mut self,
+        rbuf: &[u8],
+        is_unordered: bool,
+        is_complete: bool,
+        sack_immediately: bool,
+    ) -> Result<usize> {
         if let Some(last_pending) = self.pending_queue.back_mut() {
             if !last_pending.complete {
                 last_pending.data.append(&mut Vec::from(rbuf));
                 if is_complete {
                     last_pending.complete = true;
                 }
+                if sack_immediately {
+                    last_pending.sack_immediately = true;
+                }
                 return Ok(rbuf.len());
             }
         }
@@ -288,6 +302,7 @@ impl SctpStreamOut {
             unordeded: is_unordered,
             complete: is_complete,
             flight: false,
+            sack_immediately,
             data: Vec::from(rbuf),
         };
         self.pending_queue.push_back(pending);
@@ -303,6 +318,7 @@ impl SctpStreamOut {
             if first_pending.complete && first_pending.data.len() <= fragment_point {
                 let first_pending = self.pending_queue.pop_front().unwrap();
                 let data_chunk = SctpDataChunk {
+                    i_bit: first_pending.sack_immediately,
                     u_bit: first_pending.unordeded,
                     b_bit: !first_pending.flight,
                     e_bit: true,
@@ -325,6 +341,7 @@ impl SctpStreamOut {
                 }
                 .collect::<Vec<u8>>();
                 let data_chunk = SctpDataChunk {
+                    i_bit: false,
                     u_bit: first_pending.unordeded,
                     b_bit: !first_pending.flight,
                     e_bit: false,
@@ -350,6 +367,8 @@ struct SctpDataPending {
     unordeded: bool,
     complete: bool,
     flight: bool,
+    // The I bit is set on the last fragment.
+    sack_immediately: bool,
     data: Vec<u8>,
 }
 
@@ -673,6 +692,7 @@ fn test_stream_in_recv_nonfragment() {
         readable_unordered_queue: VecDeque::new(),
     };
     let datachunk = SctpDataChunk {
+        i_bit: false,
         u_bit: false,
         b_bit: true,
         e_bit: true,
@@ -690,6 +710,7 @@ fn test_stream_in_recv_nonfragment() {
     assert_eq!(stream_in.get_readable_num(true), 0);
 
     let datachunk = SctpDataChunk {
+        i_bit: false,
         u_bit: true,
         b_bit: true,
         e_bit: true,
@@ -707,6 +728,7 @@ fn test_stream_in_recv_nonfragment() {
     assert_eq!(stream_in.get_readable_num(true), 1);
 
     let datachunk = SctpDataChunk {
+        i_bit: false,
         u_bit: false,
         b_bit: true,
         e_bit: true,
@@ -735,6 +757,7 @@ fn test_stream_in_recv_fragment() {
         readable_unordered_queue: VecDeque::new(),
     };
     let datachunk = SctpDataChunk {
+        i_bit: false,
         u_bit: false,
         b_bit: true,
         e_bit: false,
@@ -748,6 +771,7 @@ fn test_stream_in_recv_fragment() {
     assert_eq!(ret, 0);
 
     let datachunk = SctpDataChunk {
+        i_bit: false,
         u_bit: false,
         b_bit: false,
         e_bit: false,
@@ -761,6 +785,7 @@ fn test_stream_in_recv_fragment() {
     assert_eq!(ret, 0);
 
     let datachunk = SctpDataChunk {
+        i_bit: false,
         u_bit: false,
         b_bit: false,
         e_bit: false,
@@ -774,6 +799,7 @@ fn test_stream_in_recv_fragment() {
     assert_eq!(ret, 0);
 
     let datachunk = SctpDataChunk {
+        i_bit: false,
         u_bit: false,
         b_bit: false,
         e_bit: true,
@@ -799,6 +825,7 @@ fn test_stream_in_recv_reordered_fragment() {
         readable_unordered_queue: VecDeque::new(),
     };
     let datachunk = SctpDataChunk {
+        i_bit: false,
         u_bit: false,
         b_bit: true,
         e_bit: false,
@@ -812,6 +839,7 @@ fn test_stream_in_recv_reordered_fragment() {
     assert_eq!(ret, 0);
 
     let datachunk = SctpDataChunk {
+        i_bit: false,
         u_bit: false,
         b_bit: false,
         e_bit: true,
@@ -825,6 +853,7 @@ fn test_stream_in_recv_reordered_fragment() {
     assert_eq!(ret, 0);
 
     let datachunk = SctpDataChunk {
+        i_bit: false,
         u_bit: false,
         b_bit: true,
         e_bit: false,
@@ -838,6 +867,7 @@ fn test_stream_in_recv_reordered_fragment() {
     assert_eq!(ret, 0);
 
     let datachunk = SctpDataChunk {
+        i_bit: false,
         u_bit: false,
         b_bit: false,
         e_bit: true,
@@ -862,6 +892,7 @@ fn test_stream_in_recv_invalid_nonfragment() {
         readable_unordered_queue: VecDeque::new(),
     };
     let datachunk = SctpDataChunk {
+        i_bit: false,
         u_bit: false,
         b_bit: true,
         e_bit: true,
@@ -875,6 +906,7 @@ fn test_stream_in_recv_invalid_nonfragment() {
     let ret = stream_in.recv(datachunk);
     assert_eq!(ret, Ok(1));
     let datachunk = SctpDataChunk {
+        i_bit: false,
         u_bit: false,
         b_bit: true,
         e_bit: true,
@@ -899,6 +931,7 @@ fn test_stream_in_recv_invalid_fragment() {
         readable_unordered_queue: VecDeque::new(),
     };
     let datachunk = SctpDataChunk {
+        i_bit: false,
         u_bit: false,
         b_bit: false,
         e_bit: true,
@@ -912,6 +945,7 @@ fn test_stream_in_recv_invalid_fragment() {
     let ret = stream_in.recv(datachunk);
     assert_eq!(ret, Ok(0));
     let datachunk = SctpDataChunk {
+        i_bit: false,
         u_bit: false,
         b_bit: true,
         e_bit: false,
@@ -936,6 +970,7 @@ fn test_stream_in_recv_ufragment() {
         readable_unordered_queue: VecDeque::new(),
     };
     let datachunk = SctpDataChunk {
+        i_bit: false,
         u_bit: true,
         b_bit: true,
         e_bit: false,
@@ -949,6 +984,7 @@ fn test_stream_in_recv_ufragment() {
     assert_eq!(ret, Ok(0));
 
     let datachunk = SctpDataChunk {
+        i_bit: false,
         u_bit: true,
         b_bit: false,
         e_bit: true,
@@ -974,6 +1010,7 @@ fn test_stream_in_recv_reordered_ufragment() {
         readable_unordered_queue: VecDeque::new(),
     };
     let datachunk = SctpDataChunk {
+        i_bit: false,
         u_bit: true,
         b_bit: true,
         e_bit: false,
@@ -987,6 +1024,7 @@ fn test_stream_in_recv_reordered_ufragment() {
     assert_eq!(ret, Ok(0));
 
     let datachunk = SctpDataChunk {
+        i_bit: false,
         u_bit: true,
         b_bit: true,
         e_bit: false,
@@ -1000,6 +1038,7 @@ fn test_stream_in_recv_reordered_ufragment() {
     assert_eq!(ret, Ok(0));
 
     let datachunk = SctpDataChunk {
+        i_bit: false,
         u_bit: true,
         b_bit: false,
         e_bit: true,
@@ -1013,6 +1052,7 @@ fn test_stream_in_recv_reordered_ufragment() {
     assert_eq!(ret, Ok(2));
 
     let datachunk = SctpDataChunk {
+        i_bit: false,
         u_bit: true,
         b_bit: false,
         e_bit: true,
@@ -1037,6 +1077,7 @@ fn test_stream_in_recv_splittable_reordered_ufragment() {
         readable_unordered_queue: VecDeque::new(),
     };
     let datachunk = SctpDataChunk {
+        i_bit: false,
         u_bit: true,
         b_bit: true,
         e_bit: false,
@@ -1050,6 +1091,7 @@ fn test_stream_in_recv_splittable_reordered_ufragment() {
     assert_eq!(ret, Ok(0));
 
     let datachunk = SctpDataChunk {
+        i_bit: false,
         u_bit: true,
         b_bit: false,
         e_bit: true,
@@ -1063,6 +1105,7 @@ fn test_stream_in_recv_splittable_reordered_ufragment() {
     assert_eq!(ret, Ok(0));
 
     let datachunk = SctpDataChunk {
+        i_bit: false,
         u_bit: true,
         b_bit: false,
         e_bit: true,
@@ -1076,6 +1119,7 @@ fn test_stream_in_recv_splittable_reordered_ufragment() {
     assert_eq!(ret, Ok(2));
 
     let datachunk = SctpDataChunk {
+        i_bit: false,
         u_bit: true,
         b_bit: true,
         e_bit: false,
@@ -1100,6 +1144,7 @@ fn test_stream_in_recv_splittable_reordered_ufragment2() {
         readable_unordered_queue: VecDeque::new(),
     };
     let datachunk = SctpDataChunk {
+        i_bit: false,
         u_bit: true,
         b_bit: true,
         e_bit: false,
@@ -1113,6 +1158,7 @@ fn test_stream_in_recv_splittable_reordered_ufragment2() {
     assert_eq!(ret, Ok(0));
 
     let datachunk = SctpDataChunk {
+        i_bit: false,
         u_bit: true,
         b_bit: false,
         e_bit: true,
@@ -1127,6 +1173,7 @@ fn test_stream_in_recv_splittable_reordered_ufragment2() {
     assert_eq!(ret, Ok(0));
 
     let datachunk = SctpDataChunk {
+        i_bit: false,
         u_bit: true,
         b_bit: false,
         e_bit: true,
@@ -1141,6 +1188,7 @@ fn test_stream_in_recv_splittable_reordered_ufragment2() {
     assert_eq!(ret, Ok(2));
 
     let datachunk = SctpDataChunk {
+        i_bit: false,
         u_bit: true,
         b_bit: true,
         e_bit: false,
@@ -1166,6 +1214,7 @@ fn test_stream_in_recv_splittable_reordered_ufragment3() {
         readable_unordered_queue: VecDeque::new(),
     };
     let datachunk = SctpDataChunk {
+        i_bit: false,
         u_bit: true,
         b_bit: false,
         e_bit: false,
@@ -1179,6 +1228,7 @@ fn test_stream_in_recv_splittable_reordered_ufragment3() {
     assert_eq!(ret, Ok(0));
 
     let datachunk = SctpDataChunk {
+        i_bit: false,
         u_bit: true,
         b_bit: false,
         e_bit: false,
@@ -1192,6 +1242,7 @@ fn test_stream_in_recv_splittable_reordered_ufragment3() {
     assert_eq!(ret, Ok(0));
 
     let datachunk = SctpDataChunk {
+        i_bit: false,
         u_bit: true,
         b_bit: true,
         e_bit: false,
@@ -1205,6 +1256,7 @@ fn test_stream_in_recv_splittable_reordered_ufragment3() {
     assert_eq!(ret, Ok(0));
 
     let datachunk = SctpDataChunk {
+        i_bit: false,
         u_bit: true,
         b_bit: true,
         e_bit: false,
@@ -1218,6 +1270,7 @@ fn test_stream_in_recv_splittable_reordered_ufragment3() {
     assert_eq!(ret, Ok(0));
 
     let datachunk = SctpDataChunk {
+        i_bit: false,
         u_bit: true,
         b_bit: false,
         e_bit: true,
@@ -1231,6 +1284,7 @@ fn test_stream_in_recv_splittable_reordered_ufragment3() {
     assert_eq!(ret, Ok(3));
 
     let datachunk = SctpDataChunk {
+        i_bit: false,
         u_bit: true,
         b_bit: false,
         e_bit: true,